@@ -0,0 +1,325 @@
+//! Uniswap-v2-style price accumulators. A pool adds its spot price (Q64.64)
+//! times the seconds elapsed before every swap or deposit, so the TWAP over
+//! any window is the difference of two cumulative readings divided by the
+//! window length. Each program keeps the readings of its pools in an
+//! `ObservationRing` inside its own oracle account.
+
+use anchor_lang::prelude::*;
+
+/// Observations kept per pool oracle
+pub const OBSERVATION_COUNT: usize = 64;
+
+/// Least time between stored observations, so a busy pool's ring spans a
+/// known horizon instead of its last few dozen seconds
+pub const MIN_OBSERVATION_INTERVAL: i64 = 60;
+
+/// Longest TWAP window the ring always covers once it has been written for
+/// that long: the gaps between its stored observations
+pub const MAX_TWAP_WINDOW: u32 = (OBSERVATION_COUNT as u32 - 1) * MIN_OBSERVATION_INTERVAL as u32;
+
+/// Basis points in one whole
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Price of one unit of the base token in quote tokens, as Q64.64.
+pub fn spot_price(reserve_base: u64, reserve_quote: u64) -> Option<u128> {
+    if reserve_base == 0 {
+        return None;
+    }
+    Some(((reserve_quote as u128) << 64) / reserve_base as u128)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Observation {
+    /// Unix timestamp of the reading
+    pub timestamp: i64,
+    /// Sum of token A's price in token B over time, Q64.64 * seconds
+    pub price_a_cumulative: u128,
+    /// Sum of token B's price in token A over time, Q64.64 * seconds
+    pub price_b_cumulative: u128,
+}
+
+impl Observation {
+    pub const LEN: usize = 8 + 16 + 16;
+
+    /// Reading at `now`, assuming the reserves have held since this one.
+    /// Accumulators wrap, so only differences between readings are meaningful.
+    pub fn accumulate(&self, reserve_a: u64, reserve_b: u64, now: i64) -> Observation {
+        let mut next = *self;
+        if now <= self.timestamp {
+            return next;
+        }
+        next.timestamp = now;
+
+        let elapsed = (now - self.timestamp) as u128;
+        if let (Some(price_a), Some(price_b)) =
+            (spot_price(reserve_a, reserve_b), spot_price(reserve_b, reserve_a))
+        {
+            next.price_a_cumulative = self
+                .price_a_cumulative
+                .wrapping_add(price_a.wrapping_mul(elapsed));
+            next.price_b_cumulative = self
+                .price_b_cumulative
+                .wrapping_add(price_b.wrapping_mul(elapsed));
+        }
+        next
+    }
+}
+
+/// Time-weighted average prices of token A and token B between two
+/// readings, as Q64.64.
+pub fn twap(start: &Observation, end: &Observation) -> Option<(u128, u128)> {
+    if end.timestamp <= start.timestamp {
+        return None;
+    }
+    let elapsed = (end.timestamp - start.timestamp) as u128;
+    Some((
+        end.price_a_cumulative.wrapping_sub(start.price_a_cumulative) / elapsed,
+        end.price_b_cumulative.wrapping_sub(start.price_b_cumulative) / elapsed,
+    ))
+}
+
+/// Whether `price` is within `max_deviation_bps` of `reference`.
+pub fn within_deviation(price: u128, reference: u128, max_deviation_bps: u16) -> bool {
+    price.abs_diff(reference) <= reference / BPS_DENOMINATOR * max_deviation_bps as u128
+}
+
+/// Least output a trade of `amount` may accept: its value at `twap_price`,
+/// the TWAP of the input token in the output token as Q64.64, less
+/// `max_slippage_bps`.
+pub fn min_amount_out(amount: u64, twap_price: u128, max_slippage_bps: u16) -> Option<u64> {
+    let fair = (amount as u128).checked_mul(twap_price)? >> 64;
+    let min = fair.checked_mul(BPS_DENOMINATOR.checked_sub(max_slippage_bps as u128)?)? / BPS_DENOMINATOR;
+    u64::try_from(min).ok()
+}
+
+/// Ring buffer of past readings for a pool, at most one per
+/// `MIN_OBSERVATION_INTERVAL`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, Debug, PartialEq, Eq)]
+pub struct ObservationRing {
+    /// Slot of the most recent observation
+    pub index: u16,
+    /// Number of slots written so far, up to `OBSERVATION_COUNT`
+    pub count: u16,
+    pub observations: Vec<Observation>,
+}
+
+impl ObservationRing {
+    pub const LEN: usize = 2 + // index
+                           2 + // count
+                           4 + Observation::LEN * OBSERVATION_COUNT; // observations
+
+    /// Empty ring with every slot allocated
+    pub fn new() -> Self {
+        ObservationRing {
+            index: 0,
+            count: 0,
+            observations: vec![Observation::default(); OBSERVATION_COUNT],
+        }
+    }
+
+    /// Records `observation`, unless it comes less than
+    /// `MIN_OBSERVATION_INTERVAL` after the last one or from a pool whose
+    /// clock was never started, which would make the oracle report history
+    /// back to the Unix epoch.
+    pub fn write(&mut self, observation: Observation) {
+        if observation.timestamp == 0 {
+            return;
+        }
+        if self
+            .newest()
+            .is_some_and(|newest| observation.timestamp < newest.timestamp + MIN_OBSERVATION_INTERVAL)
+        {
+            return;
+        }
+        let next = if self.count == 0 {
+            0
+        } else {
+            (self.index as usize + 1) % OBSERVATION_COUNT
+        };
+        self.observations[next] = observation;
+        self.index = next as u16;
+        self.count = (self.count + 1).min(OBSERVATION_COUNT as u16);
+    }
+
+    /// Most recent stored observation
+    pub fn newest(&self) -> Option<&Observation> {
+        (self.count > 0).then(|| &self.observations[self.index as usize])
+    }
+
+    /// Stored observations from oldest to newest
+    pub fn chronological(&self) -> impl Iterator<Item = &Observation> {
+        let count = self.count as usize;
+        let start = (self.index as usize + OBSERVATION_COUNT + 1 - count) % OBSERVATION_COUNT;
+        (0..count).map(move |i| &self.observations[(start + i) % OBSERVATION_COUNT])
+    }
+
+    /// Cumulative prices at `target`, interpolated between stored
+    /// observations and `latest`, the pool's reading as of now. `None` if
+    /// `target` is older than the oldest observation or in the future.
+    pub fn observe(&self, latest: &Observation, target: i64) -> Option<Observation> {
+        if target > latest.timestamp {
+            return None;
+        }
+
+        let mut before: Option<&Observation> = None;
+        for after in self.chronological().chain(std::iter::once(latest)) {
+            if after.timestamp == target {
+                return Some(*after);
+            }
+            if after.timestamp > target {
+                return before.map(|before| interpolate(before, after, target));
+            }
+            before = Some(after);
+        }
+        None
+    }
+
+    /// TWAPs of token A and token B over the `window` seconds up to `now`,
+    /// bringing the pool's `last` reading forward from the reserves it holds
+    /// now. `None` if the ring does not reach back that far.
+    pub fn twap_to_now(
+        &self,
+        last: &Observation,
+        reserve_a: u64,
+        reserve_b: u64,
+        now: i64,
+        window: u32,
+    ) -> Option<(u128, u128)> {
+        let latest = last.accumulate(reserve_a, reserve_b, now);
+        let start = self.observe(&latest, now - window as i64)?;
+        twap(&start, &latest)
+    }
+}
+
+fn interpolate(before: &Observation, after: &Observation, target: i64) -> Observation {
+    // Step forward at the average rate between the two readings rather than
+    // multiplying out the wrapping difference
+    let (price_a, price_b) = twap(before, after).unwrap_or_default();
+    let elapsed = (target - before.timestamp) as u128;
+    Observation {
+        timestamp: target,
+        price_a_cumulative: before.price_a_cumulative.wrapping_add(price_a.wrapping_mul(elapsed)),
+        price_b_cumulative: before.price_b_cumulative.wrapping_add(price_b.wrapping_mul(elapsed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q64: u128 = 1 << 64;
+
+    #[test]
+    fn twap_weights_prices_by_time() {
+        let start = Observation { timestamp: 100, ..Default::default() };
+        // Price 2 for 10s, then 4 for 30s: average 3.5
+        let middle = start.accumulate(1_000, 2_000, 110);
+        let end = middle.accumulate(1_000, 4_000, 140);
+        let (price_a, price_b) = twap(&start, &end).unwrap();
+        assert_eq!(price_a, Q64 * 7 / 2);
+        assert!(price_b > Q64 / 4 && price_b < Q64 / 2);
+    }
+
+    #[test]
+    fn accumulators_wrap_without_breaking_twap() {
+        let start = Observation {
+            timestamp: 0,
+            price_a_cumulative: u128::MAX - Q64,
+            price_b_cumulative: 0,
+        };
+        let end = start.accumulate(1, 1, 10);
+        assert!(end.price_a_cumulative < start.price_a_cumulative);
+        assert_eq!(twap(&start, &end).unwrap().0, Q64);
+    }
+
+    #[test]
+    fn ring_keeps_the_latest_spaced_observations() {
+        let mut ring = ObservationRing::new();
+        let mut reading = Observation::default();
+        let mut last_stored: Option<i64> = None;
+        for second in 1..=(OBSERVATION_COUNT as i64 + 10) * MIN_OBSERVATION_INTERVAL {
+            reading = reading.accumulate(1_000, 1_000, second);
+            ring.write(reading);
+            // Readings closer together than the interval are dropped
+            let newest = ring.newest().unwrap().timestamp;
+            if let Some(last) = last_stored {
+                assert!(newest == last || newest - last >= MIN_OBSERVATION_INTERVAL);
+            }
+            last_stored = Some(newest);
+        }
+        assert_eq!(ring.count as usize, OBSERVATION_COUNT);
+        let timestamps: Vec<i64> = ring.chronological().map(|o| o.timestamp).collect();
+        assert!(timestamps.windows(2).all(|pair| pair[1] - pair[0] == MIN_OBSERVATION_INTERVAL));
+    }
+
+    #[test]
+    fn busy_pool_covers_the_max_window() {
+        let mut ring = ObservationRing::new();
+        let mut reading = Observation { timestamp: 1_000, ..Default::default() };
+        ring.write(reading);
+        // A trade every second for twice the horizon
+        let end = 1_000 + 2 * MAX_TWAP_WINDOW as i64;
+        for second in 1_001..=end {
+            reading = reading.accumulate(1_000, 2_000, second);
+            ring.write(reading);
+        }
+        let latest = reading.accumulate(1_000, 2_000, end + 30);
+        let start = ring.observe(&latest, latest.timestamp - MAX_TWAP_WINDOW as i64).unwrap();
+        assert_eq!(twap(&start, &latest).unwrap().0, 2 * Q64);
+    }
+
+    #[test]
+    fn unstarted_clock_is_not_recorded() {
+        let mut ring = ObservationRing::new();
+        ring.write(Observation::default());
+        assert_eq!(ring.newest(), None);
+
+        // A pool started at 1_000 has no history before it
+        let started = Observation { timestamp: 1_000, ..Default::default() };
+        ring.write(started);
+        let latest = started.accumulate(1_000, 1_000, 1_100);
+        assert_eq!(ring.observe(&latest, 10), None);
+        assert_eq!(ring.observe(&latest, 1_050).unwrap().price_a_cumulative, Q64 * 50);
+    }
+
+    #[test]
+    fn observe_interpolates_between_readings() {
+        let mut ring = ObservationRing::new();
+        let first = Observation { timestamp: 100, ..Default::default() };
+        let second = first.accumulate(1_000, 1_000, 200);
+        ring.write(first);
+        ring.write(second);
+        let latest = second.accumulate(1_000, 3_000, 300);
+
+        assert_eq!(ring.observe(&latest, 50), None);
+        assert_eq!(ring.observe(&latest, 301), None);
+        assert_eq!(ring.observe(&latest, 200), Some(second));
+        assert_eq!(ring.observe(&latest, 150).unwrap().price_a_cumulative, Q64 * 50);
+        assert_eq!(ring.observe(&latest, 250).unwrap().price_a_cumulative, Q64 * 250);
+    }
+
+    #[test]
+    fn quiet_pool_is_brought_forward_from_its_reserves() {
+        let mut ring = ObservationRing::new();
+        let first = Observation { timestamp: 1_000, ..Default::default() };
+        let last = first.accumulate(1_000, 1_000, 2_000);
+        ring.write(first);
+        ring.write(last);
+
+        // No trade for far longer than the window: the stored readings alone
+        // cannot cover it, but the reserves that held since the last one can
+        let window = 600;
+        let now = 10_000;
+        assert_eq!(ring.observe(ring.newest().unwrap(), now - window as i64), None);
+        assert_eq!(ring.twap_to_now(&last, 1_000, 2_000, now, window).unwrap().0, 2 << 64);
+        assert_eq!(ring.twap_to_now(&last, 1_000, 2_000, now, 10_000), None);
+    }
+
+    #[test]
+    fn min_out_is_twap_value_less_slippage() {
+        // Two output tokens per input token
+        assert_eq!(min_amount_out(1_000, 2 << 64, 100), Some(1_980));
+        assert_eq!(min_amount_out(1_000, 2 << 64, 0), Some(2_000));
+        assert_eq!(min_amount_out(u64::MAX, u128::MAX, 100), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! Token-2022 transfer fees. Program math works on what actually lands in a
+//! vault or a user's account, so amounts are adjusted here before pricing,
+//! and deposits that must arrive in full are grossed up. Legacy SPL mints,
+//! and Token-2022 mints without the extension, charge nothing.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+};
+
+/// Codes sit above the programs' own `ErrorCode` ranges so the two never
+/// collide.
+#[error_code(offset = 9000)]
+pub enum TransferFeeError {
+    #[msg("Math overflow")]
+    MathOverflow,
+}
+
+/// Fee withheld by the mint when `amount` is sent.
+pub fn transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    with_fee_config(mint, |config, epoch| config.calculate_epoch_fee(epoch, amount))
+}
+
+/// What arrives when `amount` is sent.
+pub fn amount_after_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    let fee = transfer_fee(mint, amount)?;
+    Ok(amount - fee)
+}
+
+/// What must be sent for `amount` to arrive.
+pub fn amount_before_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    let fee = with_fee_config(mint, |config, epoch| {
+        config.calculate_inverse_epoch_fee(epoch, amount)
+    })?;
+    amount.checked_add(fee).ok_or_else(|| error!(TransferFeeError::MathOverflow))
+}
+
+/// Moves the fees withheld in `account` to its mint. Token-2022 refuses to
+/// close an account with fees still withheld; harvesting needs no
+/// authority, so an escrow can always be emptied this way before closing.
+pub fn harvest_withheld_fees<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+) -> Result<()> {
+    if *account.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let withheld = {
+        let data = account.try_borrow_data()?;
+        let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+        state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fees| u64::from(fees.withheld_amount))
+    };
+    if withheld == 0 {
+        return Ok(());
+    }
+    let ix = harvest_withheld_tokens_to_mint(token_program.key, mint.key, &[account.key])?;
+    invoke(&ix, &[mint.clone(), account.clone(), token_program.clone()])?;
+    Ok(())
+}
+
+fn with_fee_config(
+    mint: &AccountInfo,
+    calculate: impl FnOnce(&TransferFeeConfig, u64) -> Option<u64>,
+) -> Result<u64> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(0);
+    }
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    match state.get_extension::<TransferFeeConfig>() {
+        Ok(config) => calculate(config, Clock::get()?.epoch)
+            .ok_or_else(|| error!(TransferFeeError::MathOverflow)),
+        Err(_) => Ok(0),
+    }
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount, slice count and interval must be non-zero and the TWAP window within what the oracle keeps")]
+    InvalidDcaParameters,
+    
+    #[msg("Slippage allowance must be below 10000 basis points")]
+    InvalidSlippage,
+    
+    #[msg("Next slice is not due yet")]
+    SliceNotDue,
+    
+    #[msg("Nothing left to swap")]
+    DcaComplete,
+    
+    #[msg("Pool oracle has no TWAP over the order's window")]
+    TwapUnavailable,
+    
+    #[msg("Withdrawal exceeds the escrowed amount")]
+    InsufficientEscrow,
+    
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
+    
+    #[msg("Invalid mint")]
+    InvalidMint,
+    
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Math overflow")]
+    MathOverflow,
+}
@@ -0,0 +1,328 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use swap::program::Swap;
+use swap::{min_amount_out, PriceOracle, SwapInfo, MAX_TWAP_WINDOW};
+use transfer_fee::harvest_withheld_fees;
+
+mod error;
+mod state;
+
+use error::ErrorCode;
+use state::*;
+
+declare_id!("FDhMpHEP4J5qcMaswpXF8ucLcwc2E14yAPwKjGgbYVUj");
+
+#[program]
+pub mod dca {
+    use super::*;
+
+    /// Escrows `total_amount` to be swapped into the pool's other token in
+    /// `slice_count` equal slices, `interval` seconds apart. Each slice must
+    /// pay at least the pool's TWAP over `twap_window` seconds, less
+    /// `max_slippage_bps`. The first slice is due immediately.
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_dca(
+        ctx: Context<OpenDca>,
+        order_id: u64,
+        total_amount: u64,
+        slice_count: u64,
+        interval: i64,
+        twap_window: u32,
+        max_slippage_bps: u16,
+    ) -> Result<()> {
+        require!(
+            slice_count > 0
+                && total_amount / slice_count > 0
+                && interval > 0
+                && twap_window > 0
+                && twap_window <= MAX_TWAP_WINDOW,
+            ErrorCode::InvalidDcaParameters
+        );
+        require!(
+            (max_slippage_bps as u64) < BPS_DENOMINATOR,
+            ErrorCode::InvalidSlippage
+        );
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_in_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_in_account.to_account_info(),
+                    mint: ctx.accounts.mint_in.to_account_info(),
+                    to: ctx.accounts.escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            total_amount,
+            ctx.accounts.mint_in.decimals,
+        )?;
+
+        let order = &mut ctx.accounts.order;
+        order.owner = ctx.accounts.owner.key();
+        order.swap = ctx.accounts.swap.key();
+        order.mint_in = ctx.accounts.mint_in.key();
+        order.mint_out = ctx.accounts.mint_out.key();
+        order.escrow = ctx.accounts.escrow.key();
+        order.slice_amount = total_amount / slice_count;
+        order.interval = interval;
+        order.next_slice_at = Clock::get()?.unix_timestamp;
+        order.twap_window = twap_window;
+        order.max_slippage_bps = max_slippage_bps;
+        order.order_id = order_id;
+        order.bump = *ctx.bumps.get("order").unwrap();
+
+        Ok(())
+    }
+
+    /// Swaps the next due slice through the swap program, paying the owner.
+    /// Permissionless; the TWAP floor keeps a cranker from timing a slice
+    /// into a manipulated price. Remaining accounts are passed on to the
+    /// swap, for pools whose guardrail reads another pool's oracle.
+    pub fn execute_dca_slice<'info>(ctx: Context<'_, '_, '_, 'info, ExecuteDcaSlice<'info>>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let order = &ctx.accounts.order;
+        require!(now >= order.next_slice_at, ErrorCode::SliceNotDue);
+        let amount = order.next_slice(ctx.accounts.escrow.amount);
+        require!(amount > 0, ErrorCode::DcaComplete);
+
+        // TWAP up to now, from the reserves the slice is about to trade against
+        let swap = &ctx.accounts.swap;
+        let (reserve_a, reserve_b) = if ctx.accounts.token_in_account.key() == swap.token_a_account {
+            (ctx.accounts.token_in_account.amount, ctx.accounts.token_out_account.amount)
+        } else {
+            (ctx.accounts.token_out_account.amount, ctx.accounts.token_in_account.amount)
+        };
+        let (price_a, price_b) = ctx
+            .accounts
+            .oracle
+            .ring
+            .twap_to_now(&swap.last_observation, reserve_a, reserve_b, now, order.twap_window)
+            .ok_or(ErrorCode::TwapUnavailable)?;
+        let twap_price = if order.mint_in == swap.token_a_mint { price_a } else { price_b };
+        let minimum_amount_out = min_amount_out(amount, twap_price, order.max_slippage_bps)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let owner = order.owner;
+        let order_id = order.order_id.to_le_bytes();
+        let order_seeds = &[b"dca".as_ref(), owner.as_ref(), order_id.as_ref(), &[order.bump]];
+        swap::cpi::swap_tokens(
+            CpiContext::new_with_signer(
+                ctx.accounts.swap_program.to_account_info(),
+                swap::cpi::accounts::Swap {
+                    pool: swap::cpi::accounts::SwapPool {
+                        swap: ctx.accounts.swap.to_account_info(),
+                        token_in_account: ctx.accounts.token_in_account.to_account_info(),
+                        token_out_account: ctx.accounts.token_out_account.to_account_info(),
+                        mint_in: ctx.accounts.mint_in.to_account_info(),
+                        mint_out: ctx.accounts.mint_out.to_account_info(),
+                        oracle: Some(ctx.accounts.oracle.to_account_info()),
+                        pool_authority: ctx.accounts.pool_authority.to_account_info(),
+                        token_in_program: ctx.accounts.token_in_program.to_account_info(),
+                        token_out_program: ctx.accounts.token_out_program.to_account_info(),
+                    },
+                    user_token_in_account: ctx.accounts.escrow.to_account_info(),
+                    user_token_out_account: ctx.accounts.owner_token_out_account.to_account_info(),
+                    user: order.to_account_info(),
+                },
+                &[order_seeds],
+            )
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec()),
+            amount,
+            minimum_amount_out,
+        )?;
+
+        ctx.accounts.order.next_slice_at = now + ctx.accounts.order.interval;
+
+        Ok(())
+    }
+
+    /// Takes `amount` back out of escrow, shrinking what later slices swap.
+    pub fn withdraw_dca(ctx: Context<WithdrawDca>, amount: u64) -> Result<()> {
+        require!(
+            amount <= ctx.accounts.escrow.amount,
+            ErrorCode::InsufficientEscrow
+        );
+        let order = &ctx.accounts.order;
+        let order_id = order.order_id.to_le_bytes();
+        let order_seeds = &[b"dca".as_ref(), order.owner.as_ref(), order_id.as_ref(), &[order.bump]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_in_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    mint: ctx.accounts.mint_in.to_account_info(),
+                    to: ctx.accounts.user_token_in_account.to_account_info(),
+                    authority: order.to_account_info(),
+                },
+                &[order_seeds],
+            ),
+            amount,
+            ctx.accounts.mint_in.decimals,
+        )?;
+
+        Ok(())
+    }
+
+    /// Refunds whatever is still escrowed and closes the order. Transfer
+    /// fees withheld in the escrow go to the mint first, since Token-2022
+    /// will not close the escrow while it holds any.
+    pub fn cancel_dca(ctx: Context<CancelDca>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let order_id = order.order_id.to_le_bytes();
+        let order_seeds = &[b"dca".as_ref(), order.owner.as_ref(), order_id.as_ref(), &[order.bump]];
+
+        if ctx.accounts.escrow.amount > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_in_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        mint: ctx.accounts.mint_in.to_account_info(),
+                        to: ctx.accounts.user_token_in_account.to_account_info(),
+                        authority: order.to_account_info(),
+                    },
+                    &[order_seeds],
+                ),
+                ctx.accounts.escrow.amount,
+                ctx.accounts.mint_in.decimals,
+            )?;
+        }
+        harvest_withheld_fees(
+            &ctx.accounts.token_in_program.to_account_info(),
+            &ctx.accounts.mint_in.to_account_info(),
+            &ctx.accounts.escrow.to_account_info(),
+        )?;
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_in_program.to_account_info(),
+            token_interface::CloseAccount {
+                account: ctx.accounts.escrow.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: order.to_account_info(),
+            },
+            &[order_seeds],
+        ))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct OpenDca<'info> {
+    pub swap: Account<'info, SwapInfo>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + DcaOrder::LEN,
+        seeds = [b"dca", owner.key().as_ref(), order_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order: Account<'info, DcaOrder>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"dca_escrow", order.key().as_ref()],
+        bump,
+        token::mint = mint_in,
+        token::authority = order,
+        token::token_program = token_in_program
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = mint_in.key() == swap.token_a_mint
+            || mint_in.key() == swap.token_b_mint @ ErrorCode::InvalidMint
+    )]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        constraint = mint_out.key() == swap.token_a_mint
+            || mint_out.key() == swap.token_b_mint @ ErrorCode::InvalidMint,
+        constraint = mint_out.key() != mint_in.key() @ ErrorCode::InvalidMint
+    )]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDcaSlice<'info> {
+    #[account(mut, has_one = swap, has_one = escrow)]
+    pub order: Account<'info, DcaOrder>,
+    #[account(mut)]
+    pub swap: Box<Account<'info, SwapInfo>>,
+    /// Pool's observation ring buffer; the slice's swap writes to it too
+    #[account(
+        mut,
+        seeds = [b"oracle", swap.key().as_ref()],
+        bump,
+        seeds::program = swap_program.key()
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+    #[account(mut)]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = token_in_account.key() == swap.token_a_account
+            || token_in_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_in_account.mint == order.mint_in @ ErrorCode::InvalidMint
+    )]
+    pub token_in_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = token_out_account.key() == swap.token_a_account
+            || token_out_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_out_account.mint == order.mint_out @ ErrorCode::InvalidMint
+    )]
+    pub token_out_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = owner_token_out_account.owner == order.owner @ ErrorCode::InvalidTokenAccount,
+        constraint = owner_token_out_account.mint == order.mint_out @ ErrorCode::InvalidMint
+    )]
+    pub owner_token_out_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = order.mint_in @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = order.mint_out @ ErrorCode::InvalidMint)]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA that owns the pool token accounts, checked by the swap program
+    pub pool_authority: UncheckedAccount<'info>,
+    pub swap_program: Program<'info, Swap>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+    pub token_out_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawDca<'info> {
+    #[account(has_one = owner @ ErrorCode::Unauthorized, has_one = escrow)]
+    pub order: Account<'info, DcaOrder>,
+    #[account(mut)]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the withdrawal
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = order.mint_in @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    pub owner: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDca<'info> {
+    #[account(mut, close = owner, has_one = owner @ ErrorCode::Unauthorized, has_one = escrow)]
+    pub order: Account<'info, DcaOrder>,
+    #[account(mut)]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the refund
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    /// Writable to take the escrow's withheld transfer fees
+    #[account(mut, address = order.mint_in @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+}
@@ -0,0 +1,74 @@
+use anchor_lang::prelude::*;
+
+/// Basis points in one whole
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Input tokens escrowed to be swapped through a NovaDEX pool in equal
+/// slices, one every `interval` seconds, by whoever cranks the order.
+#[account]
+#[derive(Default)]
+pub struct DcaOrder {
+    /// Owner of the order, paid its output and refunds
+    pub owner: Pubkey,
+    /// Swap pool the slices trade through
+    pub swap: Pubkey,
+    /// Mint of the escrowed input
+    pub mint_in: Pubkey,
+    /// Mint the owner receives
+    pub mint_out: Pubkey,
+    /// Token account holding the escrowed input
+    pub escrow: Pubkey,
+    /// Input swapped per slice
+    pub slice_amount: u64,
+    /// Seconds between slices
+    pub interval: i64,
+    /// Unix timestamp from which the next slice may execute
+    pub next_slice_at: i64,
+    /// Seconds of pool TWAP each slice is priced against
+    pub twap_window: u32,
+    /// Most a slice may pay below the TWAP, in basis points
+    pub max_slippage_bps: u16,
+    /// Caller-chosen id, part of the order's address
+    pub order_id: u64,
+    /// Bump seed for the order PDA
+    pub bump: u8,
+}
+
+impl DcaOrder {
+    pub const LEN: usize = 32 + // owner
+                           32 + // swap
+                           32 + // mint_in
+                           32 + // mint_out
+                           32 + // escrow
+                           8 +  // slice_amount
+                           8 +  // interval
+                           8 +  // next_slice_at
+                           4 +  // twap_window
+                           2 +  // max_slippage_bps
+                           8 +  // order_id
+                           1;   // bump
+
+    /// Input for the next slice out of `escrowed`. A remainder too small
+    /// to be a slice of its own rides along with the last one.
+    pub fn next_slice(&self, escrowed: u64) -> u64 {
+        if escrowed < self.slice_amount.saturating_mul(2) {
+            escrowed
+        } else {
+            self.slice_amount
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_slice_takes_the_remainder() {
+        let order = DcaOrder { slice_amount: 25, ..Default::default() };
+        assert_eq!(order.next_slice(100), 25);
+        assert_eq!(order.next_slice(50), 25);
+        assert_eq!(order.next_slice(49), 49);
+        assert_eq!(order.next_slice(0), 0);
+    }
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Reward index is out of range or not set up")]
+    InvalidRewardIndex,
+    
+    #[msg("Reward slot is already in use")]
+    RewardAlreadyInitialized,
+    
+    #[msg("Emission schedule must end in the future and not before the current one")]
+    InvalidSchedule,
+    
+    #[msg("Amount must be non-zero")]
+    InvalidAmount,
+    
+    #[msg("Amount exceeds the staked balance")]
+    InsufficientStake,
+    
+    #[msg("Reported vote-escrow power exceeds the total")]
+    InvalidBoost,
+    
+    #[msg("Invalid mint")]
+    InvalidMint,
+    
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Math overflow")]
+    MathOverflow,
+}
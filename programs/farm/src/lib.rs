@@ -0,0 +1,389 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+mod error;
+mod state;
+
+use error::ErrorCode;
+pub use state::*;
+
+declare_id!("38VFKYpc9HDVBFZsNLhrT1RJBARhe241jkC9jVYgGqSr");
+
+#[program]
+pub mod farm {
+    use super::*;
+
+    /// Creates a farm for `lp_mint`. Staked LP tokens and undistributed
+    /// rewards are held by the farm authority PDA.
+    pub fn initialize_farm(ctx: Context<InitializeFarm>) -> Result<()> {
+        let farm = &mut ctx.accounts.farm;
+        farm.authority = ctx.accounts.authority.key();
+        farm.lp_mint = ctx.accounts.lp_mint.key();
+        farm.lp_vault = ctx.accounts.lp_vault.key();
+        farm.total_staked = 0;
+        farm.total_effective = 0;
+        farm.boost_authority = Pubkey::default();
+        farm.bump = *ctx.bumps.get("farm_authority").unwrap();
+        Ok(())
+    }
+
+    /// Sets up reward slot `index` to pay `reward_mint`. Nothing is emitted
+    /// until the slot is funded.
+    pub fn add_reward(ctx: Context<AddReward>, index: u8) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let reward = &mut ctx.accounts.farm.rewards[index as usize];
+        reward.mint = ctx.accounts.reward_mint.key();
+        reward.vault = ctx.accounts.reward_vault.key();
+        reward.emissions_per_second = 0;
+        reward.end_time = now;
+        reward.last_update = now;
+        reward.reward_per_share = 0;
+        Ok(())
+    }
+
+    /// Deposits `amount` of reward `index` and re-spreads everything not yet
+    /// emitted evenly from now until `end_time`. Lowering the rate this way
+    /// is allowed, shortening the schedule is not.
+    pub fn fund_reward(ctx: Context<FundReward>, index: u8, amount: u64, end_time: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        let reward = farm.rewards[index as usize];
+        require!(
+            end_time > now && end_time >= reward.end_time,
+            ErrorCode::InvalidSchedule
+        );
+        farm.accrue(now).ok_or(ErrorCode::MathOverflow)?;
+
+        // Credit what actually reached the vault, net of any transfer fee
+        let balance_before = ctx.accounts.reward_vault.amount;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.reward_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.funder_reward_account.to_account_info(),
+                    mint: ctx.accounts.reward_mint.to_account_info(),
+                    to: ctx.accounts.reward_vault.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
+        ctx.accounts.reward_vault.reload()?;
+        let received = ctx.accounts.reward_vault.amount - balance_before;
+
+        ctx.accounts.farm.rewards[index as usize]
+            .extend(received, end_time, now)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Opens `owner`'s stake account in the farm. `payer` covers rent so a
+    /// program-owned staker can stake through CPI.
+    pub fn create_stake(ctx: Context<CreateStake>) -> Result<()> {
+        let stake = &mut ctx.accounts.stake;
+        stake.farm = ctx.accounts.farm.key();
+        stake.owner = ctx.accounts.owner.key();
+        stake.reward_per_share_paid = ctx.accounts.farm.rewards.map(|reward| reward.reward_per_share);
+        Ok(())
+    }
+
+    /// Stakes `amount` LP tokens, settling rewards earned so far first.
+    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        farm.accrue(now).ok_or(ErrorCode::MathOverflow)?;
+        ctx.accounts.stake.settle(farm).ok_or(ErrorCode::MathOverflow)?;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.lp_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_lp_account.to_account_info(),
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.lp_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.lp_mint.decimals,
+        )?;
+
+        let stake = &mut ctx.accounts.stake;
+        stake.amount = stake.amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        let farm = &mut ctx.accounts.farm;
+        farm.total_staked = farm.total_staked.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        stake.update_effective(farm).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Withdraws `amount` staked LP tokens. Earned rewards stay claimable.
+    pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(amount <= ctx.accounts.stake.amount, ErrorCode::InsufficientStake);
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        farm.accrue(now).ok_or(ErrorCode::MathOverflow)?;
+        ctx.accounts.stake.settle(farm).ok_or(ErrorCode::MathOverflow)?;
+
+        let farm_key = farm.key();
+        let farm_authority_seeds = &[farm_key.as_ref(), &[farm.bump]];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.lp_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.lp_vault.to_account_info(),
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.owner_lp_account.to_account_info(),
+                    authority: ctx.accounts.farm_authority.to_account_info(),
+                },
+                &[farm_authority_seeds],
+            ),
+            amount,
+            ctx.accounts.lp_mint.decimals,
+        )?;
+
+        let stake = &mut ctx.accounts.stake;
+        stake.amount -= amount;
+        let farm = &mut ctx.accounts.farm;
+        farm.total_staked -= amount;
+        stake.update_effective(farm).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Pays out everything the stake has earned from reward `index`.
+    pub fn claim_reward(ctx: Context<ClaimReward>, index: u8) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        farm.accrue(now).ok_or(ErrorCode::MathOverflow)?;
+        let stake = &mut ctx.accounts.stake;
+        stake.settle(farm).ok_or(ErrorCode::MathOverflow)?;
+        let amount = std::mem::take(&mut stake.rewards_owed[index as usize]);
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let farm_key = farm.key();
+        let farm_authority_seeds = &[farm_key.as_ref(), &[farm.bump]];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.reward_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    mint: ctx.accounts.reward_mint.to_account_info(),
+                    to: ctx.accounts.owner_reward_account.to_account_info(),
+                    authority: ctx.accounts.farm_authority.to_account_info(),
+                },
+                &[farm_authority_seeds],
+            ),
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
+
+        Ok(())
+    }
+
+    /// Records the staker's vote-escrow power as reported by the farm's
+    /// boost authority and reweights their stake to match.
+    pub fn update_boost(ctx: Context<UpdateBoost>, ve_power: u64, ve_total_power: u64) -> Result<()> {
+        require!(ve_power <= ve_total_power, ErrorCode::InvalidBoost);
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        farm.accrue(now).ok_or(ErrorCode::MathOverflow)?;
+        let stake = &mut ctx.accounts.stake;
+        stake.settle(farm).ok_or(ErrorCode::MathOverflow)?;
+        stake.ve_power = ve_power;
+        stake.ve_total_power = ve_total_power;
+        stake.update_effective(farm).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Lets `boost_authority` report stakers' vote-escrow power, or turns
+    /// boosting off with the default pubkey. Existing stakes are reweighted
+    /// as they are next touched.
+    pub fn set_boost_authority(ctx: Context<UpdateFarm>, boost_authority: Pubkey) -> Result<()> {
+        ctx.accounts.farm.boost_authority = boost_authority;
+        Ok(())
+    }
+
+    /// Hands the farm to `new_authority`.
+    pub fn set_farm_authority(ctx: Context<UpdateFarm>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.farm.authority = new_authority;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeFarm<'info> {
+    #[account(init, payer = authority, space = 8 + Farm::LEN)]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: PDA that owns the farm's vaults
+    #[account(seeds = [farm.key().as_ref()], bump)]
+    pub farm_authority: UncheckedAccount<'info>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"lp_vault", farm.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = farm_authority,
+        token::token_program = lp_token_program
+    )]
+    pub lp_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u8)]
+pub struct AddReward<'info> {
+    #[account(
+        mut,
+        has_one = authority @ ErrorCode::Unauthorized,
+        constraint = (index as usize) < MAX_REWARDS @ ErrorCode::InvalidRewardIndex,
+        constraint = !farm.rewards[index as usize].is_initialized() @ ErrorCode::RewardAlreadyInitialized,
+        constraint = farm.rewards.iter().all(|reward| reward.mint != reward_mint.key()) @ ErrorCode::InvalidMint
+    )]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: PDA that owns the farm's vaults
+    #[account(seeds = [farm.key().as_ref()], bump = farm.bump)]
+    pub farm_authority: UncheckedAccount<'info>,
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"reward_vault", farm.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = farm_authority,
+        token::token_program = reward_token_program
+    )]
+    pub reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u8)]
+pub struct FundReward<'info> {
+    #[account(
+        mut,
+        has_one = authority @ ErrorCode::Unauthorized,
+        constraint = farm.rewards.get(index as usize).is_some_and(|reward| reward.is_initialized())
+            @ ErrorCode::InvalidRewardIndex
+    )]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(address = farm.rewards[index as usize].mint @ ErrorCode::InvalidMint)]
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = farm.rewards[index as usize].vault @ ErrorCode::InvalidRewardIndex)]
+    pub reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub funder_reward_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub authority: Signer<'info>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateStake<'info> {
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + UserStake::LEN,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub stake: Account<'info, UserStake>,
+    pub owner: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(mut, has_one = lp_mint @ ErrorCode::InvalidMint, has_one = lp_vault)]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(mut, has_one = farm, has_one = owner @ ErrorCode::Unauthorized)]
+    pub stake: Account<'info, UserStake>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub lp_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub owner_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub owner: Signer<'info>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    #[account(mut, has_one = lp_mint @ ErrorCode::InvalidMint, has_one = lp_vault)]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: PDA that owns the farm's vaults
+    #[account(seeds = [farm.key().as_ref()], bump = farm.bump)]
+    pub farm_authority: UncheckedAccount<'info>,
+    #[account(mut, has_one = farm, has_one = owner @ ErrorCode::Unauthorized)]
+    pub stake: Account<'info, UserStake>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub lp_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the LP tokens
+    #[account(mut)]
+    pub owner_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub owner: Signer<'info>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u8)]
+pub struct ClaimReward<'info> {
+    #[account(
+        mut,
+        constraint = farm.rewards.get(index as usize).is_some_and(|reward| reward.is_initialized())
+            @ ErrorCode::InvalidRewardIndex
+    )]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: PDA that owns the farm's vaults
+    #[account(seeds = [farm.key().as_ref()], bump = farm.bump)]
+    pub farm_authority: UncheckedAccount<'info>,
+    #[account(mut, has_one = farm, has_one = owner @ ErrorCode::Unauthorized)]
+    pub stake: Account<'info, UserStake>,
+    #[account(address = farm.rewards[index as usize].mint @ ErrorCode::InvalidMint)]
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = farm.rewards[index as usize].vault @ ErrorCode::InvalidRewardIndex)]
+    pub reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the rewards
+    #[account(mut)]
+    pub owner_reward_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub owner: Signer<'info>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBoost<'info> {
+    #[account(
+        mut,
+        constraint = farm.boost_authority != Pubkey::default() @ ErrorCode::Unauthorized,
+        has_one = boost_authority @ ErrorCode::Unauthorized
+    )]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(mut, has_one = farm)]
+    pub stake: Account<'info, UserStake>,
+    pub boost_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFarm<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub farm: Box<Account<'info, Farm>>,
+    pub authority: Signer<'info>,
+}
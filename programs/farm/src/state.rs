@@ -0,0 +1,267 @@
+use anchor_lang::prelude::*;
+
+/// Reward mints a farm can emit at once
+pub const MAX_REWARDS: usize = 3;
+
+/// Basis points in one whole
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Share of a stake that earns rewards without any vote-escrow boost, in
+/// basis points. A fully boosted stake earns 2.5 times as much.
+pub const UNBOOSTED_WEIGHT_BPS: u64 = 4_000;
+
+/// One reward stream of a farm. Emissions are shared between stakers by a
+/// reward-per-share accumulator, so accrual is O(1) however many stake.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct RewardInfo {
+    /// Reward mint, the default pubkey while the slot is unused
+    pub mint: Pubkey,
+    /// Token account holding undistributed rewards
+    pub vault: Pubkey,
+    /// Tokens emitted per second until `end_time`
+    pub emissions_per_second: u64,
+    /// Unix timestamp emissions stop at
+    pub end_time: i64,
+    /// Unix timestamp `reward_per_share` was last brought up to
+    pub last_update: i64,
+    /// Rewards emitted per staked token since the slot was set up, as Q64.64
+    pub reward_per_share: u128,
+}
+
+impl RewardInfo {
+    pub const LEN: usize = 32 + // mint
+                           32 + // vault
+                           8 +  // emissions_per_second
+                           8 +  // end_time
+                           8 +  // last_update
+                           16;  // reward_per_share
+
+    pub fn is_initialized(&self) -> bool {
+        self.mint != Pubkey::default()
+    }
+
+    /// Shares out everything emitted between `last_update` and `now` over
+    /// `total_staked`. Emissions while nothing is staked are not recovered.
+    pub fn accrue(&mut self, total_staked: u64, now: i64) -> Option<()> {
+        let until = now.min(self.end_time);
+        if until <= self.last_update {
+            return Some(());
+        }
+        if total_staked > 0 {
+            let emitted = ((until - self.last_update) as u128).checked_mul(self.emissions_per_second as u128)?;
+            let per_share = emitted.checked_mul(1 << 64)? / total_staked as u128;
+            self.reward_per_share = self.reward_per_share.checked_add(per_share)?;
+        }
+        self.last_update = until;
+        Some(())
+    }
+
+    /// Sets the stream to emit what is still scheduled after `now` plus
+    /// `amount`, evenly until `end_time`. `accrue` must have run first.
+    pub fn extend(&mut self, amount: u64, end_time: i64, now: i64) -> Option<()> {
+        let unemitted = if self.end_time > now {
+            ((self.end_time - now) as u128).checked_mul(self.emissions_per_second as u128)?
+        } else {
+            0
+        };
+        let total = unemitted.checked_add(amount as u128)?;
+        self.emissions_per_second = u64::try_from(total / (end_time - now) as u128).ok()?;
+        self.end_time = end_time;
+        self.last_update = now;
+        Some(())
+    }
+}
+
+/// A farm paying reward tokens to stakers of one liquidity-pool LP mint.
+#[account]
+#[derive(Default)]
+pub struct Farm {
+    /// Authority that sets up and funds rewards
+    pub authority: Pubkey,
+    /// LP mint staked in the farm
+    pub lp_mint: Pubkey,
+    /// Token account holding staked LP tokens
+    pub lp_vault: Pubkey,
+    /// LP tokens staked across all users
+    pub total_staked: u64,
+    /// Boosted stake across all users, which rewards are shared over
+    pub total_effective: u64,
+    /// Signer allowed to report stakers' vote-escrow power, the default
+    /// pubkey while boosting is off
+    pub boost_authority: Pubkey,
+    pub rewards: [RewardInfo; MAX_REWARDS],
+    /// Bump seed for the farm authority PDA, `[farm.key()]`
+    pub bump: u8,
+}
+
+impl Farm {
+    pub const LEN: usize = 32 + // authority
+                           32 + // lp_mint
+                           32 + // lp_vault
+                           8 +  // total_staked
+                           8 +  // total_effective
+                           32 + // boost_authority
+                           RewardInfo::LEN * MAX_REWARDS + // rewards
+                           1;   // bump
+
+    /// Brings every reward stream up to `now`
+    pub fn accrue(&mut self, now: i64) -> Option<()> {
+        let total_effective = self.total_effective;
+        for reward in self.rewards.iter_mut().filter(|reward| reward.is_initialized()) {
+            reward.accrue(total_effective, now)?;
+        }
+        Some(())
+    }
+
+    /// Stake that `stake` earns rewards on. With boosting on, an unboosted
+    /// stake counts for 40% of its LP and vote-escrow power tops that up
+    /// by its share of the total power times 60% of everything staked,
+    /// capped at the stake itself.
+    pub fn effective_amount(&self, stake: &UserStake) -> u64 {
+        if self.boost_authority == Pubkey::default() {
+            return stake.amount;
+        }
+        let base = stake.amount as u128 * UNBOOSTED_WEIGHT_BPS as u128 / BPS_DENOMINATOR as u128;
+        let boost = if stake.ve_total_power > 0 {
+            self.total_staked as u128 * stake.ve_power as u128 / stake.ve_total_power as u128
+                * (BPS_DENOMINATOR - UNBOOSTED_WEIGHT_BPS) as u128
+                / BPS_DENOMINATOR as u128
+        } else {
+            0
+        };
+        (base + boost).min(stake.amount as u128) as u64
+    }
+}
+
+/// A user's stake in a farm and the rewards it has earned.
+#[account]
+#[derive(Default)]
+pub struct UserStake {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    /// LP tokens staked
+    pub amount: u64,
+    /// Stake counted towards rewards, see `Farm::effective_amount`
+    pub effective_amount: u64,
+    /// Owner's vote-escrow power and the total power, as last reported by
+    /// the farm's boost authority
+    pub ve_power: u64,
+    pub ve_total_power: u64,
+    /// Each stream's `reward_per_share` as of the last settlement
+    pub reward_per_share_paid: [u128; MAX_REWARDS],
+    /// Rewards earned but not yet claimed, per stream
+    pub rewards_owed: [u64; MAX_REWARDS],
+}
+
+impl UserStake {
+    pub const LEN: usize = 32 + // farm
+                           32 + // owner
+                           8 +  // amount
+                           8 +  // effective_amount
+                           8 +  // ve_power
+                           8 +  // ve_total_power
+                           16 * MAX_REWARDS + // reward_per_share_paid
+                           8 * MAX_REWARDS;   // rewards_owed
+
+    /// Credits what the stake earned since it was last settled. The farm
+    /// must have been accrued to now, and the stake must be settled before
+    /// its amount changes.
+    pub fn settle(&mut self, farm: &Farm) -> Option<()> {
+        for (index, reward) in farm.rewards.iter().enumerate() {
+            let growth = reward.reward_per_share.checked_sub(self.reward_per_share_paid[index])?;
+            let earned = u64::try_from((self.effective_amount as u128).checked_mul(growth)? >> 64).ok()?;
+            self.rewards_owed[index] = self.rewards_owed[index].checked_add(earned)?;
+            self.reward_per_share_paid[index] = reward.reward_per_share;
+        }
+        Some(())
+    }
+
+    /// Recomputes the effective amount after the stake, the farm total or
+    /// the reported power changed, keeping the farm total in step. The
+    /// stake must be settled first.
+    pub fn update_effective(&mut self, farm: &mut Farm) -> Option<()> {
+        let effective_amount = farm.effective_amount(self);
+        farm.total_effective = farm
+            .total_effective
+            .checked_sub(self.effective_amount)?
+            .checked_add(effective_amount)?;
+        self.effective_amount = effective_amount;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reward(emissions_per_second: u64, end_time: i64) -> RewardInfo {
+        RewardInfo {
+            mint: Pubkey::new_unique(),
+            emissions_per_second,
+            end_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stakers_share_emissions_pro_rata() {
+        let mut farm = Farm { total_staked: 300, ..Default::default() };
+        farm.rewards[0] = reward(30, 100);
+        let mut alice = UserStake { amount: 100, ..Default::default() };
+        let mut bob = UserStake { amount: 200, ..Default::default() };
+        alice.update_effective(&mut farm).unwrap();
+        bob.update_effective(&mut farm).unwrap();
+        assert_eq!(farm.total_effective, 300);
+
+        farm.accrue(10).unwrap();
+        alice.settle(&farm).unwrap();
+        bob.settle(&farm).unwrap();
+        assert_eq!(alice.rewards_owed[0], 100);
+        assert_eq!(bob.rewards_owed[0], 200);
+
+        // Nothing accrues past the end of the schedule
+        farm.accrue(1_000).unwrap();
+        alice.settle(&farm).unwrap();
+        assert_eq!(alice.rewards_owed[0], 1_000);
+        assert_eq!(farm.rewards[0].last_update, 100);
+    }
+
+    #[test]
+    fn extending_spreads_the_rest_over_the_new_window() {
+        let mut stream = reward(10, 100);
+        stream.accrue(1, 50).unwrap();
+        // 500 left to emit plus 1_500 new, over 100 seconds
+        stream.extend(1_500, 150, 50).unwrap();
+        assert_eq!(stream.emissions_per_second, 20);
+        assert_eq!(stream.end_time, 150);
+
+        // A lapsed stream restarts from now
+        let mut lapsed = reward(10, 100);
+        lapsed.accrue(1, 200).unwrap();
+        lapsed.extend(1_000, 300, 200).unwrap();
+        assert_eq!(lapsed.emissions_per_second, 10);
+        assert_eq!(lapsed.last_update, 200);
+    }
+
+    #[test]
+    fn vote_escrow_power_boosts_up_to_the_stake() {
+        let mut farm = Farm { total_staked: 1_000, ..Default::default() };
+        let mut stake = UserStake { amount: 100, ..Default::default() };
+        // Boosting off, the whole stake counts
+        assert_eq!(farm.effective_amount(&stake), 100);
+
+        farm.boost_authority = Pubkey::new_unique();
+        stake.update_effective(&mut farm).unwrap();
+        assert_eq!((stake.effective_amount, farm.total_effective), (40, 40));
+
+        // 5% of the power tops up 60% of 5% of the farm
+        stake.ve_power = 5;
+        stake.ve_total_power = 100;
+        stake.update_effective(&mut farm).unwrap();
+        assert_eq!((stake.effective_amount, farm.total_effective), (70, 70));
+
+        // More power than the stake needs is capped
+        stake.ve_power = 50;
+        assert_eq!(farm.effective_amount(&stake), 100);
+    }
+}
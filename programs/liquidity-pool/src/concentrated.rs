@@ -0,0 +1,505 @@
+use anchor_lang::prelude::*;
+
+use crate::tick_math::*;
+use crate::ErrorCode;
+
+/// Number of initializable ticks stored in each tick array
+pub const TICK_ARRAY_SIZE: i32 = 60;
+
+/// A pool whose liquidity is provided over explicit price ranges instead of
+/// the full curve. Uses the same fee configuration as `LiquidityPool`.
+#[account]
+#[derive(Default)]
+pub struct ConcentratedPool {
+    /// Token A mint address
+    pub token_a_mint: Pubkey,
+    /// Token B mint address
+    pub token_b_mint: Pubkey,
+    /// Vault holding token A
+    pub token_a_account: Pubkey,
+    /// Vault holding token B
+    pub token_b_account: Pubkey,
+    /// Fee numerator (fee = numerator / denominator)
+    pub fee_numerator: u64,
+    /// Fee denominator
+    pub fee_denominator: u64,
+    /// Authority of the pool
+    pub authority: Pubkey,
+    /// Bump seed for the pool authority PDA
+    pub bump: u8,
+    /// Positions may only start and end on multiples of this
+    pub tick_spacing: u16,
+    /// Current sqrt(price) as Q64.64
+    pub sqrt_price: u128,
+    /// Greatest tick at or below the current price
+    pub tick_current: i32,
+    /// Liquidity active at the current price
+    pub liquidity: u128,
+    /// Fees earned per unit of liquidity in token A, as Q64.64
+    pub fee_growth_global_a: u128,
+    /// Fees earned per unit of liquidity in token B, as Q64.64
+    pub fee_growth_global_b: u128,
+    /// Whether swaps and deposits are stopped
+    pub paused: bool,
+}
+
+impl ConcentratedPool {
+    pub const LEN: usize = 32 + // token_a_mint
+                           32 + // token_b_mint
+                           32 + // token_a_account
+                           32 + // token_b_account
+                           8 +  // fee_numerator
+                           8 +  // fee_denominator
+                           32 + // authority
+                           1 +  // bump
+                           2 +  // tick_spacing
+                           16 + // sqrt_price
+                           4 +  // tick_current
+                           16 + // liquidity
+                           16 + // fee_growth_global_a
+                           16 + // fee_growth_global_b
+                           1;   // paused
+
+    /// Number of ticks covered by one tick array
+    pub fn ticks_per_array(&self) -> i32 {
+        TICK_ARRAY_SIZE * self.tick_spacing as i32
+    }
+
+    /// Start index of the tick array containing `tick`
+    pub fn tick_array_start(&self, tick: i32) -> i32 {
+        tick.div_euclid(self.ticks_per_array()) * self.ticks_per_array()
+    }
+
+    /// Fee growth inside `[tick_lower, tick_upper)` for both tokens, derived
+    /// from the global growth and each boundary's growth outside
+    pub fn fee_growth_inside(&self, lower: &Tick, tick_lower: i32, upper: &Tick, tick_upper: i32) -> (u128, u128) {
+        let inside = |global: u128, lower_outside: u128, upper_outside: u128| {
+            let below = if self.tick_current >= tick_lower {
+                lower_outside
+            } else {
+                global.wrapping_sub(lower_outside)
+            };
+            let above = if self.tick_current < tick_upper {
+                upper_outside
+            } else {
+                global.wrapping_sub(upper_outside)
+            };
+            global.wrapping_sub(below).wrapping_sub(above)
+        };
+        (
+            inside(self.fee_growth_global_a, lower.fee_growth_outside_a, upper.fee_growth_outside_a),
+            inside(self.fee_growth_global_b, lower.fee_growth_outside_b, upper.fee_growth_outside_b),
+        )
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Tick {
+    /// Whether any position uses this tick as a boundary
+    pub initialized: bool,
+    /// Liquidity added (lower bound) or removed (upper bound) when the price
+    /// crosses this tick moving up
+    pub liquidity_net: i128,
+    /// Total liquidity referencing this tick
+    pub liquidity_gross: u128,
+    /// Fee growth on the other side of this tick from the current price
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+}
+
+impl Tick {
+    pub const LEN: usize = 1 + 16 + 16 + 16 + 16;
+
+    /// Adds `liquidity_delta` from a position boundary. `upper` flips the sign
+    /// of the net liquidity since crossing an upper bound removes liquidity.
+    /// A tick left unreferenced keeps its fee growth until `clear_if_unused`,
+    /// so the position can still be settled against it.
+    pub fn update(&mut self, pool: &ConcentratedPool, tick_index: i32, liquidity_delta: i128, upper: bool) -> Result<()> {
+        let liquidity_gross = if liquidity_delta >= 0 {
+            self.liquidity_gross.checked_add(liquidity_delta as u128)
+        } else {
+            self.liquidity_gross.checked_sub(liquidity_delta.unsigned_abs())
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        // Fee growth below a freshly initialized tick is, by convention, all
+        // growth so far if the tick is at or below the current price
+        if self.liquidity_gross == 0 && liquidity_gross > 0 && tick_index <= pool.tick_current {
+            self.fee_growth_outside_a = pool.fee_growth_global_a;
+            self.fee_growth_outside_b = pool.fee_growth_global_b;
+        }
+
+        let net_delta = if upper { -liquidity_delta } else { liquidity_delta };
+        self.liquidity_net = self.liquidity_net.checked_add(net_delta).ok_or(ErrorCode::MathOverflow)?;
+        self.liquidity_gross = liquidity_gross;
+        self.initialized = liquidity_gross > 0;
+        Ok(())
+    }
+
+    /// Resets a tick no position references any more
+    pub fn clear_if_unused(&mut self) {
+        if self.liquidity_gross == 0 {
+            *self = Tick::default();
+        }
+    }
+
+    /// Flips fee growth outside when the price moves across this tick
+    pub fn cross(&mut self, pool: &ConcentratedPool) -> i128 {
+        self.fee_growth_outside_a = pool.fee_growth_global_a.wrapping_sub(self.fee_growth_outside_a);
+        self.fee_growth_outside_b = pool.fee_growth_global_b.wrapping_sub(self.fee_growth_outside_b);
+        self.liquidity_net
+    }
+}
+
+#[account]
+pub struct TickArray {
+    /// Pool the ticks belong to
+    pub pool: Pubkey,
+    /// Tick index of `ticks[0]`
+    pub start_tick_index: i32,
+    /// `TICK_ARRAY_SIZE` ticks, `tick_spacing` apart
+    pub ticks: Vec<Tick>,
+}
+
+impl TickArray {
+    pub const LEN: usize = 32 + 4 + 4 + TICK_ARRAY_SIZE as usize * Tick::LEN;
+
+    fn offset(&self, pool: &ConcentratedPool, tick_index: i32) -> Result<usize> {
+        let offset = tick_index - self.start_tick_index;
+        require!(
+            tick_index % pool.tick_spacing as i32 == 0
+                && offset >= 0
+                && offset < pool.ticks_per_array(),
+            ErrorCode::InvalidTickIndex
+        );
+        Ok((offset / pool.tick_spacing as i32) as usize)
+    }
+
+    pub fn tick(&self, pool: &ConcentratedPool, tick_index: i32) -> Result<&Tick> {
+        let offset = self.offset(pool, tick_index)?;
+        Ok(&self.ticks[offset])
+    }
+
+    pub fn tick_mut(&mut self, pool: &ConcentratedPool, tick_index: i32) -> Result<&mut Tick> {
+        let offset = self.offset(pool, tick_index)?;
+        Ok(&mut self.ticks[offset])
+    }
+
+    /// Next initialized tick in this array from `tick_current` in the swap
+    /// direction: the greatest at or below it when moving down, the smallest
+    /// above it when moving up.
+    pub fn next_initialized_tick(&self, pool: &ConcentratedPool, tick_current: i32, a_to_b: bool) -> Option<i32> {
+        let spacing = pool.tick_spacing as i32;
+        let indices = (0..TICK_ARRAY_SIZE).map(|i| (i, self.start_tick_index + i * spacing));
+        if a_to_b {
+            indices
+                .rev()
+                .find(|(i, tick)| *tick <= tick_current && self.ticks[*i as usize].initialized)
+                .map(|(_, tick)| tick)
+        } else {
+            indices
+                .into_iter()
+                .find(|(i, tick)| *tick > tick_current && self.ticks[*i as usize].initialized)
+                .map(|(_, tick)| tick)
+        }
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct ConcentratedPosition {
+    /// Pool the position belongs to
+    pub pool: Pubkey,
+    /// Owner of the position
+    pub owner: Pubkey,
+    /// Lower bound of the price range (inclusive)
+    pub tick_lower: i32,
+    /// Upper bound of the price range (exclusive)
+    pub tick_upper: i32,
+    /// Liquidity provided over the range
+    pub liquidity: u128,
+    /// Fee growth inside the range at the last update
+    pub fee_growth_inside_last_a: u128,
+    pub fee_growth_inside_last_b: u128,
+    /// Fees earned but not yet collected
+    pub fees_owed_a: u64,
+    pub fees_owed_b: u64,
+}
+
+impl ConcentratedPosition {
+    pub const LEN: usize = 32 + // pool
+                           32 + // owner
+                           4 +  // tick_lower
+                           4 +  // tick_upper
+                           16 + // liquidity
+                           16 + // fee_growth_inside_last_a
+                           16 + // fee_growth_inside_last_b
+                           8 +  // fees_owed_a
+                           8;   // fees_owed_b
+
+    /// Credits fees earned since the last update and applies `liquidity_delta`
+    pub fn update(&mut self, fee_growth_inside: (u128, u128), liquidity_delta: i128) -> Result<()> {
+        let earned = |growth: u128, last: u128| {
+            mul_div_floor(growth.wrapping_sub(last), self.liquidity, 1 << 64)
+                .and_then(|fees| u64::try_from(fees).ok())
+                .ok_or(ErrorCode::MathOverflow)
+        };
+        let earned_a = earned(fee_growth_inside.0, self.fee_growth_inside_last_a)?;
+        let earned_b = earned(fee_growth_inside.1, self.fee_growth_inside_last_b)?;
+
+        self.fees_owed_a = self.fees_owed_a.checked_add(earned_a).ok_or(ErrorCode::MathOverflow)?;
+        self.fees_owed_b = self.fees_owed_b.checked_add(earned_b).ok_or(ErrorCode::MathOverflow)?;
+        self.fee_growth_inside_last_a = fee_growth_inside.0;
+        self.fee_growth_inside_last_b = fee_growth_inside.1;
+        self.liquidity = if liquidity_delta >= 0 {
+            self.liquidity.checked_add(liquidity_delta as u128)
+        } else {
+            self.liquidity.checked_sub(liquidity_delta.unsigned_abs())
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// Token amounts backing `liquidity` over `[tick_lower, tick_upper)` at the
+/// pool's current price
+pub fn position_amounts(
+    pool: &ConcentratedPool,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64)> {
+    let sqrt_lower = sqrt_price_from_tick(tick_lower).ok_or(ErrorCode::InvalidTickIndex)?;
+    let sqrt_upper = sqrt_price_from_tick(tick_upper).ok_or(ErrorCode::InvalidTickIndex)?;
+
+    let amounts = if pool.tick_current < tick_lower {
+        // Entirely above the price: all token A
+        (amount_a_delta(sqrt_lower, sqrt_upper, liquidity, round_up), Some(0))
+    } else if pool.tick_current < tick_upper {
+        (
+            amount_a_delta(pool.sqrt_price, sqrt_upper, liquidity, round_up),
+            amount_b_delta(sqrt_lower, pool.sqrt_price, liquidity, round_up),
+        )
+    } else {
+        // Entirely below the price: all token B
+        (Some(0), amount_b_delta(sqrt_lower, sqrt_upper, liquidity, round_up))
+    };
+    match amounts {
+        (Some(a), Some(b)) => Ok((a, b)),
+        _ => err!(ErrorCode::MathOverflow),
+    }
+}
+
+/// Outcome of `swap_concentrated` before any tokens move
+pub struct ConcentratedSwap {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+}
+
+/// Runs an exact-input swap across the supplied tick arrays, updating the
+/// pool price, liquidity, fee growth and crossed ticks in place. Tick arrays
+/// must be consecutive in the swap direction, starting with the one that
+/// holds the current tick.
+pub fn swap_across_ticks(
+    pool: &mut ConcentratedPool,
+    tick_arrays: &mut [TickArray],
+    amount: u64,
+    a_to_b: bool,
+    sqrt_price_limit: u128,
+) -> Result<ConcentratedSwap> {
+    require!(
+        if a_to_b {
+            sqrt_price_limit < pool.sqrt_price && sqrt_price_limit >= MIN_SQRT_PRICE
+        } else {
+            sqrt_price_limit > pool.sqrt_price && sqrt_price_limit <= MAX_SQRT_PRICE
+        },
+        ErrorCode::InvalidSqrtPriceLimit
+    );
+
+    let ticks_per_array = pool.ticks_per_array();
+    let first_start = pool.tick_array_start(pool.tick_current);
+    for (i, tick_array) in tick_arrays.iter().enumerate() {
+        let offset = i as i32 * ticks_per_array;
+        let expected = if a_to_b { first_start - offset } else { first_start + offset };
+        require!(
+            tick_array.start_tick_index == expected,
+            ErrorCode::TickArrayMissing
+        );
+    }
+    require!(!tick_arrays.is_empty(), ErrorCode::TickArrayMissing);
+    let coverage_end = if a_to_b {
+        (first_start - (tick_arrays.len() as i32 - 1) * ticks_per_array).max(MIN_TICK)
+    } else {
+        (first_start + tick_arrays.len() as i32 * ticks_per_array).min(MAX_TICK)
+    };
+
+    let mut amount_remaining = amount;
+    let mut amount_out: u64 = 0;
+    let mut fee_total: u64 = 0;
+
+    while amount_remaining > 0 && pool.sqrt_price != sqrt_price_limit {
+        // Swap up to the next initialized tick, or the end of the supplied arrays
+        let next_tick = tick_arrays
+            .iter()
+            .enumerate()
+            .find_map(|(i, tick_array)| {
+                tick_array
+                    .next_initialized_tick(pool, pool.tick_current, a_to_b)
+                    .map(|tick| (i, tick))
+            });
+        let target_tick = next_tick.map_or(coverage_end, |(_, tick)| tick);
+        let sqrt_price_tick = sqrt_price_from_tick(target_tick).ok_or(ErrorCode::InvalidTickIndex)?;
+        if next_tick.is_none() && pool.sqrt_price == sqrt_price_tick {
+            return err!(ErrorCode::TickArrayMissing);
+        }
+        let sqrt_price_target = if a_to_b {
+            sqrt_price_tick.max(sqrt_price_limit)
+        } else {
+            sqrt_price_tick.min(sqrt_price_limit)
+        };
+
+        let step = compute_swap_step(
+            pool.sqrt_price,
+            sqrt_price_target,
+            pool.liquidity,
+            amount_remaining,
+            pool.fee_numerator,
+            pool.fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        amount_remaining = amount_remaining
+            .checked_sub(step.amount_in + step.fee_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        amount_out = amount_out.checked_add(step.amount_out).ok_or(ErrorCode::MathOverflow)?;
+        fee_total = fee_total.checked_add(step.fee_amount).ok_or(ErrorCode::MathOverflow)?;
+
+        if pool.liquidity > 0 {
+            let growth = mul_div_floor(step.fee_amount as u128, 1 << 64, pool.liquidity)
+                .ok_or(ErrorCode::MathOverflow)?;
+            if a_to_b {
+                pool.fee_growth_global_a = pool.fee_growth_global_a.wrapping_add(growth);
+            } else {
+                pool.fee_growth_global_b = pool.fee_growth_global_b.wrapping_add(growth);
+            }
+        }
+        pool.sqrt_price = step.sqrt_price_next;
+
+        if step.sqrt_price_next != sqrt_price_tick {
+            pool.tick_current = tick_from_sqrt_price(pool.sqrt_price).ok_or(ErrorCode::MathOverflow)?;
+            continue;
+        }
+
+        match next_tick {
+            Some((i, tick_index)) => {
+                let tick = tick_arrays[i].tick_mut(pool, tick_index)?;
+                let liquidity_net = tick.cross(pool);
+                let liquidity_delta = if a_to_b { -liquidity_net } else { liquidity_net };
+                pool.liquidity = if liquidity_delta >= 0 {
+                    pool.liquidity.checked_add(liquidity_delta as u128)
+                } else {
+                    pool.liquidity.checked_sub(liquidity_delta.unsigned_abs())
+                }
+                .ok_or(ErrorCode::MathOverflow)?;
+                pool.tick_current = if a_to_b { tick_index - 1 } else { tick_index };
+            }
+            // Stopped on the edge of the supplied arrays without crossing it;
+            // stay just below so an initialized tick there is still crossed later
+            None => pool.tick_current = target_tick - 1,
+        }
+    }
+
+    Ok(ConcentratedSwap {
+        amount_in: amount - amount_remaining,
+        amount_out,
+        fee_amount: fee_total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool_with_position(liquidity: u128, tick_lower: i32, tick_upper: i32) -> (ConcentratedPool, Vec<TickArray>) {
+        let mut pool = ConcentratedPool {
+            fee_numerator: 3,
+            fee_denominator: 1_000,
+            tick_spacing: 10,
+            sqrt_price: sqrt_price_from_tick(0).unwrap(),
+            tick_current: 0,
+            liquidity,
+            ..Default::default()
+        };
+        let mut tick_arrays: Vec<TickArray> = [-600, 0]
+            .iter()
+            .map(|start| TickArray {
+                pool: Pubkey::default(),
+                start_tick_index: *start,
+                ticks: vec![Tick::default(); TICK_ARRAY_SIZE as usize],
+            })
+            .collect();
+        let delta = liquidity as i128;
+        tick_arrays[0].tick_mut(&pool, tick_lower).unwrap().update(&pool, tick_lower, delta, false).unwrap();
+        tick_arrays[1].tick_mut(&pool, tick_upper).unwrap().update(&pool, tick_upper, delta, true).unwrap();
+        pool.tick_current = 0;
+        (pool, tick_arrays)
+    }
+
+    #[test]
+    fn swap_crosses_out_of_a_position_range() {
+        let (mut pool, mut tick_arrays) = pool_with_position(1_000_000_000, -100, 100);
+        let mut down = vec![tick_arrays[1].clone(), tick_arrays[0].clone()];
+
+        // Small swap stays in range and charges the fee to the input token
+        let result = swap_across_ticks(&mut pool, &mut down, 10_000, true, MIN_SQRT_PRICE).unwrap();
+        assert_eq!(result.amount_in, 10_000);
+        assert!((29..=31).contains(&result.fee_amount));
+        assert!(pool.tick_current < 0 && pool.tick_current > -100);
+        assert_eq!(pool.liquidity, 1_000_000_000);
+        assert!(pool.fee_growth_global_a > 0);
+
+        // Pushing through the lower bound leaves no liquidity, so the swap
+        // stops at the price limit with part of the input unused. The current
+        // tick has moved into the lower array, which now goes first.
+        let mut lower_only = vec![down[1].clone()];
+        let limit = sqrt_price_from_tick(-200).unwrap();
+        let result = swap_across_ticks(&mut pool, &mut lower_only, 100_000_000, true, limit).unwrap();
+        assert!(result.amount_in < 100_000_000);
+        assert_eq!(pool.liquidity, 0);
+        assert_eq!(pool.sqrt_price, limit);
+        assert_eq!(pool.tick_current, -200);
+
+        // Swapping back up re-enters the range and restores the liquidity
+        tick_arrays = vec![lower_only[0].clone(), down[0].clone()];
+        let limit = sqrt_price_from_tick(0).unwrap();
+        swap_across_ticks(&mut pool, &mut tick_arrays, 100_000_000, false, limit).unwrap();
+        assert_eq!(pool.liquidity, 1_000_000_000);
+        assert_eq!(pool.sqrt_price, limit);
+    }
+
+    #[test]
+    fn fee_growth_inside_only_counts_in_range_swaps() {
+        let (mut pool, mut tick_arrays) = pool_with_position(1_000_000_000, -100, 100);
+        let mut down = vec![tick_arrays[1].clone(), tick_arrays[0].clone()];
+        swap_across_ticks(&mut pool, &mut down, 10_000, true, MIN_SQRT_PRICE).unwrap();
+        tick_arrays = vec![down[1].clone(), down[0].clone()];
+
+        let lower = *tick_arrays[0].tick(&pool, -100).unwrap();
+        let upper = *tick_arrays[1].tick(&pool, 100).unwrap();
+        let (inside_a, inside_b) = pool.fee_growth_inside(&lower, -100, &upper, 100);
+        assert_eq!(inside_a, pool.fee_growth_global_a);
+        assert_eq!(inside_b, 0);
+
+        let mut position = ConcentratedPosition {
+            tick_lower: -100,
+            tick_upper: 100,
+            liquidity: 1_000_000_000,
+            ..Default::default()
+        };
+        position.update((inside_a, inside_b), 0).unwrap();
+        assert!(position.fees_owed_a > 0 && position.fees_owed_a <= 30);
+        assert_eq!(position.fees_owed_b, 0);
+    }
+}
@@ -0,0 +1,91 @@
+//! Volatility-scaled swap fees. Each swap adds its price impact (in basis
+//! points) to a volatility accumulator that halves every
+//! `VOLATILITY_HALF_LIFE` seconds; the fee is the pool's base fee plus a
+//! share of the accumulator, clamped to the bounds chosen at pool creation.
+
+/// Seconds for the volatility accumulator to decay by half
+pub const VOLATILITY_HALF_LIFE: i64 = 60;
+/// Cap on the accumulator, in basis points of price movement
+pub const MAX_VOLATILITY_BPS: u64 = 100_000;
+/// Basis points of accumulated volatility per basis point of extra fee
+pub const VOLATILITY_FEE_DIVISOR: u64 = 10;
+
+const BPS: u128 = 10_000;
+
+/// Accumulator after `elapsed` seconds without trades.
+pub fn decay_volatility(volatility: u64, elapsed: i64) -> u64 {
+    if elapsed <= 0 {
+        return volatility;
+    }
+    let half_lives = elapsed / VOLATILITY_HALF_LIFE;
+    if half_lives >= 64 {
+        return 0;
+    }
+    let halved = volatility >> half_lives;
+    // Linear between whole half-lives
+    let remainder = (elapsed % VOLATILITY_HALF_LIFE) as u128;
+    let decay = halved as u128 * remainder / (2 * VOLATILITY_HALF_LIFE as u128);
+    halved - decay as u64
+}
+
+/// Relative move between two prices, in basis points.
+pub fn price_move_bps(price_before: u128, price_after: u128) -> u64 {
+    if price_before == 0 {
+        return 0;
+    }
+    let moved = price_before.abs_diff(price_after);
+    // Scale down first when the multiplication would overflow; the ratio is
+    // unaffected beyond rounding
+    let bps = match moved.checked_mul(BPS) {
+        Some(scaled) => scaled / price_before,
+        None => moved / (price_before / BPS).max(1),
+    };
+    bps.min(MAX_VOLATILITY_BPS as u128) as u64
+}
+
+/// Fee numerator for the current volatility, over `fee_denominator`.
+pub fn dynamic_fee_numerator(
+    base_fee_numerator: u64,
+    min_fee_numerator: u64,
+    max_fee_numerator: u64,
+    fee_denominator: u64,
+    volatility: u64,
+) -> u64 {
+    let volatility_fee =
+        volatility as u128 * fee_denominator as u128 / (BPS * VOLATILITY_FEE_DIVISOR as u128);
+    let fee = (base_fee_numerator as u128).saturating_add(volatility_fee);
+    fee.clamp(min_fee_numerator as u128, max_fee_numerator as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volatility_halves_every_half_life() {
+        assert_eq!(decay_volatility(1_000, 0), 1_000);
+        assert_eq!(decay_volatility(1_000, VOLATILITY_HALF_LIFE), 500);
+        assert_eq!(decay_volatility(1_000, 2 * VOLATILITY_HALF_LIFE), 250);
+        assert_eq!(decay_volatility(1_000, VOLATILITY_HALF_LIFE / 2), 750);
+        assert_eq!(decay_volatility(u64::MAX, 100 * VOLATILITY_HALF_LIFE), 0);
+    }
+
+    #[test]
+    fn price_move_is_relative_and_capped() {
+        assert_eq!(price_move_bps(100 << 64, 101 << 64), 100);
+        assert_eq!(price_move_bps(100 << 64, 99 << 64), 100);
+        assert_eq!(price_move_bps(1, u128::MAX), MAX_VOLATILITY_BPS);
+        assert_eq!(price_move_bps(u128::MAX / 2, u128::MAX / 4), 5_000);
+    }
+
+    #[test]
+    fn fee_rises_with_volatility_within_bounds() {
+        // 30 bp base, bounded to 10-100 bp
+        let fee = |volatility| dynamic_fee_numerator(30, 10, 100, 10_000, volatility);
+        assert_eq!(fee(0), 30);
+        // 200 bp of recent movement adds 20 bp
+        assert_eq!(fee(200), 50);
+        assert_eq!(fee(MAX_VOLATILITY_BPS), 100);
+        assert_eq!(dynamic_fee_numerator(5, 10, 100, 10_000, 0), 10);
+    }
+}
@@ -1,17 +1,61 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::{system_program, Discriminator};
+use anchor_spl::token::spl_token::native_mint;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, TokenInterface, TransferChecked};
 
-declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
+mod concentrated;
+mod dynamic_fee;
+mod math;
+mod oracle;
+mod stable_math;
+mod tick_math;
+mod weighted;
+mod weighted_math;
+
+use concentrated::*;
+use dynamic_fee::*;
+use math::*;
+pub use math::integer_sqrt;
+pub use oracle::*;
+use stable_math::*;
+use tick_math::*;
+use transfer_fee::{amount_after_fee, amount_before_fee};
+use weighted::*;
+use weighted_math::*;
+
+declare_id!("FmYo3DdATZoFj5tPFSnoYWDp223fhDCP2EJW4j4Amxrb");
+
+/// Decimals of every pool's LP mint
+pub const LP_DECIMALS: u8 = 9;
 
 #[program]
 pub mod liquidity_pool {
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
         fee_numerator: u64,
         fee_denominator: u64,
+        protocol_fee_numerator: u64,
+        protocol_fee_denominator: u64,
+        curve_type: CurveType,
+        amp: u64,
+        dynamic_fee: Option<DynamicFeeBounds>,
     ) -> Result<()> {
+        if curve_type == CurveType::StableSwap {
+            require!((MIN_AMP..=MAX_AMP).contains(&amp), ErrorCode::InvalidAmp);
+            // Balances are compared 1:1, so both sides must use the same units
+            require!(
+                ctx.accounts.token_a_mint.decimals == ctx.accounts.token_b_mint.decimals,
+                ErrorCode::UnsupportedCurve
+            );
+        }
+        
         let pool = &mut ctx.accounts.pool;
         
         // Initialize pool state
@@ -23,80 +67,127 @@ pub mod liquidity_pool {
         pool.fee_numerator = fee_numerator;
         pool.fee_denominator = fee_denominator;
         pool.authority = ctx.accounts.authority.key();
+        pool.bump = *ctx.bumps.get("pool_authority").unwrap();
+        pool.protocol_fee_numerator = protocol_fee_numerator;
+        pool.protocol_fee_denominator = protocol_fee_denominator;
+        pool.protocol_fee_recipient = ctx.accounts.authority.key();
+        pool.curve_type = curve_type;
+        pool.amp_initial = amp;
+        pool.amp_target = amp;
+        pool.dynamic_fee = dynamic_fee;
+        // Start the price clock now, so the first reading does not
+        // integrate from the Unix epoch
+        pool.last_observation = Observation {
+            timestamp: Clock::get()?.unix_timestamp,
+            ..Default::default()
+        };
         
         // Validate fee
         require!(
             fee_denominator > 0 && fee_numerator < fee_denominator,
             ErrorCode::InvalidFee
         );
+        require!(
+            protocol_fee_denominator > 0 && protocol_fee_numerator <= protocol_fee_denominator,
+            ErrorCode::InvalidFee
+        );
+        if let Some(bounds) = dynamic_fee {
+            require!(
+                bounds.min_fee_numerator <= fee_numerator
+                    && fee_numerator <= bounds.max_fee_numerator
+                    && bounds.max_fee_numerator < fee_denominator,
+                ErrorCode::InvalidFee
+            );
+        }
 
         Ok(())
     }
 
     pub fn add_liquidity(
         ctx: Context<AddLiquidity>, 
-        amount_a: u64, 
-        amount_b: u64, 
+        max_amount_a: u64, 
+        max_amount_b: u64, 
         min_lp_tokens: u64
     ) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
+        // Current balances
+        let reserve_a = ctx.accounts.token_a_account.amount;
+        let reserve_b = ctx.accounts.token_b_account.amount;
+        record_observation(&mut ctx.accounts.pool, ctx.accounts.oracle.as_mut(), reserve_a, reserve_b)?;
+        
+        let pool = &ctx.accounts.pool;
         let token_a_account = &ctx.accounts.token_a_account;
         let token_b_account = &ctx.accounts.token_b_account;
-        
-        // Current balances
-        let reserve_a = token_a_account.amount;
-        let reserve_b = token_b_account.amount;
         let total_supply = ctx.accounts.lp_mint.supply;
         
+        // Only take the pair that matches the current pool ratio; whatever is
+        // left of the larger side stays with the user
+        let (amount_a, amount_b) = if total_supply == 0 {
+            (max_amount_a, max_amount_b)
+        } else {
+            optimal_deposit(max_amount_a, max_amount_b, reserve_a, reserve_b)
+                .ok_or(ErrorCode::MathOverflow)?
+        };
+        
+        // Token-2022 mints may withhold a transfer fee; only what reaches the
+        // vaults counts towards the deposit
+        let received_a = amount_after_fee(&ctx.accounts.token_a_mint.to_account_info(), amount_a)?;
+        let received_b = amount_after_fee(&ctx.accounts.token_b_mint.to_account_info(), amount_b)?;
+        
         let lp_tokens_to_mint: u64;
+        let mut lp_tokens_to_lock: u64 = 0;
         
-        // If first deposit, mint LP tokens proportional to sqrt(amount_a * amount_b)
+        // If first deposit, mint LP tokens proportional to sqrt(amount_a * amount_b),
+        // permanently locking MINIMUM_LIQUIDITY of them in the pool
         if total_supply == 0 {
-            // Simple calculation for first deposit
-            lp_tokens_to_mint = (amount_a as u128).checked_mul(amount_b as u128)
-                .unwrap()
-                .checked_sqrt()
-                .unwrap() as u64;
+            let (user_lp, locked_lp) = initial_lp_tokens(received_a, received_b)
+                .ok_or(ErrorCode::InitialLiquidityTooLow)?;
+            lp_tokens_to_mint = user_lp;
+            lp_tokens_to_lock = locked_lp;
         } else {
             // Calculate proportional LP tokens
-            let deposit_percentage = std::cmp::min(
-                (amount_a as u128).checked_mul(total_supply as u128).unwrap()
-                    .checked_div(reserve_a as u128).unwrap(),
-                (amount_b as u128).checked_mul(total_supply as u128).unwrap()
-                    .checked_div(reserve_b as u128).unwrap()
-            ) as u64;
-            
-            lp_tokens_to_mint = deposit_percentage;
+            lp_tokens_to_mint = proportional_lp_tokens(
+                received_a,
+                received_b,
+                reserve_a,
+                reserve_b,
+                total_supply,
+            )
+            .ok_or(ErrorCode::MathOverflow)?;
         }
         
+        require!(lp_tokens_to_mint > 0, ErrorCode::InsufficientLiquidity);
         require!(
             lp_tokens_to_mint >= min_lp_tokens,
             ErrorCode::SlippageExceeded
         );
         
         // Transfer tokens from user to pool
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_a_program.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.user_token_a.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
                     to: token_a_account.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
             amount_a,
+            ctx.accounts.token_a_mint.decimals,
         )?;
         
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_b_program.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.user_token_b.to_account_info(),
+                    mint: ctx.accounts.token_b_mint.to_account_info(),
                     to: token_b_account.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
             amount_b,
+            ctx.accounts.token_b_mint.decimals,
         )?;
         
         // Mint LP tokens to user
@@ -118,6 +209,107 @@ pub mod liquidity_pool {
             lp_tokens_to_mint,
         )?;
         
+        if lp_tokens_to_lock > 0 {
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::MintTo {
+                        mint: ctx.accounts.lp_mint.to_account_info(),
+                        to: ctx.accounts.locked_lp_token.to_account_info(),
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    &[pool_authority_seeds],
+                ),
+                lp_tokens_to_lock,
+            )?;
+        }
+        
+        emit!(LiquidityAdded {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_a: received_a,
+            amount_b: received_b,
+            lp_tokens: lp_tokens_to_mint,
+        });
+        
+        Ok(())
+    }
+
+    /// `add_liquidity` for a pool with a native-mint side, paid in SOL. The
+    /// SOL side's maximum is wrapped into a temporary wSOL account, and
+    /// whatever the deposit leaves unspent comes back as lamports when the
+    /// account is closed.
+    pub fn add_liquidity_native<'info>(
+        ctx: Context<'_, '_, '_, 'info, AddLiquidityNative<'info>>,
+        max_amount_a: u64,
+        max_amount_b: u64,
+        min_lp_tokens: u64,
+    ) -> Result<()> {
+        let sol_is_a = ctx.accounts.pool.token_a_mint == native_mint::ID;
+        let lamports = if sol_is_a { max_amount_a } else { max_amount_b };
+        
+        // Wrap the SOL side
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.wsol_account.to_account_info(),
+                },
+            ),
+            lamports,
+        )?;
+        token::sync_native(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::SyncNative {
+                account: ctx.accounts.wsol_account.to_account_info(),
+            },
+        ))?;
+        
+        let wsol_account = InterfaceAccount::try_from(&ctx.accounts.wsol_account.to_account_info())?;
+        let (user_token_a, user_token_b) = if sol_is_a {
+            (wsol_account, ctx.accounts.user_token.clone())
+        } else {
+            (ctx.accounts.user_token.clone(), wsol_account)
+        };
+        let mut accounts = AddLiquidity {
+            pool: ctx.accounts.pool.clone(),
+            token_a_account: ctx.accounts.token_a_account.clone(),
+            token_b_account: ctx.accounts.token_b_account.clone(),
+            lp_mint: ctx.accounts.lp_mint.clone(),
+            user_token_a,
+            user_token_b,
+            user_lp_token: ctx.accounts.user_lp_token.clone(),
+            locked_lp_token: ctx.accounts.locked_lp_token.clone(),
+            oracle: ctx.accounts.oracle.clone(),
+            pool_authority: ctx.accounts.pool_authority.clone(),
+            user: ctx.accounts.user.clone(),
+            token_a_mint: ctx.accounts.token_a_mint.clone(),
+            token_b_mint: ctx.accounts.token_b_mint.clone(),
+            token_a_program: ctx.accounts.token_a_program.clone(),
+            token_b_program: ctx.accounts.token_b_program.clone(),
+            token_program: ctx.accounts.token_program.clone(),
+        };
+        add_liquidity(
+            Context::new(ctx.program_id, &mut accounts, ctx.remaining_accounts, ctx.bumps.clone()),
+            max_amount_a,
+            max_amount_b,
+            min_lp_tokens,
+        )?;
+        // Keep the updated pool and oracle so they are written back on exit
+        ctx.accounts.pool = accounts.pool;
+        ctx.accounts.oracle = accounts.oracle;
+        
+        // Return unspent SOL and the account's rent
+        token::close_account(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ))?;
+        
         Ok(())
     }
 
@@ -127,25 +319,34 @@ pub mod liquidity_pool {
         min_amount_a: u64,
         min_amount_b: u64,
     ) -> Result<()> {
+        record_observation(
+            &mut ctx.accounts.pool,
+            ctx.accounts.oracle.as_mut(),
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+        )?;
         let pool = &ctx.accounts.pool;
         let total_supply = ctx.accounts.lp_mint.supply;
         
         // Calculate token amounts to withdraw based on LP token proportion
-        let token_a_amount = (lp_amount as u128)
-            .checked_mul(ctx.accounts.token_a_account.amount as u128)
-            .unwrap()
-            .checked_div(total_supply as u128)
-            .unwrap() as u64;
+        let token_a_amount = withdraw_amount(
+            lp_amount,
+            ctx.accounts.token_a_account.amount,
+            total_supply,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
             
-        let token_b_amount = (lp_amount as u128)
-            .checked_mul(ctx.accounts.token_b_account.amount as u128)
-            .unwrap()
-            .checked_div(total_supply as u128)
-            .unwrap() as u64;
+        let token_b_amount = withdraw_amount(
+            lp_amount,
+            ctx.accounts.token_b_account.amount,
+            total_supply,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
         
-        // Check slippage tolerance
+        // Check slippage tolerance against what reaches the user
         require!(
-            token_a_amount >= min_amount_a && token_b_amount >= min_amount_b,
+            amount_after_fee(&ctx.accounts.token_a_mint.to_account_info(), token_a_amount)? >= min_amount_a
+                && amount_after_fee(&ctx.accounts.token_b_mint.to_account_info(), token_b_amount)? >= min_amount_b,
             ErrorCode::SlippageExceeded
         );
         
@@ -168,157 +369,2699 @@ pub mod liquidity_pool {
             &[pool.bump],
         ];
         
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_a_program.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.token_a_account.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
                     to: ctx.accounts.user_token_a.to_account_info(),
                     authority: ctx.accounts.pool_authority.to_account_info(),
                 },
                 &[pool_authority_seeds],
             ),
             token_a_amount,
+            ctx.accounts.token_a_mint.decimals,
         )?;
         
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_b_program.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.token_b_account.to_account_info(),
+                    mint: ctx.accounts.token_b_mint.to_account_info(),
                     to: ctx.accounts.user_token_b.to_account_info(),
                     authority: ctx.accounts.pool_authority.to_account_info(),
                 },
                 &[pool_authority_seeds],
             ),
             token_b_amount,
+            ctx.accounts.token_b_mint.decimals,
         )?;
         
         Ok(())
     }
-}
-
-#[derive(Accounts)]
-#[instruction(fee_numerator: u64, fee_denominator: u64)]
-pub struct InitializePool<'info> {
-    #[account(init, payer = authority, space = 8 + LiquidityPool::LEN)]
-    pub pool: Account<'info, LiquidityPool>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
-    #[account(
-        constraint = token_a_account.mint == token_a_mint.key(),
-        constraint = token_a_account.owner == pool_authority.key()
-    )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    #[account(
-        constraint = token_b_account.mint == token_b_mint.key(),
-        constraint = token_b_account.owner == pool_authority.key()
-    )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    pub lp_mint: Account<'info, Mint>,
-    /// CHECK: This is the PDA that will manage token accounts
-    #[account(seeds = [pool.key().as_ref()], bump)]
-    pub pool_authority: UncheckedAccount<'info>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-}
-
-#[derive(Accounts)]
-pub struct AddLiquidity<'info> {
-    #[account(mut)]
-    pub pool: Account<'info, LiquidityPool>,
-    #[account(
-        mut,
-        constraint = token_a_account.mint == pool.token_a_mint,
-        constraint = token_a_account.owner == pool_authority.key()
-    )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = token_b_account.mint == pool.token_b_mint,
-        constraint = token_b_account.owner == pool_authority.key()
-    )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = lp_mint.key() == pool.lp_mint
-    )]
-    pub lp_mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub user_token_a: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_token_b: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_lp_token: Account<'info, TokenAccount>,
-    /// CHECK: This is the PDA that will manage token accounts
-    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
-    pub pool_authority: UncheckedAccount<'info>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-}
-
-#[derive(Accounts)]
-pub struct RemoveLiquidity<'info> {
-    #[account(mut)]
-    pub pool: Account<'info, LiquidityPool>,
-    #[account(
-        mut,
-        constraint = token_a_account.mint == pool.token_a_mint,
-        constraint = token_a_account.owner == pool_authority.key()
-    )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = token_b_account.mint == pool.token_b_mint,
-        constraint = token_b_account.owner == pool_authority.key()
-    )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = lp_mint.key() == pool.lp_mint
-    )]
-    pub lp_mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub user_token_a: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_token_b: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_lp_token: Account<'info, TokenAccount>,
-    /// CHECK: This is the PDA that will manage token accounts
-    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
-    pub pool_authority: UncheckedAccount<'info>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-}
 
-#[account]
-pub struct LiquidityPool {
-    pub token_a_mint: Pubkey,
-    pub token_b_mint: Pubkey,
-    pub token_a_account: Pubkey,
-    pub token_b_account: Pubkey,
-    pub lp_mint: Pubkey,
-    pub fee_numerator: u64,
-    pub fee_denominator: u64,
-    pub authority: Pubkey,
-    pub bump: u8,
-}
+    pub fn add_liquidity_single_sided(
+        ctx: Context<AddLiquiditySingleSided>,
+        token_in: Pubkey,
+        amount_in: u64,
+        min_lp_tokens: u64,
+    ) -> Result<()> {
+        record_observation(
+            &mut ctx.accounts.pool,
+            ctx.accounts.oracle.as_mut(),
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+        )?;
+        let pool = &ctx.accounts.pool;
+        let total_supply = ctx.accounts.lp_mint.supply;
+        require!(
+            pool.curve_type == CurveType::ConstantProduct,
+            ErrorCode::UnsupportedCurve
+        );
+        require!(total_supply > 0, ErrorCode::InsufficientLiquidity);
+        
+        let (reserve_in, reserve_out, vault_in, mint_in, token_program_in) = if token_in == pool.token_a_mint {
+            (
+                ctx.accounts.token_a_account.amount,
+                ctx.accounts.token_b_account.amount,
+                &ctx.accounts.token_a_account,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_a_program,
+            )
+        } else if token_in == pool.token_b_mint {
+            (
+                ctx.accounts.token_b_account.amount,
+                ctx.accounts.token_a_account.amount,
+                &ctx.accounts.token_b_account,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_b_program,
+            )
+        } else {
+            return err!(ErrorCode::InvalidMint);
+        };
+        let received_in = amount_after_fee(&mint_in.to_account_info(), amount_in)?;
+        
+        // Virtually swap part of the input for the other token at the pool's
+        // fee, then deposit both halves at the post-swap ratio. The swap output
+        // never leaves the vault, so only `amount_in` moves.
+        let fee_numerator = pool.swap_fee_numerator(Clock::get()?.unix_timestamp);
+        let swap_amount = optimal_swap_amount(received_in, reserve_in, fee_numerator, pool.fee_denominator)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let swap_out = swap_amount_out(swap_amount, reserve_in, reserve_out, fee_numerator, pool.fee_denominator)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let lp_tokens_to_mint = proportional_lp_tokens(
+            received_in.checked_sub(swap_amount).ok_or(ErrorCode::MathOverflow)?,
+            swap_out,
+            reserve_in.checked_add(swap_amount).ok_or(ErrorCode::MathOverflow)?,
+            reserve_out.checked_sub(swap_out).ok_or(ErrorCode::MathOverflow)?,
+            total_supply,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        
+        require!(lp_tokens_to_mint > 0, ErrorCode::InsufficientLiquidity);
+        require!(
+            lp_tokens_to_mint >= min_lp_tokens,
+            ErrorCode::SlippageExceeded
+        );
+        
+        token_interface::transfer_checked(
+            CpiContext::new(
+                token_program_in.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_in.to_account_info(),
+                    mint: mint_in.to_account_info(),
+                    to: vault_in.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+            mint_in.decimals,
+        )?;
+        
+        let pool_authority_seeds = &[
+            pool.to_account_info().key.as_ref(),
+            &[pool.bump],
+        ];
+        
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            lp_tokens_to_mint,
+        )?;
+        
+        let (amount_a, amount_b) = if token_in == pool.token_a_mint {
+            (received_in, 0)
+        } else {
+            (0, received_in)
+        };
+        emit!(LiquidityAdded {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_a,
+            amount_b,
+            lp_tokens: lp_tokens_to_mint,
+        });
+        
+        Ok(())
+    }
 
-impl LiquidityPool {
-    pub const LEN: usize = 32 + 32 + 32 + 32 + 32 + 8 + 8 + 32 + 1;
-}
+    pub fn remove_liquidity_single_sided(
+        ctx: Context<RemoveLiquiditySingleSided>,
+        token_out: Pubkey,
+        lp_amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        record_observation(
+            &mut ctx.accounts.pool,
+            ctx.accounts.oracle.as_mut(),
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+        )?;
+        let pool = &ctx.accounts.pool;
+        let total_supply = ctx.accounts.lp_mint.supply;
+        require!(
+            pool.curve_type == CurveType::ConstantProduct,
+            ErrorCode::UnsupportedCurve
+        );
+        
+        let (reserve_out, reserve_other, vault_out, mint_out, token_program_out) = if token_out == pool.token_a_mint {
+            (
+                ctx.accounts.token_a_account.amount,
+                ctx.accounts.token_b_account.amount,
+                &ctx.accounts.token_a_account,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_a_program,
+            )
+        } else if token_out == pool.token_b_mint {
+            (
+                ctx.accounts.token_b_account.amount,
+                ctx.accounts.token_a_account.amount,
+                &ctx.accounts.token_b_account,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_b_program,
+            )
+        } else {
+            return err!(ErrorCode::InvalidMint);
+        };
+        
+        // Withdraw proportionally, then virtually swap the other side back into
+        // `token_out` against the post-withdrawal reserves
+        let withdrawn_out = withdraw_amount(lp_amount, reserve_out, total_supply)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let withdrawn_other = withdraw_amount(lp_amount, reserve_other, total_supply)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let swap_out = swap_amount_out(
+            withdrawn_other,
+            reserve_other
+                .checked_sub(withdrawn_other)
+                .ok_or(ErrorCode::MathOverflow)?,
+            reserve_out
+                .checked_sub(withdrawn_out)
+                .ok_or(ErrorCode::MathOverflow)?,
+            pool.swap_fee_numerator(Clock::get()?.unix_timestamp),
+            pool.fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        let amount_out = withdrawn_out
+            .checked_add(swap_out)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        require!(
+            amount_after_fee(&mint_out.to_account_info(), amount_out)? >= min_amount_out,
+            ErrorCode::SlippageExceeded
+        );
+        
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+        
+        let pool_authority_seeds = &[
+            pool.to_account_info().key.as_ref(),
+            &[pool.bump],
+        ];
+        
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program_out.to_account_info(),
+                TransferChecked {
+                    from: vault_out.to_account_info(),
+                    mint: mint_out.to_account_info(),
+                    to: ctx.accounts.user_token_out.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            amount_out,
+            mint_out.decimals,
+        )?;
+        
+        Ok(())
+    }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Invalid fee configuration")]
-    InvalidFee,
-    #[msg("Slippage tolerance exceeded")]
-    SlippageExceeded,
-    #[msg("Insufficient liquidity")]
-    InsufficientLiquidity,
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, minimum_amount_out: u64) -> Result<()> {
+        record_observation(
+            &mut ctx.accounts.pool,
+            ctx.accounts.oracle.as_mut(),
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+        )?;
+        let total_supply = ctx.accounts.lp_mint.supply;
+        let a_to_b = ctx.accounts.user_token_in.mint == ctx.accounts.pool.token_a_mint;
+        let (vault_in, vault_out, mint_in, mint_out, token_program_in, token_program_out) = if a_to_b {
+            (
+                &ctx.accounts.token_a_account,
+                &ctx.accounts.token_b_account,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_a_program,
+                &ctx.accounts.token_b_program,
+            )
+        } else {
+            (
+                &ctx.accounts.token_b_account,
+                &ctx.accounts.token_a_account,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_b_program,
+                &ctx.accounts.token_a_program,
+            )
+        };
+        require!(
+            ctx.accounts.user_token_out.mint == vault_out.mint,
+            ErrorCode::InvalidMint
+        );
+        require!(total_supply > 0, ErrorCode::InsufficientLiquidity);
+        
+        // Price the swap on what the vault actually receives
+        let received_in = amount_after_fee(&mint_in.to_account_info(), amount_in)?;
+        let pool = &ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        let fee_numerator = pool.swap_fee_numerator(now);
+        let amount_out = match pool.curve_type {
+            CurveType::ConstantProduct => swap_amount_out(
+                received_in,
+                vault_in.amount,
+                vault_out.amount,
+                fee_numerator,
+                pool.fee_denominator,
+            ),
+            CurveType::StableSwap => stable_swap_amount_out(
+                pool.amp(now),
+                received_in,
+                vault_in.amount,
+                vault_out.amount,
+                fee_numerator,
+                pool.fee_denominator,
+            ),
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+        
+        require!(amount_out > 0, ErrorCode::InsufficientLiquidity);
+        let amount_received = amount_after_fee(&mint_out.to_account_info(), amount_out)?;
+        require!(
+            amount_received >= minimum_amount_out,
+            ErrorCode::SlippageExceeded
+        );
+        
+        // The whole fee stays in the vault for LPs, minus the protocol's share
+        // which is skimmed from the vault into the recipient's token account
+        let (lp_fee, protocol_fee) = split_swap_fee(
+            received_in,
+            fee_numerator,
+            pool.fee_denominator,
+            pool.protocol_fee_numerator,
+            pool.protocol_fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        
+        token_interface::transfer_checked(
+            CpiContext::new(
+                token_program_in.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_in.to_account_info(),
+                    mint: mint_in.to_account_info(),
+                    to: vault_in.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+            mint_in.decimals,
+        )?;
+        
+        let pool_authority_seeds = &[
+            pool.to_account_info().key.as_ref(),
+            &[pool.bump],
+        ];
+        
+        if protocol_fee > 0 {
+            let protocol_fee_account = ctx
+                .accounts
+                .protocol_fee_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingProtocolFeeAccount)?;
+            require!(
+                protocol_fee_account.owner == pool.protocol_fee_recipient
+                    && protocol_fee_account.mint == vault_in.mint,
+                ErrorCode::MissingProtocolFeeAccount
+            );
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    token_program_in.to_account_info(),
+                    TransferChecked {
+                        from: vault_in.to_account_info(),
+                        mint: mint_in.to_account_info(),
+                        to: protocol_fee_account.to_account_info(),
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    &[pool_authority_seeds],
+                ),
+                protocol_fee,
+                mint_in.decimals,
+            )?;
+        }
+        
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program_out.to_account_info(),
+                TransferChecked {
+                    from: vault_out.to_account_info(),
+                    mint: mint_out.to_account_info(),
+                    to: ctx.accounts.user_token_out.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            amount_out,
+            mint_out.decimals,
+        )?;
+        
+        let fee_growth = fee_growth_delta(lp_fee, total_supply).ok_or(ErrorCode::MathOverflow)?;
+        let reserves_before = (vault_in.amount, vault_out.amount);
+        let reserves_after = (
+            vault_in.amount + (received_in - protocol_fee),
+            vault_out.amount - amount_out,
+        );
+        let pool_key = ctx.accounts.pool.key();
+        let pool = &mut ctx.accounts.pool;
+        // Price of the input token, which is all the volatility measure needs
+        if let (Some(price_before), Some(price_after)) = (
+            spot_price(reserves_before.0, reserves_before.1),
+            spot_price(reserves_after.0, reserves_after.1),
+        ) {
+            pool.accrue_volatility(price_before, price_after, now);
+        }
+        if a_to_b {
+            pool.fee_growth_a = pool.fee_growth_a.wrapping_add(fee_growth);
+        } else {
+            pool.fee_growth_b = pool.fee_growth_b.wrapping_add(fee_growth);
+        }
+        
+        emit!(Swapped {
+            pool: pool_key,
+            user: ctx.accounts.user.key(),
+            a_to_b,
+            amount_in,
+            amount_out: amount_received,
+            lp_fee,
+            protocol_fee,
+        });
+        
+        Ok(())
+    }
+
+    pub fn ramp_amp(ctx: Context<RampAmp>, target_amp: u64, ramp_end: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            pool.curve_type == CurveType::StableSwap,
+            ErrorCode::UnsupportedCurve
+        );
+        require!(
+            ramp_end >= now + MIN_RAMP_DURATION,
+            ErrorCode::InvalidAmpRamp
+        );
+        
+        let amp_current = pool.amp(now);
+        require!(
+            (MIN_AMP..=MAX_AMP).contains(&target_amp)
+                && target_amp <= amp_current * MAX_AMP_CHANGE
+                && target_amp * MAX_AMP_CHANGE >= amp_current,
+            ErrorCode::InvalidAmp
+        );
+        
+        pool.amp_initial = amp_current;
+        pool.amp_target = target_amp;
+        pool.amp_ramp_start = now;
+        pool.amp_ramp_end = ramp_end;
+        
+        Ok(())
+    }
+
+    pub fn set_fees(
+        ctx: Context<UpdatePool>,
+        fee_numerator: u64,
+        fee_denominator: u64,
+        protocol_fee_numerator: u64,
+        protocol_fee_denominator: u64,
+    ) -> Result<()> {
+        require!(
+            fee_denominator > 0 && fee_numerator < fee_denominator,
+            ErrorCode::InvalidFee
+        );
+        require!(
+            protocol_fee_denominator > 0 && protocol_fee_numerator <= protocol_fee_denominator,
+            ErrorCode::InvalidFee
+        );
+        let pool = &mut ctx.accounts.pool;
+        if let Some(bounds) = pool.dynamic_fee {
+            require!(
+                bounds.min_fee_numerator <= fee_numerator
+                    && fee_numerator <= bounds.max_fee_numerator
+                    && bounds.max_fee_numerator < fee_denominator,
+                ErrorCode::InvalidFee
+            );
+        }
+        
+        pool.fee_numerator = fee_numerator;
+        pool.fee_denominator = fee_denominator;
+        pool.protocol_fee_numerator = protocol_fee_numerator;
+        pool.protocol_fee_denominator = protocol_fee_denominator;
+        
+        Ok(())
+    }
+
+    /// First step of an authority transfer; takes effect once
+    /// `new_authority` calls `accept_authority`.
+    pub fn set_authority(ctx: Context<UpdatePool>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.pool.pending_authority = new_authority;
+        
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.authority = pool.pending_authority;
+        pool.pending_authority = Pubkey::default();
+        
+        Ok(())
+    }
+
+    /// Stops swaps and deposits. Withdrawals stay open so LPs can always exit.
+    pub fn pause(ctx: Context<UpdatePool>) -> Result<()> {
+        ctx.accounts.pool.paused = true;
+        
+        Ok(())
+    }
+
+    pub fn unpause(ctx: Context<UpdatePool>) -> Result<()> {
+        ctx.accounts.pool.paused = false;
+        
+        Ok(())
+    }
+
+    /// `pause` and `unpause` for a concentrated pool. Positions can always
+    /// be decreased and their fees collected.
+    pub fn set_concentrated_paused(ctx: Context<UpdateConcentratedPool>, paused: bool) -> Result<()> {
+        ctx.accounts.pool.paused = paused;
+        
+        Ok(())
+    }
+
+    /// `pause` and `unpause` for a weighted pool. Exits stay open.
+    pub fn set_weighted_paused(ctx: Context<UpdateWeightedPool>, paused: bool) -> Result<()> {
+        ctx.accounts.pool.paused = paused;
+        
+        Ok(())
+    }
+
+    pub fn set_protocol_fee_recipient(ctx: Context<UpdatePool>, recipient: Pubkey) -> Result<()> {
+        ctx.accounts.pool.protocol_fee_recipient = recipient;
+        
+        Ok(())
+    }
+
+    /// Lends vault tokens for the rest of the transaction. A `flash_repay`
+    /// for this pool must appear later in the same transaction; until it
+    /// runs, every other instruction against the pool is rejected.
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount_a: u64, amount_b: u64) -> Result<()> {
+        require!(amount_a > 0 || amount_b > 0, ErrorCode::InsufficientLiquidity);
+        require_flash_repay(&ctx.accounts.instructions, &ctx.accounts.pool.key())?;
+        
+        let pool = &ctx.accounts.pool;
+        // Fees are charged on what leaves the vault; any transfer fee withheld
+        // on the way out is the borrower's cost
+        let fee_a = math::mul_div_ceil(amount_a, pool.fee_numerator, pool.fee_denominator)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let fee_b = math::mul_div_ceil(amount_b, pool.fee_numerator, pool.fee_denominator)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let pool_authority_seeds = &[
+            pool.to_account_info().key.as_ref(),
+            &[pool.bump],
+        ];
+        for (vault, user_token, mint, token_program, amount) in [
+            (
+                &ctx.accounts.token_a_account,
+                &ctx.accounts.user_token_a,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_a_program,
+                amount_a,
+            ),
+            (
+                &ctx.accounts.token_b_account,
+                &ctx.accounts.user_token_b,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_b_program,
+                amount_b,
+            ),
+        ] {
+            if amount > 0 {
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        token_program.to_account_info(),
+                        TransferChecked {
+                            from: vault.to_account_info(),
+                            mint: mint.to_account_info(),
+                            to: user_token.to_account_info(),
+                            authority: ctx.accounts.pool_authority.to_account_info(),
+                        },
+                        &[pool_authority_seeds],
+                    ),
+                    amount,
+                    mint.decimals,
+                )?;
+            }
+        }
+        
+        let pool = &mut ctx.accounts.pool;
+        pool.flash_loan_a = amount_a;
+        pool.flash_loan_b = amount_b;
+        pool.flash_fee_a = fee_a;
+        pool.flash_fee_b = fee_b;
+        
+        Ok(())
+    }
+
+    /// Returns an outstanding flash loan plus its fee. The fee stays in the
+    /// vaults for LPs.
+    pub fn flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        for (vault, user_token, mint, token_program, owed) in [
+            (
+                &ctx.accounts.token_a_account,
+                &ctx.accounts.user_token_a,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_a_program,
+                pool.flash_loan_a + pool.flash_fee_a,
+            ),
+            (
+                &ctx.accounts.token_b_account,
+                &ctx.accounts.user_token_b,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_b_program,
+                pool.flash_loan_b + pool.flash_fee_b,
+            ),
+        ] {
+            if owed > 0 {
+                // Gross up so the vault is made whole after any transfer fee
+                let amount = amount_before_fee(&mint.to_account_info(), owed)?;
+                token_interface::transfer_checked(
+                    CpiContext::new(
+                        token_program.to_account_info(),
+                        TransferChecked {
+                            from: user_token.to_account_info(),
+                            mint: mint.to_account_info(),
+                            to: vault.to_account_info(),
+                            authority: ctx.accounts.user.to_account_info(),
+                        },
+                    ),
+                    amount,
+                    mint.decimals,
+                )?;
+            }
+        }
+        
+        let total_supply = ctx.accounts.lp_mint.supply;
+        let fee_growth_a = fee_growth_delta(pool.flash_fee_a, total_supply).ok_or(ErrorCode::MathOverflow)?;
+        let fee_growth_b = fee_growth_delta(pool.flash_fee_b, total_supply).ok_or(ErrorCode::MathOverflow)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.fee_growth_a = pool.fee_growth_a.wrapping_add(fee_growth_a);
+        pool.fee_growth_b = pool.fee_growth_b.wrapping_add(fee_growth_b);
+        pool.flash_loan_a = 0;
+        pool.flash_loan_b = 0;
+        pool.flash_fee_a = 0;
+        pool.flash_fee_b = 0;
+        
+        Ok(())
+    }
+
+    pub fn initialize_oracle(ctx: Context<InitializeOracle>) -> Result<()> {
+        let oracle = &mut ctx.accounts.oracle;
+        oracle.pool = ctx.accounts.pool.key();
+        oracle.ring = ObservationRing::new();
+        oracle.ring.write(ctx.accounts.pool.last_observation);
+        
+        Ok(())
+    }
+
+    /// Cumulative prices as of `seconds_ago` seconds before now, for each
+    /// entry. Callers derive a TWAP by differencing two readings with `twap`.
+    pub fn observe(ctx: Context<Observe>, seconds_ago: Vec<u32>) -> Result<Vec<Observation>> {
+        let now = Clock::get()?.unix_timestamp;
+        let latest = ctx.accounts.pool.last_observation.accumulate(
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+            now,
+        );
+        
+        seconds_ago
+            .iter()
+            .map(|seconds| {
+                ctx.accounts
+                    .oracle
+                    .ring
+                    .observe(&latest, now - *seconds as i64)
+                    .ok_or_else(|| error!(ErrorCode::ObservationTooOld))
+            })
+            .collect()
+    }
+
+    /// Creates a concentrated pool over two legacy SPL mints; Token-2022
+    /// mints are rejected.
+    pub fn initialize_concentrated_pool(
+        ctx: Context<InitializeConcentratedPool>,
+        fee_numerator: u64,
+        fee_denominator: u64,
+        tick_spacing: u16,
+        initial_sqrt_price: u128,
+    ) -> Result<()> {
+        require!(
+            fee_denominator > 0 && fee_numerator < fee_denominator,
+            ErrorCode::InvalidFee
+        );
+        require!(tick_spacing > 0, ErrorCode::InvalidTickSpacing);
+        let tick_current = tick_from_sqrt_price(initial_sqrt_price)
+            .ok_or(ErrorCode::InvalidSqrtPriceLimit)?;
+        
+        let pool = &mut ctx.accounts.pool;
+        pool.token_a_mint = ctx.accounts.token_a_mint.key();
+        pool.token_b_mint = ctx.accounts.token_b_mint.key();
+        pool.token_a_account = ctx.accounts.token_a_account.key();
+        pool.token_b_account = ctx.accounts.token_b_account.key();
+        pool.fee_numerator = fee_numerator;
+        pool.fee_denominator = fee_denominator;
+        pool.authority = ctx.accounts.authority.key();
+        pool.bump = *ctx.bumps.get("pool_authority").unwrap();
+        pool.tick_spacing = tick_spacing;
+        pool.sqrt_price = initial_sqrt_price;
+        pool.tick_current = tick_current;
+        
+        Ok(())
+    }
+
+    pub fn initialize_tick_array(ctx: Context<InitializeTickArray>, start_tick_index: i32) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(
+            start_tick_index == pool.tick_array_start(start_tick_index)
+                && start_tick_index + pool.ticks_per_array() > MIN_TICK
+                && start_tick_index <= MAX_TICK,
+            ErrorCode::InvalidTickIndex
+        );
+        
+        let tick_array = &mut ctx.accounts.tick_array;
+        tick_array.pool = pool.key();
+        tick_array.start_tick_index = start_tick_index;
+        tick_array.ticks = vec![Tick::default(); TICK_ARRAY_SIZE as usize];
+        
+        Ok(())
+    }
+
+    pub fn open_concentrated_position(
+        ctx: Context<OpenConcentratedPosition>,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<()> {
+        let spacing = ctx.accounts.pool.tick_spacing as i32;
+        require!(
+            tick_lower < tick_upper
+                && tick_lower >= MIN_TICK
+                && tick_upper <= MAX_TICK
+                && tick_lower % spacing == 0
+                && tick_upper % spacing == 0,
+            ErrorCode::InvalidTickIndex
+        );
+        
+        let position = &mut ctx.accounts.position;
+        position.pool = ctx.accounts.pool.key();
+        position.owner = ctx.accounts.owner.key();
+        position.tick_lower = tick_lower;
+        position.tick_upper = tick_upper;
+        
+        Ok(())
+    }
+
+    pub fn increase_liquidity(
+        ctx: Context<ModifyConcentratedLiquidity>,
+        liquidity: u128,
+        max_amount_a: u64,
+        max_amount_b: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+        require!(liquidity > 0, ErrorCode::InsufficientLiquidity);
+        let liquidity_delta = i128::try_from(liquidity).map_err(|_| ErrorCode::MathOverflow)?;
+        
+        let accounts = ctx.accounts;
+        modify_position_liquidity(
+            &mut accounts.pool,
+            &mut accounts.position,
+            &mut accounts.tick_array_lower,
+            &mut accounts.tick_array_upper,
+            liquidity_delta,
+        )?;
+        let (amount_a, amount_b) = position_amounts(
+            &accounts.pool,
+            accounts.position.tick_lower,
+            accounts.position.tick_upper,
+            liquidity,
+            true,
+        )?;
+        require!(
+            amount_a <= max_amount_a && amount_b <= max_amount_b,
+            ErrorCode::SlippageExceeded
+        );
+        
+        for (from, to, amount) in [
+            (&accounts.user_token_a, &accounts.token_a_account, amount_a),
+            (&accounts.user_token_b, &accounts.token_b_account, amount_b),
+        ] {
+            if amount > 0 {
+                token::transfer(
+                    CpiContext::new(
+                        accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: from.to_account_info(),
+                            to: to.to_account_info(),
+                            authority: accounts.owner.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+            }
+        }
+        
+        Ok(())
+    }
+
+    pub fn decrease_liquidity(
+        ctx: Context<ModifyConcentratedLiquidity>,
+        liquidity: u128,
+        min_amount_a: u64,
+        min_amount_b: u64,
+    ) -> Result<()> {
+        require!(
+            liquidity > 0 && liquidity <= ctx.accounts.position.liquidity,
+            ErrorCode::InsufficientLiquidity
+        );
+        let liquidity_delta = i128::try_from(liquidity).map_err(|_| ErrorCode::MathOverflow)?;
+        
+        let accounts = ctx.accounts;
+        modify_position_liquidity(
+            &mut accounts.pool,
+            &mut accounts.position,
+            &mut accounts.tick_array_lower,
+            &mut accounts.tick_array_upper,
+            -liquidity_delta,
+        )?;
+        let (amount_a, amount_b) = position_amounts(
+            &accounts.pool,
+            accounts.position.tick_lower,
+            accounts.position.tick_upper,
+            liquidity,
+            false,
+        )?;
+        require!(
+            amount_a >= min_amount_a && amount_b >= min_amount_b,
+            ErrorCode::SlippageExceeded
+        );
+        
+        let pool_key = accounts.pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[accounts.pool.bump]];
+        for (from, to, amount) in [
+            (&accounts.token_a_account, &accounts.user_token_a, amount_a),
+            (&accounts.token_b_account, &accounts.user_token_b, amount_b),
+        ] {
+            if amount > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: from.to_account_info(),
+                            to: to.to_account_info(),
+                            authority: accounts.pool_authority.to_account_info(),
+                        },
+                        &[pool_authority_seeds],
+                    ),
+                    amount,
+                )?;
+            }
+        }
+        
+        Ok(())
+    }
+
+    pub fn collect_concentrated_fees(ctx: Context<ModifyConcentratedLiquidity>) -> Result<()> {
+        let accounts = ctx.accounts;
+        
+        // Zero-liquidity update credits everything earned since the last one
+        modify_position_liquidity(
+            &mut accounts.pool,
+            &mut accounts.position,
+            &mut accounts.tick_array_lower,
+            &mut accounts.tick_array_upper,
+            0,
+        )?;
+        let amount_a = accounts.position.fees_owed_a;
+        let amount_b = accounts.position.fees_owed_b;
+        accounts.position.fees_owed_a = 0;
+        accounts.position.fees_owed_b = 0;
+        
+        let pool_key = accounts.pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[accounts.pool.bump]];
+        for (from, to, amount) in [
+            (&accounts.token_a_account, &accounts.user_token_a, amount_a),
+            (&accounts.token_b_account, &accounts.user_token_b, amount_b),
+        ] {
+            if amount > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: from.to_account_info(),
+                            to: to.to_account_info(),
+                            authority: accounts.pool_authority.to_account_info(),
+                        },
+                        &[pool_authority_seeds],
+                    ),
+                    amount,
+                )?;
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Tick arrays are passed as writable remaining accounts, consecutive in
+    /// the swap direction and starting with the one holding the current tick.
+    pub fn swap_concentrated<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapConcentrated<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        a_to_b: bool,
+        sqrt_price_limit: u128,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.pool.key();
+        let mut tick_array_accounts = ctx
+            .remaining_accounts
+            .iter()
+            .map(|account_info| {
+                let tick_array = Account::<TickArray>::try_from(account_info)?;
+                require_keys_eq!(tick_array.pool, pool_key, ErrorCode::TickArrayMissing);
+                Ok(tick_array)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut tick_arrays: Vec<TickArray> = tick_array_accounts
+            .iter()
+            .map(|tick_array| (**tick_array).clone())
+            .collect();
+        
+        let result = swap_across_ticks(
+            &mut ctx.accounts.pool,
+            &mut tick_arrays,
+            amount_in,
+            a_to_b,
+            sqrt_price_limit,
+        )?;
+        require!(
+            result.amount_out >= minimum_amount_out,
+            ErrorCode::SlippageExceeded
+        );
+        
+        for (account, tick_array) in tick_array_accounts.iter_mut().zip(tick_arrays) {
+            account.set_inner(tick_array);
+            account.exit(ctx.program_id)?;
+        }
+        
+        let (vault_in, vault_out) = if a_to_b {
+            (&ctx.accounts.token_a_account, &ctx.accounts.token_b_account)
+        } else {
+            (&ctx.accounts.token_b_account, &ctx.accounts.token_a_account)
+        };
+        require!(
+            ctx.accounts.user_token_in.mint == vault_in.mint
+                && ctx.accounts.user_token_out.mint == vault_out.mint,
+            ErrorCode::InvalidMint
+        );
+        
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token_in.to_account_info(),
+                    to: vault_in.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            result.amount_in,
+        )?;
+        
+        let pool_authority_seeds = &[pool_key.as_ref(), &[ctx.accounts.pool.bump]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: vault_out.to_account_info(),
+                    to: ctx.accounts.user_token_out.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            result.amount_out,
+        )?;
+        
+        emit!(Swapped {
+            pool: pool_key,
+            user: ctx.accounts.user.key(),
+            a_to_b,
+            amount_in: result.amount_in,
+            amount_out: result.amount_out,
+            lp_fee: result.fee_amount,
+            protocol_fee: 0,
+        });
+        
+        Ok(())
+    }
+
+    /// Pool mints are passed as remaining accounts in ascending key order.
+    /// Only legacy SPL mints are accepted.
+    pub fn initialize_weighted_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeWeightedPool<'info>>,
+        fee_numerator: u64,
+        fee_denominator: u64,
+        weights: Vec<u64>,
+    ) -> Result<()> {
+        require!(
+            fee_denominator > 0 && fee_numerator < fee_denominator,
+            ErrorCode::InvalidFee
+        );
+        require!(validate_weights(&weights), ErrorCode::InvalidWeights);
+        require!(
+            ctx.remaining_accounts.len() == weights.len(),
+            ErrorCode::InvalidWeights
+        );
+        
+        let pool = &mut ctx.accounts.pool;
+        for (i, account_info) in ctx.remaining_accounts.iter().enumerate() {
+            let mint = legacy_mint(account_info)?;
+            require!(
+                i == 0 || pool.mints[i - 1] < mint.key(),
+                ErrorCode::InvalidMintOrder
+            );
+            pool.mints[i] = mint.key();
+            pool.weights[i] = weights[i];
+        }
+        pool.token_count = weights.len() as u8;
+        pool.authority = ctx.accounts.authority.key();
+        pool.bump = *ctx.bumps.get("pool_authority").unwrap();
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.fee_numerator = fee_numerator;
+        pool.fee_denominator = fee_denominator;
+        
+        Ok(())
+    }
+
+    pub fn initialize_weighted_vault(ctx: Context<InitializeWeightedVault>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let index = pool
+            .mints()
+            .iter()
+            .position(|mint| *mint == ctx.accounts.mint.key())
+            .ok_or(ErrorCode::InvalidMint)?;
+        pool.vaults[index] = ctx.accounts.vault.key();
+        
+        Ok(())
+    }
+
+    /// Multi-asset join in pool proportions. Remaining accounts are
+    /// `(vault, user token account)` pairs for every token in pool order.
+    /// The first join deposits `max_amounts` as-is and sizes the LP supply
+    /// from the pool invariant, with `lp_amount` acting as the minimum.
+    pub fn join_weighted_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, JoinWeightedPool<'info>>,
+        lp_amount: u64,
+        max_amounts: Vec<u64>,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let token_accounts = load_weighted_token_accounts(pool, ctx.remaining_accounts)?;
+        require!(
+            max_amounts.len() == token_accounts.len(),
+            ErrorCode::InvalidMint
+        );
+        let total_supply = ctx.accounts.lp_mint.supply;
+        
+        let (amounts, lp_tokens_to_mint, lp_tokens_to_lock) = if total_supply == 0 {
+            let lp = invariant(&max_amounts, pool.weights()).ok_or(ErrorCode::MathOverflow)?;
+            require!(lp > MINIMUM_LIQUIDITY, ErrorCode::InitialLiquidityTooLow);
+            require!(
+                lp - MINIMUM_LIQUIDITY >= lp_amount,
+                ErrorCode::SlippageExceeded
+            );
+            (max_amounts.clone(), lp - MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY)
+        } else {
+            let amounts = token_accounts
+                .iter()
+                .map(|(vault, _)| {
+                    proportional_amount(vault.amount, lp_amount, total_supply, true)
+                        .ok_or_else(|| error!(ErrorCode::MathOverflow))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            (amounts, lp_amount, 0)
+        };
+        require!(lp_tokens_to_mint > 0, ErrorCode::InsufficientLiquidity);
+        
+        for (((vault, user_token), amount), max_amount) in
+            token_accounts.iter().zip(&amounts).zip(&max_amounts)
+        {
+            require!(*amount <= *max_amount, ErrorCode::SlippageExceeded);
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: user_token.to_account_info(),
+                        to: vault.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                *amount,
+            )?;
+        }
+        
+        let pool_key = pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[pool.bump]];
+        for (to, amount) in [
+            (ctx.accounts.user_lp_token.to_account_info(), lp_tokens_to_mint),
+            (ctx.accounts.locked_lp_token.to_account_info(), lp_tokens_to_lock),
+        ] {
+            if amount > 0 {
+                token::mint_to(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::MintTo {
+                            mint: ctx.accounts.lp_mint.to_account_info(),
+                            to,
+                            authority: ctx.accounts.pool_authority.to_account_info(),
+                        },
+                        &[pool_authority_seeds],
+                    ),
+                    amount,
+                )?;
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Multi-asset exit in pool proportions. Remaining accounts are
+    /// `(vault, user token account)` pairs for every token in pool order.
+    pub fn exit_weighted_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExitWeightedPool<'info>>,
+        lp_amount: u64,
+        min_amounts: Vec<u64>,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let token_accounts = load_weighted_token_accounts(pool, ctx.remaining_accounts)?;
+        require!(
+            min_amounts.len() == token_accounts.len(),
+            ErrorCode::InvalidMint
+        );
+        let total_supply = ctx.accounts.lp_mint.supply;
+        
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+        
+        let pool_key = pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[pool.bump]];
+        for ((vault, user_token), min_amount) in token_accounts.iter().zip(&min_amounts) {
+            let amount = proportional_amount(vault.amount, lp_amount, total_supply, false)
+                .ok_or(ErrorCode::MathOverflow)?;
+            require!(amount >= *min_amount, ErrorCode::SlippageExceeded);
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: vault.to_account_info(),
+                        to: user_token.to_account_info(),
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    &[pool_authority_seeds],
+                ),
+                amount,
+            )?;
+        }
+        
+        Ok(())
+    }
+
+    pub fn join_weighted_single(
+        ctx: Context<WeightedSingleAsset>,
+        amount_in: u64,
+        min_lp_tokens: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(!pool.paused, ErrorCode::PoolPaused);
+        let index = pool.vault_index(&ctx.accounts.vault.key())?;
+        let total_supply = ctx.accounts.lp_mint.supply;
+        require!(total_supply > 0, ErrorCode::InsufficientLiquidity);
+        
+        let lp_tokens_to_mint = lp_out_given_single_in(
+            ctx.accounts.vault.amount,
+            pool.weights[index],
+            total_supply,
+            amount_in,
+            pool.fee_numerator,
+            pool.fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        require!(lp_tokens_to_mint > 0, ErrorCode::InsufficientLiquidity);
+        require!(
+            lp_tokens_to_mint >= min_lp_tokens,
+            ErrorCode::SlippageExceeded
+        );
+        
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+        
+        let pool_key = pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[pool.bump]];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            lp_tokens_to_mint,
+        )?;
+        
+        Ok(())
+    }
+
+    pub fn exit_weighted_single(
+        ctx: Context<WeightedSingleAsset>,
+        lp_amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let index = pool.vault_index(&ctx.accounts.vault.key())?;
+        
+        let amount_out = single_out_given_lp_in(
+            ctx.accounts.vault.amount,
+            pool.weights[index],
+            ctx.accounts.lp_mint.supply,
+            lp_amount,
+            pool.fee_numerator,
+            pool.fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            amount_out >= min_amount_out,
+            ErrorCode::SlippageExceeded
+        );
+        
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+        
+        let pool_key = pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[pool.bump]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.user_token.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            amount_out,
+        )?;
+        
+        Ok(())
+    }
+
+    pub fn swap_weighted(
+        ctx: Context<SwapWeighted>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let index_in = pool.vault_index(&ctx.accounts.vault_in.key())?;
+        let index_out = pool.vault_index(&ctx.accounts.vault_out.key())?;
+        require!(index_in != index_out, ErrorCode::InvalidMint);
+        
+        let amount_out = out_given_in(
+            ctx.accounts.vault_in.amount,
+            pool.weights[index_in],
+            ctx.accounts.vault_out.amount,
+            pool.weights[index_out],
+            amount_in,
+            pool.fee_numerator,
+            pool.fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        require!(amount_out > 0, ErrorCode::InsufficientLiquidity);
+        require!(
+            amount_out >= minimum_amount_out,
+            ErrorCode::SlippageExceeded
+        );
+        
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token_in.to_account_info(),
+                    to: ctx.accounts.vault_in.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+        
+        let pool_key = pool.key();
+        let pool_authority_seeds = &[pool_key.as_ref(), &[pool.bump]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_out.to_account_info(),
+                    to: ctx.accounts.user_token_out.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[pool_authority_seeds],
+            ),
+            amount_out,
+        )?;
+        
+        Ok(())
+    }
+}
+
+/// Requires that `flash_borrow` was called directly by the transaction,
+/// not through CPI, and that a `flash_repay` for `pool` follows it.
+fn require_flash_repay(instructions: &AccountInfo, pool: &Pubkey) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)? as usize;
+    let current = load_instruction_at_checked(current_index, instructions)?;
+    require_keys_eq!(current.program_id, crate::ID, ErrorCode::FlashLoanNotRepaid);
+    
+    let mut index = current_index + 1;
+    while let Ok(next) = load_instruction_at_checked(index, instructions) {
+        if next.program_id == crate::ID
+            && next.data.get(..8) == Some(&instruction::FlashRepay::DISCRIMINATOR[..])
+            && next.accounts.first().map(|meta| meta.pubkey) == Some(*pool)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+    err!(ErrorCode::FlashLoanNotRepaid)
+}
+
+/// Concentrated and weighted pools move tokens with the legacy token
+/// program and do no transfer-fee accounting, so they only take legacy SPL
+/// mints.
+fn legacy_mint<'info>(account: &AccountInfo<'info>) -> Result<Account<'info, Mint>> {
+    require_keys_eq!(*account.owner, token::ID, ErrorCode::UnsupportedTokenProgram);
+    Account::try_from(account)
+}
+
+/// Brings the pool's price accumulators up to now using the reserves
+/// before this instruction moves them, and stores the reading in the oracle
+/// when one is supplied.
+fn record_observation(
+    pool: &mut LiquidityPool,
+    oracle: Option<&mut Account<PriceOracle>>,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    pool.last_observation = pool.last_observation.accumulate(reserve_a, reserve_b, now);
+    if let Some(oracle) = oracle {
+        oracle.ring.write(pool.last_observation);
+    }
+    Ok(())
+}
+
+/// Applies `liquidity_delta` to a position and both of its boundary ticks,
+/// crediting fees earned so far. Ticks the change leaves unreferenced are
+/// only cleared once the position has been settled against their fee
+/// growth. When both bounds live in the same tick array
+/// the lower account is updated and copied over the upper one, so the two
+/// deserialized copies agree when they are written back.
+fn modify_position_liquidity(
+    pool: &mut ConcentratedPool,
+    position: &mut ConcentratedPosition,
+    tick_array_lower: &mut TickArray,
+    tick_array_upper: &mut TickArray,
+    liquidity_delta: i128,
+) -> Result<()> {
+    let same_array = tick_array_lower.start_tick_index == tick_array_upper.start_tick_index;
+    let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+    
+    if liquidity_delta != 0 {
+        tick_array_lower
+            .tick_mut(pool, tick_lower)?
+            .update(pool, tick_lower, liquidity_delta, false)?;
+        let upper_array = if same_array { &mut *tick_array_lower } else { &mut *tick_array_upper };
+        upper_array
+            .tick_mut(pool, tick_upper)?
+            .update(pool, tick_upper, liquidity_delta, true)?;
+    }
+    
+    let lower = *tick_array_lower.tick(pool, tick_lower)?;
+    let upper = if same_array {
+        *tick_array_lower.tick(pool, tick_upper)?
+    } else {
+        *tick_array_upper.tick(pool, tick_upper)?
+    };
+    position.update(pool.fee_growth_inside(&lower, tick_lower, &upper, tick_upper), liquidity_delta)?;
+    
+    tick_array_lower.tick_mut(pool, tick_lower)?.clear_if_unused();
+    let upper_array = if same_array { &mut *tick_array_lower } else { &mut *tick_array_upper };
+    upper_array.tick_mut(pool, tick_upper)?.clear_if_unused();
+    if same_array {
+        tick_array_upper.ticks = tick_array_lower.ticks.clone();
+    }
+    
+    if pool.tick_current >= tick_lower && pool.tick_current < tick_upper {
+        pool.liquidity = if liquidity_delta >= 0 {
+            pool.liquidity.checked_add(liquidity_delta as u128)
+        } else {
+            pool.liquidity.checked_sub(liquidity_delta.unsigned_abs())
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+    }
+    
+    Ok(())
+}
+
+
+#[derive(Accounts)]
+#[instruction(
+    fee_numerator: u64,
+    fee_denominator: u64,
+    protocol_fee_numerator: u64,
+    protocol_fee_denominator: u64,
+    curve_type: CurveType,
+    amp: u64
+)]
+pub struct InitializePool<'info> {
+    /// One pool per mint pair, enforced by deriving the address from the
+    /// mints in canonical (sorted) order
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + LiquidityPool::LEN,
+        seeds = [b"pool", token_a_mint.key().as_ref(), token_b_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, LiquidityPool>>,
+    #[account(constraint = token_a_mint.key() < token_b_mint.key() @ ErrorCode::InvalidMintOrder)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault", pool.key().as_ref(), token_a_mint.key().as_ref()],
+        bump,
+        token::mint = token_a_mint,
+        token::authority = pool_authority,
+        token::token_program = token_a_program
+    )]
+    pub token_a_account: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault", pool.key().as_ref(), token_b_mint.key().as_ref()],
+        bump,
+        token::mint = token_b_mint,
+        token::authority = pool_authority,
+        token::token_program = token_b_program
+    )]
+    pub token_b_account: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump,
+        mint::decimals = LP_DECIMALS,
+        mint::authority = pool_authority
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    /// Holds the MINIMUM_LIQUIDITY locked on the first deposit
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"locked_lp", pool.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool_authority
+    )]
+    pub locked_lp_token: Box<Account<'info, TokenAccount>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(
+        mut,
+        constraint = !pool.paused @ ErrorCode::PoolPaused,
+        constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user_token_a: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_token_b: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    /// LP token account owned by the pool authority that holds the
+    /// permanently locked MINIMUM_LIQUIDITY. Nothing ever transfers out of it.
+    #[account(
+        mut,
+        seeds = [b"locked_lp", pool.key().as_ref()],
+        bump,
+        constraint = locked_lp_token.mint == pool.lp_mint,
+        constraint = locked_lp_token.owner == pool_authority.key()
+    )]
+    pub locked_lp_token: Account<'info, TokenAccount>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidityNative<'info> {
+    #[account(
+        mut,
+        constraint = !pool.paused @ ErrorCode::PoolPaused,
+        constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive,
+        constraint = pool.token_a_mint == native_mint::ID
+            || pool.token_b_mint == native_mint::ID @ ErrorCode::InvalidMint
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    /// User's account for the pool's other token
+    #[account(mut)]
+    pub user_token: InterfaceAccount<'info, token_interface::TokenAccount>,
+    /// Temporary wSOL account, closed again before the instruction returns
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump,
+        token::mint = native_mint,
+        token::authority = user
+    )]
+    pub wsol_account: Box<Account<'info, TokenAccount>>,
+    #[account(address = native_mint::ID)]
+    pub native_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"locked_lp", pool.key().as_ref()],
+        bump,
+        constraint = locked_lp_token.mint == pool.lp_mint,
+        constraint = locked_lp_token.owner == pool_authority.key()
+    )]
+    pub locked_lp_token: Account<'info, TokenAccount>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint and of wSOL
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(mut, constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user_token_a: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_token_b: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(token_in: Pubkey)]
+pub struct AddLiquiditySingleSided<'info> {
+    #[account(
+        mut,
+        constraint = !pool.paused @ ErrorCode::PoolPaused,
+        constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, constraint = user_token_in.mint == token_in @ ErrorCode::InvalidMint)]
+    pub user_token_in: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(token_out: Pubkey)]
+pub struct RemoveLiquiditySingleSided<'info> {
+    #[account(mut, constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, constraint = user_token_out.mint == token_out @ ErrorCode::InvalidMint)]
+    pub user_token_out: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        mut,
+        constraint = !pool.paused @ ErrorCode::PoolPaused,
+        constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account,
+        constraint = token_a_account.owner == pool_authority.key()
+    )]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account,
+        constraint = token_b_account.owner == pool_authority.key()
+    )]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(constraint = lp_mint.key() == pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        constraint = user_token_in.mint == pool.token_a_mint || user_token_in.mint == pool.token_b_mint @ ErrorCode::InvalidMint
+    )]
+    pub user_token_in: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_token_out: InterfaceAccount<'info, token_interface::TokenAccount>,
+    /// Protocol fee recipient's account for the input token. Only required
+    /// when the pool skims a protocol share.
+    #[account(mut)]
+    pub protocol_fee_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    /// Token program of the LP mint
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RampAmp<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConcentratedPool<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWeightedPool<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub pool: Box<Account<'info, WeightedPool>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        constraint = pool.pending_authority == new_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    #[account(
+        mut,
+        constraint = !pool.paused @ ErrorCode::PoolPaused,
+        constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_token_a: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_token_b: InterfaceAccount<'info, token_interface::TokenAccount>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    /// CHECK: Instructions sysvar, checked by address
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+/// `pool` must stay the first account; `flash_borrow` looks for it there.
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut, address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user_token_a: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub user_token_b: InterfaceAccount<'info, token_interface::TokenAccount>,
+    pub user: Signer<'info>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeOracle<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PriceOracle::LEN,
+        seeds = [b"oracle", pool.key().as_ref()],
+        bump
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Observe<'info> {
+    #[account(constraint = !pool.flash_loan_active() @ ErrorCode::FlashLoanActive)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(seeds = [b"oracle", pool.key().as_ref()], bump)]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+    #[account(address = pool.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(address = pool.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(fee_numerator: u64, fee_denominator: u64, tick_spacing: u16)]
+pub struct InitializeConcentratedPool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ConcentratedPool::LEN,
+        seeds = [
+            b"concentrated_pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+            &tick_spacing.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+    /// Legacy SPL mints only; see `legacy_mint`
+    #[account(
+        constraint = token_a_mint.key() < token_b_mint.key() @ ErrorCode::InvalidMintOrder,
+        constraint = *token_a_mint.to_account_info().owner == token::ID @ ErrorCode::UnsupportedTokenProgram
+    )]
+    pub token_a_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(
+        constraint = *token_b_mint.to_account_info().owner == token::ID @ ErrorCode::UnsupportedTokenProgram
+    )]
+    pub token_b_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault", pool.key().as_ref(), token_a_mint.key().as_ref()],
+        bump,
+        token::mint = token_a_mint,
+        token::authority = pool_authority
+    )]
+    pub token_a_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault", pool.key().as_ref(), token_b_mint.key().as_ref()],
+        bump,
+        token::mint = token_b_mint,
+        token::authority = pool_authority
+    )]
+    pub token_b_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(start_tick_index: i32)]
+pub struct InitializeTickArray<'info> {
+    pub pool: Account<'info, ConcentratedPool>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TickArray::LEN,
+        seeds = [b"tick_array", pool.key().as_ref(), &start_tick_index.to_le_bytes()],
+        bump
+    )]
+    pub tick_array: Box<Account<'info, TickArray>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tick_lower: i32, tick_upper: i32)]
+pub struct OpenConcentratedPosition<'info> {
+    pub pool: Account<'info, ConcentratedPool>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + ConcentratedPosition::LEN,
+        seeds = [
+            b"position",
+            pool.key().as_ref(),
+            owner.key().as_ref(),
+            &tick_lower.to_le_bytes(),
+            &tick_upper.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub position: Account<'info, ConcentratedPosition>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyConcentratedLiquidity<'info> {
+    #[account(mut)]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = owner @ ErrorCode::Unauthorized
+    )]
+    pub position: Box<Account<'info, ConcentratedPosition>>,
+    #[account(
+        mut,
+        has_one = pool,
+        constraint = tick_array_lower.start_tick_index == pool.tick_array_start(position.tick_lower) @ ErrorCode::TickArrayMissing
+    )]
+    pub tick_array_lower: Box<Account<'info, TickArray>>,
+    #[account(
+        mut,
+        has_one = pool,
+        constraint = tick_array_upper.start_tick_index == pool.tick_array_start(position.tick_upper) @ ErrorCode::TickArrayMissing
+    )]
+    pub tick_array_upper: Box<Account<'info, TickArray>>,
+    #[account(mut, constraint = token_a_account.key() == pool.token_a_account)]
+    pub token_a_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, constraint = token_b_account.key() == pool.token_b_account)]
+    pub token_b_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, constraint = user_token_a.mint == pool.token_a_mint @ ErrorCode::InvalidMint)]
+    pub user_token_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, constraint = user_token_b.mint == pool.token_b_mint @ ErrorCode::InvalidMint)]
+    pub user_token_b: Box<Account<'info, TokenAccount>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SwapConcentrated<'info> {
+    #[account(mut, constraint = !pool.paused @ ErrorCode::PoolPaused)]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+    #[account(mut, constraint = token_a_account.key() == pool.token_a_account)]
+    pub token_a_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, constraint = token_b_account.key() == pool.token_b_account)]
+    pub token_b_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_token_in: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_token_out: Box<Account<'info, TokenAccount>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWeightedPool<'info> {
+    #[account(init, payer = authority, space = 8 + WeightedPool::LEN)]
+    pub pool: Box<Account<'info, WeightedPool>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump,
+        mint::decimals = LP_DECIMALS,
+        mint::authority = pool_authority
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    /// Holds the MINIMUM_LIQUIDITY locked on the first join
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"locked_lp", pool.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool_authority
+    )]
+    pub locked_lp_token: Box<Account<'info, TokenAccount>>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWeightedVault<'info> {
+    #[account(mut)]
+    pub pool: Box<Account<'info, WeightedPool>>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"vault", pool.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = pool_authority
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct JoinWeightedPool<'info> {
+    #[account(constraint = !pool.paused @ ErrorCode::PoolPaused)]
+    pub pool: Box<Account<'info, WeightedPool>>,
+    #[account(mut, constraint = lp_mint.key() == pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"locked_lp", pool.key().as_ref()], bump)]
+    pub locked_lp_token: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExitWeightedPool<'info> {
+    pub pool: Box<Account<'info, WeightedPool>>,
+    #[account(mut, constraint = lp_mint.key() == pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WeightedSingleAsset<'info> {
+    pub pool: Box<Account<'info, WeightedPool>>,
+    #[account(mut, constraint = vault.owner == pool_authority.key())]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_token.mint == vault.mint @ ErrorCode::InvalidMint)]
+    pub user_token: Account<'info, TokenAccount>,
+    #[account(mut, constraint = lp_mint.key() == pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user_lp_token: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SwapWeighted<'info> {
+    #[account(constraint = !pool.paused @ ErrorCode::PoolPaused)]
+    pub pool: Box<Account<'info, WeightedPool>>,
+    #[account(mut)]
+    pub vault_in: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_out: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_token_in.mint == vault_in.mint @ ErrorCode::InvalidMint)]
+    pub user_token_in: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_token_out.mint == vault_out.mint @ ErrorCode::InvalidMint)]
+    pub user_token_out: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that will manage token accounts
+    #[account(seeds = [pool.key().as_ref()], bump = pool.bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+pub struct LiquidityPool {
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_account: Pubkey,
+    pub token_b_account: Pubkey,
+    pub lp_mint: Pubkey,
+    pub fee_numerator: u64,
+    pub fee_denominator: u64,
+    pub authority: Pubkey,
+    pub bump: u8,
+    /// Share of each swap fee sent to the protocol (numerator / denominator)
+    pub protocol_fee_numerator: u64,
+    pub protocol_fee_denominator: u64,
+    /// Owner of the token accounts that receive the protocol fee
+    pub protocol_fee_recipient: Pubkey,
+    /// Cumulative LP fees earned per LP token in token A, as Q64.64
+    pub fee_growth_a: u128,
+    /// Cumulative LP fees earned per LP token in token B, as Q64.64
+    pub fee_growth_b: u128,
+    /// Pricing curve used by `swap`
+    pub curve_type: CurveType,
+    /// StableSwap amplification at the start of the current ramp
+    pub amp_initial: u64,
+    /// StableSwap amplification at the end of the current ramp
+    pub amp_target: u64,
+    /// Ramp window, as unix timestamps
+    pub amp_ramp_start: i64,
+    pub amp_ramp_end: i64,
+    /// Price accumulators as of the last swap or liquidity change. For
+    /// StableSwap pools the accumulated price is the reserve ratio.
+    pub last_observation: Observation,
+    /// Bounds on the volatility-scaled swap fee, `None` for a fixed fee
+    pub dynamic_fee: Option<DynamicFeeBounds>,
+    /// Recent price movement in basis points, decaying over time
+    pub volatility_accumulator: u64,
+    /// Unix timestamp the accumulator was last updated
+    pub volatility_updated_at: i64,
+    /// Proposed authority awaiting `accept_authority`
+    pub pending_authority: Pubkey,
+    /// Swaps and deposits are rejected while set
+    pub paused: bool,
+    /// Principal of the outstanding flash loan, per token
+    pub flash_loan_a: u64,
+    pub flash_loan_b: u64,
+    /// Fee owed on top of the outstanding flash loan, per token
+    pub flash_fee_a: u64,
+    pub flash_fee_b: u64,
+}
+
+impl LiquidityPool {
+    pub const LEN: usize = 32 + // token_a_mint
+                           32 + // token_b_mint
+                           32 + // token_a_account
+                           32 + // token_b_account
+                           32 + // lp_mint
+                           8 +  // fee_numerator
+                           8 +  // fee_denominator
+                           32 + // authority
+                           1 +  // bump
+                           8 +  // protocol_fee_numerator
+                           8 +  // protocol_fee_denominator
+                           32 + // protocol_fee_recipient
+                           16 + // fee_growth_a
+                           16 + // fee_growth_b
+                           1 +  // curve_type
+                           8 +  // amp_initial
+                           8 +  // amp_target
+                           8 +  // amp_ramp_start
+                           8 +  // amp_ramp_end
+                           Observation::LEN + // last_observation
+                           1 + 16 + // dynamic_fee
+                           8 +  // volatility_accumulator
+                           8 +  // volatility_updated_at
+                           32 + // pending_authority
+                           1 +  // paused
+                           8 +  // flash_loan_a
+                           8 +  // flash_loan_b
+                           8 +  // flash_fee_a
+                           8;   // flash_fee_b
+
+    /// StableSwap amplification in effect at `now`
+    pub fn amp(&self, now: i64) -> u64 {
+        current_amp(self.amp_initial, self.amp_target, self.amp_ramp_start, self.amp_ramp_end, now)
+    }
+
+    /// Whether a flash loan is awaiting `flash_repay`
+    pub fn flash_loan_active(&self) -> bool {
+        self.flash_loan_a > 0 || self.flash_loan_b > 0
+    }
+
+    /// Swap fee numerator in effect at `now`, over `fee_denominator`
+    pub fn swap_fee_numerator(&self, now: i64) -> u64 {
+        match self.dynamic_fee {
+            None => self.fee_numerator,
+            Some(bounds) => dynamic_fee_numerator(
+                self.fee_numerator,
+                bounds.min_fee_numerator,
+                bounds.max_fee_numerator,
+                self.fee_denominator,
+                decay_volatility(self.volatility_accumulator, now - self.volatility_updated_at),
+            ),
+        }
+    }
+
+    /// Adds a swap's price impact to the volatility accumulator
+    pub fn accrue_volatility(&mut self, price_before: u128, price_after: u128, now: i64) {
+        if self.dynamic_fee.is_none() {
+            return;
+        }
+        let decayed = decay_volatility(self.volatility_accumulator, now - self.volatility_updated_at);
+        self.volatility_accumulator = decayed
+            .saturating_add(price_move_bps(price_before, price_after))
+            .min(MAX_VOLATILITY_BPS);
+        self.volatility_updated_at = now;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CurveType {
+    /// x * y = k over the full price range
+    ConstantProduct,
+    /// Curve-style invariant for pegged pairs
+    StableSwap,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DynamicFeeBounds {
+    /// Lowest fee numerator the pool may charge, over `fee_denominator`
+    pub min_fee_numerator: u64,
+    /// Highest fee numerator the pool may charge, over `fee_denominator`
+    pub max_fee_numerator: u64,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    /// Token A actually transferred into the pool
+    pub amount_a: u64,
+    /// Token B actually transferred into the pool
+    pub amount_b: u64,
+    /// LP tokens minted to the user
+    pub lp_tokens: u64,
+}
+
+#[event]
+pub struct Swapped {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    /// True when token A was sold for token B
+    pub a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Fee left in the pool for LPs, in the input token
+    pub lp_fee: u64,
+    /// Fee skimmed to the protocol, in the input token
+    pub protocol_fee: u64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Invalid fee configuration")]
+    InvalidFee,
+    #[msg("Slippage tolerance exceeded")]
+    SlippageExceeded,
+    #[msg("Insufficient liquidity")]
+    InsufficientLiquidity,
+    #[msg("Initial deposit does not cover the minimum locked liquidity")]
+    InitialLiquidityTooLow,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Token is not part of this pool")]
+    InvalidMint,
+    #[msg("Pool mints must be passed in ascending key order")]
+    InvalidMintOrder,
+    #[msg("Operation not supported by this pool's curve")]
+    UnsupportedCurve,
+    #[msg("Amplification coefficient out of range")]
+    InvalidAmp,
+    #[msg("Amplification ramp ends too soon")]
+    InvalidAmpRamp,
+    #[msg("Weights must number 2-8, each at least 1%, summing to 1")]
+    InvalidWeights,
+    #[msg("Tick index out of range or not a multiple of the tick spacing")]
+    InvalidTickIndex,
+    #[msg("Tick spacing must be non-zero")]
+    InvalidTickSpacing,
+    #[msg("Sqrt price limit is on the wrong side of the current price or out of range")]
+    InvalidSqrtPriceLimit,
+    #[msg("Required tick array was not supplied")]
+    TickArrayMissing,
+    #[msg("Requested observation is older than the oracle's history")]
+    ObservationTooOld,
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    #[msg("Pool is paused")]
+    PoolPaused,
+    #[msg("Pool has an outstanding flash loan")]
+    FlashLoanActive,
+    #[msg("Flash loan must be repaid by a later instruction in the same transaction")]
+    FlashLoanNotRepaid,
+    #[msg("Protocol fee account missing or not owned by the fee recipient")]
+    MissingProtocolFeeAccount,
+    #[msg("Concentrated and weighted pools only support legacy SPL token mints")]
+    UnsupportedTokenProgram,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::program_option::COption;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_spl::token::spl_token;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn account(key: Pubkey, owner: Pubkey, data: Vec<u8>, is_signer: bool, executable: bool) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(key)),
+            is_signer,
+            true,
+            Box::leak(Box::new(1_000_000_000)),
+            Box::leak(data.into_boxed_slice()),
+            Box::leak(Box::new(owner)),
+            executable,
+            0,
+        )
+    }
+
+    fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) -> AccountInfo<'static> {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account(key, spl_token::ID, data, false, false)
+    }
+
+    fn mint(key: Pubkey, authority: Pubkey, supply: u64) -> AccountInfo<'static> {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: COption::Some(authority),
+            supply,
+            decimals: LP_DECIMALS,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut data);
+        account(key, spl_token::ID, data, false, false)
+    }
+
+    /// A constant-product pool with its real vaults, plus an account the
+    /// attacker created for token A and handed to the pool authority
+    struct Fixture {
+        pool: AccountInfo<'static>,
+        pool_authority: AccountInfo<'static>,
+        token_a_account: AccountInfo<'static>,
+        token_b_account: AccountInfo<'static>,
+        fake_token_a_account: AccountInfo<'static>,
+        lp_mint: AccountInfo<'static>,
+        token_a_mint: AccountInfo<'static>,
+        token_b_mint: AccountInfo<'static>,
+        user: AccountInfo<'static>,
+        user_token_a: AccountInfo<'static>,
+        user_token_b: AccountInfo<'static>,
+        user_lp_token: AccountInfo<'static>,
+        locked_lp_token: AccountInfo<'static>,
+        no_oracle: AccountInfo<'static>,
+        token_program: AccountInfo<'static>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let pool_key = Pubkey::new_unique();
+            let (authority_key, bump) = Pubkey::find_program_address(&[pool_key.as_ref()], &crate::ID);
+            let (mint_a, mint_b, lp_mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+            let (vault_a, vault_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            let user = Pubkey::new_unique();
+            let (locked_lp, _) = Pubkey::find_program_address(&[b"locked_lp", pool_key.as_ref()], &crate::ID);
+
+            let pool = LiquidityPool {
+                token_a_mint: mint_a,
+                token_b_mint: mint_b,
+                token_a_account: vault_a,
+                token_b_account: vault_b,
+                lp_mint,
+                fee_numerator: 3,
+                fee_denominator: 1_000,
+                authority: Pubkey::new_unique(),
+                bump,
+                protocol_fee_numerator: 0,
+                protocol_fee_denominator: 1,
+                protocol_fee_recipient: Pubkey::default(),
+                fee_growth_a: 0,
+                fee_growth_b: 0,
+                curve_type: CurveType::ConstantProduct,
+                amp_initial: 0,
+                amp_target: 0,
+                amp_ramp_start: 0,
+                amp_ramp_end: 0,
+                last_observation: Observation::default(),
+                dynamic_fee: None,
+                volatility_accumulator: 0,
+                volatility_updated_at: 0,
+                pending_authority: Pubkey::default(),
+                paused: false,
+                flash_loan_a: 0,
+                flash_loan_b: 0,
+                flash_fee_a: 0,
+                flash_fee_b: 0,
+            };
+            let mut pool_data = Vec::new();
+            pool.try_serialize(&mut pool_data).unwrap();
+
+            Fixture {
+                pool: account(pool_key, crate::ID, pool_data, false, false),
+                pool_authority: account(authority_key, system_program::ID, Vec::new(), false, false),
+                token_a_account: token_account(vault_a, mint_a, authority_key, 1_000_000),
+                token_b_account: token_account(vault_b, mint_b, authority_key, 1_000_000),
+                fake_token_a_account: token_account(Pubkey::new_unique(), mint_a, authority_key, 1),
+                lp_mint: mint(lp_mint, authority_key, 1_000_000),
+                token_a_mint: mint(mint_a, Pubkey::new_unique(), 0),
+                token_b_mint: mint(mint_b, Pubkey::new_unique(), 0),
+                user: account(user, system_program::ID, Vec::new(), true, false),
+                user_token_a: token_account(Pubkey::new_unique(), mint_a, user, 1_000_000),
+                user_token_b: token_account(Pubkey::new_unique(), mint_b, user, 1_000_000),
+                user_lp_token: token_account(Pubkey::new_unique(), lp_mint, user, 0),
+                locked_lp_token: token_account(locked_lp, lp_mint, authority_key, MINIMUM_LIQUIDITY),
+                no_oracle: account(crate::ID, Pubkey::default(), Vec::new(), false, true),
+                token_program: account(spl_token::ID, Pubkey::default(), Vec::new(), false, true),
+            }
+        }
+
+        fn token_a_vault(&self, fake: bool) -> AccountInfo<'static> {
+            if fake {
+                self.fake_token_a_account.clone()
+            } else {
+                self.token_a_account.clone()
+            }
+        }
+
+        fn add_liquidity(&self, fake: bool) -> Vec<AccountInfo<'static>> {
+            vec![
+                self.pool.clone(),
+                self.token_a_vault(fake),
+                self.token_b_account.clone(),
+                self.lp_mint.clone(),
+                self.user_token_a.clone(),
+                self.user_token_b.clone(),
+                self.user_lp_token.clone(),
+                self.locked_lp_token.clone(),
+                self.no_oracle.clone(),
+                self.pool_authority.clone(),
+                self.user.clone(),
+                self.token_a_mint.clone(),
+                self.token_b_mint.clone(),
+                self.token_program.clone(),
+                self.token_program.clone(),
+                self.token_program.clone(),
+            ]
+        }
+
+        fn remove_liquidity(&self, fake: bool) -> Vec<AccountInfo<'static>> {
+            let mut accounts = self.add_liquidity(fake);
+            // Same layout without the locked LP account
+            accounts.remove(7);
+            accounts
+        }
+
+        fn single_sided(&self, fake: bool) -> Vec<AccountInfo<'static>> {
+            vec![
+                self.pool.clone(),
+                self.token_a_vault(fake),
+                self.token_b_account.clone(),
+                self.lp_mint.clone(),
+                self.user_token_a.clone(),
+                self.user_lp_token.clone(),
+                self.no_oracle.clone(),
+                self.pool_authority.clone(),
+                self.user.clone(),
+                self.token_a_mint.clone(),
+                self.token_b_mint.clone(),
+                self.token_program.clone(),
+                self.token_program.clone(),
+                self.token_program.clone(),
+            ]
+        }
+    }
+
+    fn try_accounts<'info, T: Accounts<'info>>(accounts: &[AccountInfo<'info>], ix_data: &[u8]) -> Result<T> {
+        let mut accounts = accounts;
+        T::try_accounts(&crate::ID, &mut accounts, ix_data, &mut BTreeMap::new(), &mut BTreeSet::new())
+    }
+
+    fn is_constraint_address<T>(result: Result<T>) -> bool {
+        result.is_err_and(|err| {
+            ProgramError::from(err) == ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintAddress.into())
+        })
+    }
+
+    #[test]
+    fn single_sided_liquidity_rejects_a_foreign_vault() {
+        let fixture = Fixture::new();
+        let token_in = fixture.token_a_mint.key().try_to_vec().unwrap();
+
+        assert!(try_accounts::<AddLiquiditySingleSided>(&fixture.single_sided(false), &token_in).is_ok());
+        assert!(is_constraint_address(try_accounts::<AddLiquiditySingleSided>(
+            &fixture.single_sided(true),
+            &token_in,
+        )));
+        assert!(try_accounts::<RemoveLiquiditySingleSided>(&fixture.single_sided(false), &token_in).is_ok());
+        assert!(is_constraint_address(try_accounts::<RemoveLiquiditySingleSided>(
+            &fixture.single_sided(true),
+            &token_in,
+        )));
+    }
+    #[test]
+    fn liquidity_rejects_a_foreign_vault() {
+        let fixture = Fixture::new();
+
+        assert!(try_accounts::<AddLiquidity>(&fixture.add_liquidity(false), &[]).is_ok());
+        assert!(is_constraint_address(try_accounts::<AddLiquidity>(&fixture.add_liquidity(true), &[])));
+        assert!(try_accounts::<RemoveLiquidity>(&fixture.remove_liquidity(false), &[]).is_ok());
+        assert!(is_constraint_address(try_accounts::<RemoveLiquidity>(
+            &fixture.remove_liquidity(true),
+            &[],
+        )));
+    }
+    fn concentrated_pool() -> (ConcentratedPool, Vec<TickArray>) {
+        let pool = ConcentratedPool {
+            fee_numerator: 3,
+            fee_denominator: 1_000,
+            tick_spacing: 10,
+            sqrt_price: sqrt_price_from_tick(0).unwrap(),
+            // Fees earned before any of the test positions existed
+            fee_growth_global_a: 5 << 64,
+            ..Default::default()
+        };
+        let tick_arrays = [-600, 0]
+            .iter()
+            .map(|start| TickArray {
+                pool: Pubkey::default(),
+                start_tick_index: *start,
+                ticks: vec![Tick::default(); TICK_ARRAY_SIZE as usize],
+            })
+            .collect();
+        (pool, tick_arrays)
+    }
+
+    fn modify(
+        pool: &mut ConcentratedPool,
+        tick_arrays: &mut [TickArray],
+        position: &mut ConcentratedPosition,
+        liquidity_delta: i128,
+    ) -> Result<()> {
+        let (lower, upper) = tick_arrays.split_at_mut(1);
+        modify_position_liquidity(pool, position, &mut lower[0], &mut upper[0], liquidity_delta)
+    }
+
+    fn position(tick_lower: i32, tick_upper: i32) -> ConcentratedPosition {
+        ConcentratedPosition { tick_lower, tick_upper, ..Default::default() }
+    }
+
+    #[test]
+    fn full_burn_settles_fees_before_clearing_ticks() {
+        // In range: only fees accrued after the mint are owed
+        let (mut pool, mut tick_arrays) = concentrated_pool();
+        let mut in_range = position(-100, 100);
+        modify(&mut pool, &mut tick_arrays, &mut in_range, 1_000).unwrap();
+        pool.fee_growth_global_a += 2 << 64;
+        modify(&mut pool, &mut tick_arrays, &mut in_range, -1_000).unwrap();
+        assert_eq!((in_range.liquidity, in_range.fees_owed_a), (0, 2_000));
+        assert!(!tick_arrays[0].tick(&pool, -100).unwrap().initialized);
+        assert!(!tick_arrays[1].tick(&pool, 100).unwrap().initialized);
+        assert_eq!(pool.liquidity, 0);
+
+        // Below the price, sharing its lower tick with a live position: the
+        // burn clears only the upper tick and earns nothing
+        let (mut pool, mut tick_arrays) = concentrated_pool();
+        let mut shared = position(-100, 10);
+        let mut below = position(-100, 0);
+        modify(&mut pool, &mut tick_arrays, &mut shared, 1_000).unwrap();
+        modify(&mut pool, &mut tick_arrays, &mut below, 1_000).unwrap();
+        pool.fee_growth_global_a += 2 << 64;
+        modify(&mut pool, &mut tick_arrays, &mut below, -1_000).unwrap();
+        assert_eq!((below.liquidity, below.fees_owed_a), (0, 0));
+        assert!(tick_arrays[0].tick(&pool, -100).unwrap().initialized);
+        assert!(!tick_arrays[1].tick(&pool, 0).unwrap().initialized);
+
+        modify(&mut pool, &mut tick_arrays, &mut shared, -1_000).unwrap();
+        assert_eq!(shared.fees_owed_a, 2_000);
+    }
+
+    #[test]
+    fn concentrated_and_weighted_pools_reject_token_2022_mints() {
+        let authority = Pubkey::new_unique();
+        let legacy = mint(Pubkey::new_unique(), authority, 0);
+        assert!(legacy_mint(&legacy).is_ok());
+
+        let token_2022 = account(
+            Pubkey::new_unique(),
+            anchor_spl::token_2022::ID,
+            legacy.try_borrow_data().unwrap().to_vec(),
+            false,
+            false,
+        );
+        assert!(legacy_mint(&token_2022).is_err_and(|err| {
+            ProgramError::from(err) == ProgramError::Custom(ErrorCode::UnsupportedTokenProgram.into())
+        }));
+    }
 }
@@ -0,0 +1,267 @@
+/// LP tokens permanently locked on the first deposit. Making the first
+/// depositor give up a fixed slice of supply means inflating the share price
+/// through donations costs the attacker far more than it can steal.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Integer square root (floor) using Newton's method.
+pub fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    let mut x0 = value;
+    let mut x1 = value >> 1;
+    while x1 < x0 {
+        x0 = x1;
+        x1 = (x1 + value / x1) >> 1;
+    }
+    x0
+}
+
+/// LP tokens for the very first deposit into an empty pool.
+///
+/// Returns `(user_lp_tokens, locked_lp_tokens)`, or `None` if the deposit is
+/// too small to cover `MINIMUM_LIQUIDITY`.
+pub fn initial_lp_tokens(amount_a: u64, amount_b: u64) -> Option<(u64, u64)> {
+    let liquidity = integer_sqrt((amount_a as u128).checked_mul(amount_b as u128)?);
+    let liquidity = u64::try_from(liquidity).ok()?;
+    if liquidity <= MINIMUM_LIQUIDITY {
+        return None;
+    }
+    Some((liquidity - MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY))
+}
+
+/// LP tokens for a deposit into a pool that already has liquidity, rounded
+/// down in favour of existing LPs.
+pub fn proportional_lp_tokens(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    total_supply: u64,
+) -> Option<u64> {
+    let lp_from_a = (amount_a as u128)
+        .checked_mul(total_supply as u128)?
+        .checked_div(reserve_a as u128)?;
+    let lp_from_b = (amount_b as u128)
+        .checked_mul(total_supply as u128)?
+        .checked_div(reserve_b as u128)?;
+    u64::try_from(std::cmp::min(lp_from_a, lp_from_b)).ok()
+}
+
+/// Largest `(amount_a, amount_b)` within the given maxima that matches the
+/// current reserve ratio. The dependent side is rounded up so the pool never
+/// receives less than its ratio requires.
+pub fn optimal_deposit(
+    max_amount_a: u64,
+    max_amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Option<(u64, u64)> {
+    let amount_b_optimal = mul_div_ceil(max_amount_a, reserve_b, reserve_a)?;
+    if amount_b_optimal <= max_amount_b {
+        return Some((max_amount_a, amount_b_optimal));
+    }
+    let amount_a_optimal = mul_div_ceil(max_amount_b, reserve_a, reserve_b)?;
+    Some((amount_a_optimal.min(max_amount_a), max_amount_b))
+}
+
+/// `a * b / c`, rounded up.
+pub fn mul_div_ceil(a: u64, b: u64, c: u64) -> Option<u64> {
+    if c == 0 {
+        return None;
+    }
+    let product = (a as u128).checked_mul(b as u128)?;
+    let result = product.checked_add(c as u128 - 1)? / c as u128;
+    u64::try_from(result).ok()
+}
+
+/// Constant-product output for `amount_in`, charging
+/// `fee_numerator / fee_denominator` on the input.
+pub fn swap_amount_out(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u64> {
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator)? as u128;
+    let amount_in_with_fee = (amount_in as u128).checked_mul(fee_multiplier)?;
+    let numerator = amount_in_with_fee.checked_mul(reserve_out as u128)?;
+    let denominator = (reserve_in as u128)
+        .checked_mul(fee_denominator as u128)?
+        .checked_add(amount_in_with_fee)?;
+    if denominator == 0 {
+        return None;
+    }
+    u64::try_from(numerator / denominator).ok()
+}
+
+/// Splits the fee charged on `amount_in` into `(lp_fee, protocol_fee)`.
+pub fn split_swap_fee(
+    amount_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    protocol_fee_numerator: u64,
+    protocol_fee_denominator: u64,
+) -> Option<(u64, u64)> {
+    let fee = (amount_in as u128)
+        .checked_mul(fee_numerator as u128)?
+        .checked_div(fee_denominator as u128)?;
+    let protocol_fee = fee
+        .checked_mul(protocol_fee_numerator as u128)?
+        .checked_div(protocol_fee_denominator as u128)?;
+    Some((u64::try_from(fee - protocol_fee).ok()?, u64::try_from(protocol_fee).ok()?))
+}
+
+/// Fee growth per LP token contributed by `fee`, as a Q64.64 number.
+pub fn fee_growth_delta(fee: u64, total_supply: u64) -> Option<u128> {
+    ((fee as u128) << 64).checked_div(total_supply as u128)
+}
+
+/// How much of a single-sided `amount_in` to swap so that the remainder and
+/// the swap output match the post-swap reserve ratio:
+///
+/// `s = (sqrt(R^2 (d + r)^2 + 4 r d R a) - R (d + r)) / 2r`
+///
+/// where `r = d - n` is the fee multiplier. Very large reserves are scaled
+/// down until the intermediate products fit in a u128; the result only
+/// decides how the deposit is split, so the lost precision costs the user
+/// dust at most and never the pool.
+pub fn optimal_swap_amount(
+    amount_in: u64,
+    reserve_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u64> {
+    let r = fee_denominator.checked_sub(fee_numerator)? as u128;
+    let d = fee_denominator as u128;
+    if r == 0 {
+        return None;
+    }
+
+    for shift in 0..64 {
+        let reserve = (reserve_in >> shift) as u128;
+        let amount = (amount_in >> shift) as u128;
+        let solve = || -> Option<u128> {
+            let b = reserve.checked_mul(d + r)?;
+            let discriminant = b
+                .checked_mul(b)?
+                .checked_add(r.checked_mul(d)?.checked_mul(4)?.checked_mul(reserve)?.checked_mul(amount)?)?;
+            Some((integer_sqrt(discriminant) - b) / (2 * r))
+        };
+        if let Some(scaled) = solve() {
+            let swap_amount = scaled.checked_shl(shift)?;
+            return u64::try_from(swap_amount.min(amount_in as u128)).ok();
+        }
+    }
+    None
+}
+
+/// Share of `reserve` owed to the holder of `lp_amount` out of `total_supply`,
+/// rounded down in favour of the pool.
+pub fn withdraw_amount(lp_amount: u64, reserve: u64, total_supply: u64) -> Option<u64> {
+    let amount = (lp_amount as u128)
+        .checked_mul(reserve as u128)?
+        .checked_div(total_supply as u128)?;
+    u64::try_from(amount).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_sqrt_is_floor() {
+        assert_eq!(integer_sqrt(0), 0);
+        assert_eq!(integer_sqrt(1), 1);
+        assert_eq!(integer_sqrt(3), 1);
+        assert_eq!(integer_sqrt(4), 2);
+        assert_eq!(integer_sqrt(99), 9);
+        assert_eq!(integer_sqrt(u64::MAX as u128 * u64::MAX as u128), u64::MAX as u128);
+        for v in [2u128, 10, 1_000_001, 123_456_789_012_345_678] {
+            let r = integer_sqrt(v);
+            assert!(r * r <= v && (r + 1) * (r + 1) > v);
+        }
+    }
+
+    #[test]
+    fn first_deposit_locks_minimum_liquidity() {
+        assert_eq!(initial_lp_tokens(1_000_000, 4_000_000), Some((1_999_000, MINIMUM_LIQUIDITY)));
+        assert_eq!(initial_lp_tokens(1_000, 1_000), None);
+        assert_eq!(initial_lp_tokens(0, 1_000_000), None);
+    }
+
+    #[test]
+    fn optimal_deposit_only_takes_the_pool_ratio() {
+        // Pool is 1:2, user offers too much B
+        assert_eq!(optimal_deposit(100, 500, 1_000, 2_000), Some((100, 200)));
+        // User offers too much A
+        assert_eq!(optimal_deposit(100, 100, 1_000, 2_000), Some((50, 100)));
+        // Dependent side rounds up
+        assert_eq!(optimal_deposit(1, 10, 3, 2), Some((1, 1)));
+        assert_eq!(optimal_deposit(10, 10, 0, 0), None);
+    }
+
+    #[test]
+    fn optimal_swap_leaves_a_balanced_deposit() {
+        let (fee_n, fee_d) = (3, 1_000);
+        for (reserve_a, reserve_b, amount_in) in [
+            (1_000_000u64, 4_000_000u64, 50_000u64),
+            (5_000_000_000, 5_000_000_000, 5_000_000_000),
+            (1_000_000_000_000_000_000, 500_000_000_000_000_000, 100_000_000_000_000_000),
+        ] {
+            let swap = optimal_swap_amount(amount_in, reserve_a, fee_n, fee_d).unwrap();
+            let out = swap_amount_out(swap, reserve_a, reserve_b, fee_n, fee_d).unwrap();
+            let remaining = amount_in - swap;
+
+            // remaining / (reserve_a + swap) == out / (reserve_b - out), within rounding
+            let lhs = remaining as u128 * (reserve_b - out) as u128;
+            let rhs = out as u128 * (reserve_a + swap) as u128;
+            let diff = lhs.abs_diff(rhs);
+            assert!(diff * 10_000 <= lhs.max(rhs), "unbalanced zap for {amount_in}");
+        }
+    }
+
+    #[test]
+    fn swap_fee_is_split_between_lps_and_protocol() {
+        // 0.3% fee, protocol takes 1/6 of it
+        assert_eq!(split_swap_fee(1_000_000, 3, 1_000, 1, 6), Some((2_500, 500)));
+        assert_eq!(split_swap_fee(1_000_000, 3, 1_000, 0, 1), Some((3_000, 0)));
+        assert_eq!(fee_growth_delta(3_000, 1_000), Some(3u128 << 64));
+    }
+
+    #[test]
+    fn donation_attack_is_unprofitable() {
+        // Attacker seeds the pool with the smallest deposit that is accepted.
+        let seed = MINIMUM_LIQUIDITY + 1;
+        let (attacker_lp, locked_lp) = initial_lp_tokens(seed, seed).unwrap();
+        assert_eq!(attacker_lp, 1);
+        let mut supply = attacker_lp + locked_lp;
+
+        // Then donates straight into the vaults to inflate the share price.
+        let donation = 1_000_000_000_000u64;
+        let reserve_a = seed + donation;
+        let reserve_b = seed + donation;
+        let attacker_cost = seed + donation;
+
+        // The victim's deposit is rounded down against the inflated price.
+        let victim_deposit = 500_000_000_000u64;
+        let victim_lp = proportional_lp_tokens(
+            victim_deposit,
+            victim_deposit,
+            reserve_a,
+            reserve_b,
+            supply,
+        )
+        .unwrap();
+        supply += victim_lp;
+        let reserve_a = reserve_a + victim_deposit;
+
+        // Whatever the attacker redeems is worth less than what they put in,
+        // because the locked supply owns almost all of the donation.
+        let attacker_out = withdraw_amount(attacker_lp, reserve_a, supply).unwrap();
+        assert!(attacker_out < attacker_cost);
+        assert!(attacker_cost - attacker_out > victim_deposit);
+    }
+}
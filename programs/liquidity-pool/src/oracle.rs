@@ -0,0 +1,18 @@
+//! Per-pool observation history. The accumulators and ring buffer come
+//! from the shared `price_oracle` crate; this is the account holding them.
+
+use anchor_lang::prelude::*;
+
+pub use price_oracle::*;
+
+#[account]
+pub struct PriceOracle {
+    pub pool: Pubkey,
+    /// Past readings of the pool's price accumulators
+    pub ring: ObservationRing,
+}
+
+impl PriceOracle {
+    pub const LEN: usize = 32 + // pool
+                           ObservationRing::LEN; // ring
+}
@@ -0,0 +1,332 @@
+//! Fixed-point math for concentrated liquidity pools. Prices are stored as
+//! `sqrt(price)` in Q64.64, where price is token B per token A and each tick
+//! is a 1 bp (1.0001x) step in price.
+
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+
+/// `sqrt_price_from_tick(MIN_TICK)`
+pub const MIN_SQRT_PRICE: u128 = 4_295_048_016;
+/// `sqrt_price_from_tick(MAX_TICK)`
+pub const MAX_SQRT_PRICE: u128 = 79_226_673_521_066_979_257_578_248_091;
+
+const Q64: u128 = 1 << 64;
+
+/// `2^64 / sqrt(1.0001)^(2^i)` for i in 0..19
+const TICK_RATIOS: [u128; 19] = [
+    0xfffcb933bd6fad37,
+    0xfff97272373d4132,
+    0xfff2e50f5f656932,
+    0xffe5caca7e10e4e6,
+    0xffcb9843d60f6159,
+    0xff973b41fa98c081,
+    0xff2ea16466c96a38,
+    0xfe5dee046a99a2a8,
+    0xfcbe86c7900a88ae,
+    0xf987a7253ac41317,
+    0xf3392b0822b70005,
+    0xe7159475a2c29b74,
+    0xd097f3bdfd2022b8,
+    0xa9f746462d870fdf,
+    0x70d869a156d2a1b8,
+    0x31be135f97d08fd9,
+    0x9aa508b5b7a84e1,
+    0x5d6af8dedb8119,
+    0x2216e584f5fa,
+];
+
+/// 256-bit product of two u128 values as `(high, low)`.
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & mask);
+    let (b_hi, b_lo) = (b >> 64, b & mask);
+
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_hi = a_hi * b_hi;
+
+    let cross = (lo_lo >> 64) + (hi_lo & mask) + (lo_hi & mask);
+    let low = (cross << 64) | (lo_lo & mask);
+    let high = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (cross >> 64);
+    (high, low)
+}
+
+/// `a * b / denominator` with a 256-bit intermediate, returning the quotient
+/// and whether there was a remainder. `None` if the result exceeds u128.
+fn mul_div(a: u128, b: u128, denominator: u128) -> Option<(u128, bool)> {
+    if denominator == 0 {
+        return None;
+    }
+    let (high, low) = full_mul(a, b);
+    if high == 0 {
+        return Some((low / denominator, low % denominator != 0));
+    }
+    if high >= denominator {
+        return None;
+    }
+
+    // Schoolbook long division of (high, low) by the denominator
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for i in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> i) & 1);
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1 << i;
+        }
+    }
+    Some((quotient, remainder != 0))
+}
+
+pub fn mul_div_floor(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    mul_div(a, b, denominator).map(|(quotient, _)| quotient)
+}
+
+pub fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    let (quotient, has_remainder) = mul_div(a, b, denominator)?;
+    quotient.checked_add(has_remainder as u128)
+}
+
+/// `sqrt(1.0001^tick)` as Q64.64.
+pub fn sqrt_price_from_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 { TICK_RATIOS[0] } else { Q64 };
+    for (i, tick_ratio) in TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            // Both factors are below 2^64, so the product fits in a u128
+            ratio = (ratio * tick_ratio) >> 64;
+        }
+    }
+
+    if tick > 0 {
+        ratio = u128::MAX / ratio;
+    }
+    Some(ratio)
+}
+
+/// Greatest tick whose sqrt price is at or below `sqrt_price`.
+pub fn tick_from_sqrt_price(sqrt_price: u128) -> Option<i32> {
+    if !(MIN_SQRT_PRICE..=MAX_SQRT_PRICE).contains(&sqrt_price) {
+        return None;
+    }
+
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_from_tick(mid)? <= sqrt_price {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(low)
+}
+
+/// Token A needed to move `liquidity` between two sqrt prices:
+/// `L * (upper - lower) / (upper * lower)`.
+pub fn amount_a_delta(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u64> {
+    let (lower, upper) = sorted(sqrt_price_0, sqrt_price_1);
+    if lower == 0 {
+        return None;
+    }
+    let amount = if round_up {
+        let scaled = mul_div_ceil(liquidity, upper - lower, upper)?;
+        mul_div_ceil(scaled, Q64, lower)?
+    } else {
+        let scaled = mul_div_floor(liquidity, upper - lower, upper)?;
+        mul_div_floor(scaled, Q64, lower)?
+    };
+    u64::try_from(amount).ok()
+}
+
+/// Token B needed to move `liquidity` between two sqrt prices:
+/// `L * (upper - lower)`.
+pub fn amount_b_delta(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u64> {
+    let (lower, upper) = sorted(sqrt_price_0, sqrt_price_1);
+    let amount = if round_up {
+        mul_div_ceil(liquidity, upper - lower, Q64)?
+    } else {
+        mul_div_floor(liquidity, upper - lower, Q64)?
+    };
+    u64::try_from(amount).ok()
+}
+
+/// Sqrt price after adding `amount_in` of the input token at `liquidity`,
+/// rounded so the pool never gives away more than the input pays for.
+pub fn next_sqrt_price_from_input(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount_in: u64,
+    a_to_b: bool,
+) -> Option<u128> {
+    if amount_in == 0 {
+        return Some(sqrt_price);
+    }
+    if a_to_b {
+        // L * sqrt_p / (L + amount * sqrt_p)
+        let product = mul_div_ceil(amount_in as u128, sqrt_price, Q64)?;
+        let denominator = liquidity.checked_add(product)?;
+        mul_div_ceil(liquidity, sqrt_price, denominator)
+    } else {
+        // sqrt_p + amount / L
+        let delta = mul_div_floor(amount_in as u128, Q64, liquidity)?;
+        sqrt_price.checked_add(delta)
+    }
+}
+
+/// Result of swapping within a single liquidity range.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: u128,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+}
+
+/// Swaps as much of `amount_remaining` (exact input, fee included) as fits
+/// before the price reaches `sqrt_price_target`.
+pub fn compute_swap_step(
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<SwapStep> {
+    let a_to_b = sqrt_price_target <= sqrt_price_current;
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator)?;
+    let amount_less_fee = u64::try_from(
+        (amount_remaining as u128)
+            .checked_mul(fee_multiplier as u128)?
+            .checked_div(fee_denominator as u128)?,
+    )
+    .ok()?;
+
+    let amount_to_target = if a_to_b {
+        amount_a_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+    } else {
+        amount_b_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+    };
+
+    let reaches_target = amount_less_fee >= amount_to_target;
+    let sqrt_price_next = if reaches_target {
+        sqrt_price_target
+    } else {
+        next_sqrt_price_from_input(sqrt_price_current, liquidity, amount_less_fee, a_to_b)?
+    };
+
+    let (amount_in, amount_out) = if a_to_b {
+        (
+            amount_a_delta(sqrt_price_next, sqrt_price_current, liquidity, true)?,
+            amount_b_delta(sqrt_price_next, sqrt_price_current, liquidity, false)?,
+        )
+    } else {
+        (
+            amount_b_delta(sqrt_price_current, sqrt_price_next, liquidity, true)?,
+            amount_a_delta(sqrt_price_current, sqrt_price_next, liquidity, false)?,
+        )
+    };
+
+    let fee_amount = if reaches_target {
+        u64::try_from(mul_div_ceil(
+            amount_in as u128,
+            fee_numerator as u128,
+            fee_multiplier as u128,
+        )?)
+        .ok()?
+    } else {
+        // Whatever input is left over once the price has moved is the fee
+        amount_remaining.checked_sub(amount_in)?
+    };
+
+    Some(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+fn sorted(a: u128, b: u128) -> (u128, u128) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_handles_256_bit_products() {
+        assert_eq!(mul_div_floor(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(mul_div_floor(1 << 100, 1 << 100, 1 << 90), Some(1 << 110));
+        assert_eq!(mul_div_ceil(7, 3, 2), Some(11));
+        assert_eq!(mul_div_floor(u128::MAX, 2, 1), None);
+    }
+
+    #[test]
+    fn sqrt_price_bounds_and_monotonicity() {
+        assert_eq!(sqrt_price_from_tick(0), Some(Q64));
+        assert_eq!(sqrt_price_from_tick(MIN_TICK), Some(MIN_SQRT_PRICE));
+        assert_eq!(sqrt_price_from_tick(MAX_TICK), Some(MAX_SQRT_PRICE));
+        assert_eq!(sqrt_price_from_tick(MAX_TICK + 1), None);
+
+        let mut previous = 0;
+        for tick in (MIN_TICK..=MAX_TICK).step_by(997) {
+            let sqrt_price = sqrt_price_from_tick(tick).unwrap();
+            assert!(sqrt_price > previous);
+            previous = sqrt_price;
+        }
+    }
+
+    #[test]
+    fn tick_round_trips_through_sqrt_price() {
+        for tick in [MIN_TICK, -100_000, -1, 0, 1, 12_345, MAX_TICK] {
+            let sqrt_price = sqrt_price_from_tick(tick).unwrap();
+            assert_eq!(tick_from_sqrt_price(sqrt_price), Some(tick));
+            if tick < MAX_TICK {
+                assert_eq!(tick_from_sqrt_price(sqrt_price + 1), Some(tick));
+            }
+        }
+    }
+
+    #[test]
+    fn swap_step_never_overpays() {
+        let liquidity = 1_000_000_000_000u128;
+        let current = sqrt_price_from_tick(0).unwrap();
+        let lower = sqrt_price_from_tick(-100).unwrap();
+
+        // Partial step: the whole input is consumed
+        let step = compute_swap_step(current, lower, liquidity, 1_000_000, 3, 1_000).unwrap();
+        assert!(step.sqrt_price_next < current && step.sqrt_price_next > lower);
+        assert_eq!(step.amount_in + step.fee_amount, 1_000_000);
+        assert!(step.amount_out < 1_000_000);
+
+        // Full step: stops exactly at the target
+        let step = compute_swap_step(current, lower, liquidity, u64::MAX / 2, 3, 1_000).unwrap();
+        assert_eq!(step.sqrt_price_next, lower);
+        assert_eq!(
+            step.amount_in,
+            amount_a_delta(lower, current, liquidity, true).unwrap()
+        );
+    }
+}
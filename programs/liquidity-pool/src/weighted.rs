@@ -0,0 +1,86 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+
+use crate::weighted_math::MAX_TOKENS;
+use crate::ErrorCode;
+
+/// Balancer-style pool holding 2 to `MAX_TOKENS` tokens at fixed weights.
+/// Uses the same fee configuration as `LiquidityPool`.
+#[account]
+#[derive(Default)]
+pub struct WeightedPool {
+    /// Authority of the pool
+    pub authority: Pubkey,
+    /// Bump seed for the pool authority PDA
+    pub bump: u8,
+    /// LP token mint
+    pub lp_mint: Pubkey,
+    /// Fee numerator (fee = numerator / denominator)
+    pub fee_numerator: u64,
+    /// Fee denominator
+    pub fee_denominator: u64,
+    /// Number of tokens in use; entries past this are unused
+    pub token_count: u8,
+    /// Token mints, in ascending key order
+    pub mints: [Pubkey; MAX_TOKENS],
+    /// Vault for each mint, set by `initialize_weighted_vault`
+    pub vaults: [Pubkey; MAX_TOKENS],
+    /// Normalized weight of each mint, summing to `weighted_math::ONE`
+    pub weights: [u64; MAX_TOKENS],
+    /// Whether swaps and joins are stopped
+    pub paused: bool,
+}
+
+impl WeightedPool {
+    pub const LEN: usize = 32 + // authority
+                           1 +  // bump
+                           32 + // lp_mint
+                           8 +  // fee_numerator
+                           8 +  // fee_denominator
+                           1 +  // token_count
+                           32 * MAX_TOKENS + // mints
+                           32 * MAX_TOKENS + // vaults
+                           8 * MAX_TOKENS + // weights
+                           1;   // paused
+
+    pub fn mints(&self) -> &[Pubkey] {
+        &self.mints[..self.token_count as usize]
+    }
+
+    pub fn weights(&self) -> &[u64] {
+        &self.weights[..self.token_count as usize]
+    }
+
+    /// Index of the token held in `vault`
+    pub fn vault_index(&self, vault: &Pubkey) -> Result<usize> {
+        self.vaults[..self.token_count as usize]
+            .iter()
+            .position(|key| key == vault && *key != Pubkey::default())
+            .ok_or_else(|| error!(ErrorCode::InvalidMint))
+    }
+}
+
+/// Reads `(vault, user token account)` pairs for every pool token, in pool
+/// order, from remaining accounts.
+pub fn load_weighted_token_accounts<'info>(
+    pool: &WeightedPool,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<Vec<(Account<'info, TokenAccount>, Account<'info, TokenAccount>)>> {
+    let token_count = pool.token_count as usize;
+    require!(
+        remaining_accounts.len() == 2 * token_count,
+        ErrorCode::InvalidMint
+    );
+
+    remaining_accounts
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let vault = Account::<TokenAccount>::try_from(&pair[0])?;
+            let user_token = Account::<TokenAccount>::try_from(&pair[1])?;
+            require_keys_eq!(vault.key(), pool.vaults[i], ErrorCode::InvalidMint);
+            require_keys_eq!(user_token.mint, pool.mints[i], ErrorCode::InvalidMint);
+            Ok((vault, user_token))
+        })
+        .collect()
+}
@@ -0,0 +1,316 @@
+//! Balancer-style weighted pool math. Weights and intermediate ratios are
+//! 18-decimal fixed point; token balances stay plain integers.
+
+pub const ONE: u128 = 1_000_000_000_000_000_000;
+pub const MIN_WEIGHT: u64 = 10_000_000_000_000_000; // 1%
+pub const MIN_TOKENS: usize = 2;
+pub const MAX_TOKENS: usize = 8;
+/// Largest share of a balance that may go in or out in one operation
+pub const MAX_RATIO: u128 = 300_000_000_000_000_000; // 30%
+
+const LN_2: i128 = 693_147_180_559_945_309;
+const ONE_I: i128 = ONE as i128;
+/// Relative error bound of `pow`, applied to round results against the caller
+const MAX_POW_RELATIVE_ERROR: u128 = 10_000; // 1e-14
+
+/// Natural log of a positive fixed-point number.
+fn ln(x: u128) -> Option<i128> {
+    if x == 0 {
+        return None;
+    }
+
+    // Reduce to m in [1, 2) with x = m * 2^k
+    let (mut m, mut k) = if x >= 2 * ONE {
+        let k = 127 - (x / ONE).leading_zeros() as i128;
+        (x >> k, k)
+    } else {
+        (x, 0)
+    };
+    while m < ONE {
+        m <<= 1;
+        k -= 1;
+    }
+
+    // ln(m) = 2 * atanh((m - 1) / (m + 1)), with the argument below 1/3
+    let m = m as i128;
+    let z = (m - ONE_I) * ONE_I / (m + ONE_I);
+    let z_squared = z * z / ONE_I;
+    let mut term = z;
+    let mut sum = 0i128;
+    let mut i = 0;
+    while term != 0 {
+        sum += term / (2 * i + 1);
+        term = term * z_squared / ONE_I;
+        i += 1;
+    }
+
+    Some(2 * sum + k * LN_2)
+}
+
+/// e^y for a signed fixed-point exponent.
+fn exp(y: i128) -> Option<u128> {
+    // y = k * ln(2) + r with r in [0, ln 2)
+    let k = y.div_euclid(LN_2);
+    let r = y.rem_euclid(LN_2);
+
+    let mut term = ONE_I;
+    let mut sum = ONE_I;
+    let mut i = 1;
+    while term != 0 {
+        term = term * r / (i * ONE_I);
+        sum += term;
+        i += 1;
+    }
+
+    let sum = sum as u128;
+    if k >= 0 {
+        let k = u32::try_from(k).ok()?;
+        if k >= sum.leading_zeros() {
+            return None;
+        }
+        Some(sum << k)
+    } else {
+        Some(sum.checked_shr(u32::try_from(-k).ok()?).unwrap_or(0))
+    }
+}
+
+/// `x^y` for fixed-point `x > 0` and `y >= 0`, accurate to within
+/// `MAX_POW_RELATIVE_ERROR`.
+pub fn pow(x: u128, y: u128) -> Option<u128> {
+    if y == 0 || x == ONE {
+        return Some(ONE);
+    }
+    let exponent = ln(x)?.checked_mul(i128::try_from(y).ok()?)? / ONE_I;
+    exp(exponent)
+}
+
+fn pow_up(x: u128, y: u128) -> Option<u128> {
+    let raw = pow(x, y)?;
+    raw.checked_add(raw / (ONE / MAX_POW_RELATIVE_ERROR) + 1)
+}
+
+fn pow_down(x: u128, y: u128) -> Option<u128> {
+    let raw = pow(x, y)?;
+    Some(raw.saturating_sub(raw / (ONE / MAX_POW_RELATIVE_ERROR) + 1))
+}
+
+fn div_down(a: u128, b: u128) -> Option<u128> {
+    a.checked_mul(ONE)?.checked_div(b)
+}
+
+fn div_up(a: u128, b: u128) -> Option<u128> {
+    if b == 0 {
+        return None;
+    }
+    Some(a.checked_mul(ONE)?.checked_add(b - 1)? / b)
+}
+
+/// Checks that weights are individually at least `MIN_WEIGHT` and sum to `ONE`.
+pub fn validate_weights(weights: &[u64]) -> bool {
+    (MIN_TOKENS..=MAX_TOKENS).contains(&weights.len())
+        && weights.iter().all(|weight| *weight >= MIN_WEIGHT)
+        && weights.iter().map(|weight| *weight as u128).sum::<u128>() == ONE
+}
+
+/// Weighted geometric mean of the balances, `prod(b_i ^ w_i)`. Used to size
+/// the LP supply on the first join.
+pub fn invariant(balances: &[u64], weights: &[u64]) -> Option<u64> {
+    let mut log_sum = 0i128;
+    for (balance, weight) in balances.iter().zip(weights) {
+        let ln_balance = ln((*balance as u128).checked_mul(ONE)?)?;
+        log_sum = log_sum.checked_add(ln_balance.checked_mul(*weight as i128)? / ONE_I)?;
+    }
+    u64::try_from(exp(log_sum)? / ONE).ok()
+}
+
+/// Swap output: `b_out * (1 - (b_in / (b_in + a_in)) ^ (w_in / w_out))`, with
+/// the fee taken from the input.
+pub fn out_given_in(
+    balance_in: u64,
+    weight_in: u64,
+    balance_out: u64,
+    weight_out: u64,
+    amount_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u64> {
+    let amount_in = (amount_in as u128)
+        .checked_mul(fee_denominator.checked_sub(fee_numerator)? as u128)?
+        / fee_denominator as u128;
+    if amount_in > balance_in as u128 * MAX_RATIO / ONE {
+        return None;
+    }
+
+    let base = div_up(balance_in as u128, (balance_in as u128).checked_add(amount_in)?)?;
+    let exponent = div_down(weight_in as u128, weight_out as u128)?;
+    let power = pow_up(base, exponent)?;
+    let amount_out = (balance_out as u128).checked_mul(ONE.saturating_sub(power))? / ONE;
+    if amount_out > balance_out as u128 * MAX_RATIO / ONE {
+        return None;
+    }
+    u64::try_from(amount_out).ok()
+}
+
+/// Amount of token `i` owed for (round up) or paid out against (round down)
+/// `lp_amount` out of `total_supply`.
+pub fn proportional_amount(balance: u64, lp_amount: u64, total_supply: u64, round_up: bool) -> Option<u64> {
+    let product = (balance as u128).checked_mul(lp_amount as u128)?;
+    let supply = total_supply as u128;
+    if supply == 0 {
+        return None;
+    }
+    let amount = if round_up {
+        product.div_ceil(supply)
+    } else {
+        product / supply
+    };
+    u64::try_from(amount).ok()
+}
+
+/// LP tokens for a single-asset join:
+/// `supply * ((1 + a_in' / b_i) ^ w_i - 1)`, where only the share of the
+/// input that would otherwise have been swapped (`1 - w_i`) pays the fee.
+pub fn lp_out_given_single_in(
+    balance: u64,
+    weight: u64,
+    total_supply: u64,
+    amount_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u64> {
+    if amount_in as u128 > balance as u128 * MAX_RATIO / ONE {
+        return None;
+    }
+    let taxable = (amount_in as u128).checked_mul(ONE - weight as u128)? / ONE;
+    let fee = taxable.checked_mul(fee_numerator as u128)? / fee_denominator as u128;
+    let amount_in = amount_in as u128 - fee;
+
+    let base = ONE.checked_add(div_down(amount_in, balance as u128)?)?;
+    let power = pow_down(base, weight as u128)?;
+    let lp_out = (total_supply as u128).checked_mul(power.saturating_sub(ONE))? / ONE;
+    u64::try_from(lp_out).ok()
+}
+
+/// Token `i` paid out for a single-asset exit:
+/// `b_i * (1 - (1 - lp / supply) ^ (1 / w_i))`, less the fee on the
+/// non-proportional share.
+pub fn single_out_given_lp_in(
+    balance: u64,
+    weight: u64,
+    total_supply: u64,
+    lp_amount: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u64> {
+    if lp_amount as u128 > total_supply as u128 * MAX_RATIO / ONE {
+        return None;
+    }
+    let base = ONE.checked_sub(div_up(lp_amount as u128, total_supply as u128)?)?;
+    let exponent = div_down(ONE, weight as u128)?;
+    let power = pow_up(base, exponent)?;
+    let amount_out = (balance as u128).checked_mul(ONE.saturating_sub(power))? / ONE;
+
+    let taxable = amount_out.checked_mul(ONE - weight as u128)? / ONE;
+    let fee = taxable
+        .checked_mul(fee_numerator as u128)?
+        .div_ceil(fee_denominator as u128);
+    u64::try_from(amount_out.saturating_sub(fee)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: u128, expected: u128, tolerance: u128) {
+        let diff = actual.abs_diff(expected);
+        assert!(diff <= tolerance, "{actual} not within {tolerance} of {expected}");
+    }
+
+    #[test]
+    fn ln_and_exp_match_known_values() {
+        assert_eq!(ln(ONE), Some(0));
+        assert_close(ln(2 * ONE).unwrap() as u128, LN_2 as u128, 10);
+        assert_close(ln(ONE / 2).unwrap().unsigned_abs(), LN_2 as u128, 10);
+        assert_close(exp(ONE_I).unwrap(), 2_718_281_828_459_045_235, 100);
+        assert_close(exp(-ONE_I).unwrap(), 367_879_441_171_442_321, 100);
+    }
+
+    #[test]
+    fn pow_handles_fractional_exponents() {
+        // 4^0.5 = 2, 0.25^0.5 = 0.5, 2^10 = 1024
+        assert_close(pow(4 * ONE, ONE / 2).unwrap(), 2 * ONE, 1_000);
+        assert_close(pow(ONE / 4, ONE / 2).unwrap(), ONE / 2, 1_000);
+        assert_close(pow(2 * ONE, 10 * ONE).unwrap(), 1_024 * ONE, 1_000_000);
+    }
+
+    #[test]
+    fn weights_must_be_normalized() {
+        assert!(validate_weights(&[ONE as u64 / 2, ONE as u64 / 2]));
+        assert!(validate_weights(&[800_000_000_000_000_000, 200_000_000_000_000_000]));
+        assert!(!validate_weights(&[ONE as u64]));
+        assert!(!validate_weights(&[ONE as u64 / 2, ONE as u64 / 4]));
+        assert!(!validate_weights(&[ONE as u64 - 1_000, 1_000]));
+        assert!(!validate_weights(&[ONE as u64 / 9; 9]));
+    }
+
+    #[test]
+    fn equal_weights_match_constant_product() {
+        let half = ONE as u64 / 2;
+        let out = out_given_in(1_000_000_000, half, 2_000_000_000, half, 10_000_000, 0, 1).unwrap();
+        let constant_product = crate::math::swap_amount_out(10_000_000, 1_000_000_000, 2_000_000_000, 0, 1).unwrap();
+        assert!(out <= constant_product);
+        assert!(constant_product - out <= 1);
+    }
+
+    #[test]
+    fn heavier_output_weight_gives_less_slippage() {
+        // 80/20 pool quoted at the same spot price as a 50/50 pool
+        let out_80_20 = out_given_in(
+            1_000_000_000,
+            200_000_000_000_000_000,
+            4_000_000_000,
+            800_000_000_000_000_000,
+            100_000_000,
+            3,
+            1_000,
+        )
+        .unwrap();
+        let out_50_50 = out_given_in(1_000_000_000, ONE as u64 / 2, 1_000_000_000, ONE as u64 / 2, 100_000_000, 3, 1_000).unwrap();
+        assert!(out_80_20 > out_50_50);
+        assert!(out_given_in(1_000, ONE as u64 / 2, 1_000, ONE as u64 / 2, 400, 0, 1).is_none());
+    }
+
+    #[test]
+    fn invariant_is_weighted_geometric_mean() {
+        let third = ONE as u64 / 3;
+        let weights = [third, third, ONE as u64 - 2 * third];
+        let value = invariant(&[1_000_000, 1_000_000, 1_000_000], &weights).unwrap();
+        assert_close(value as u128, 1_000_000, 1);
+        let value = invariant(&[100, 400], &[ONE as u64 / 2, ONE as u64 / 2]).unwrap();
+        assert_close(value as u128, 200, 1);
+    }
+
+    #[test]
+    fn single_asset_join_then_exit_never_profits() {
+        let weight = 250_000_000_000_000_000; // 25%
+        let (balance, supply) = (5_000_000_000u64, 1_000_000_000u64);
+        let amount_in = 500_000_000;
+
+        let lp = lp_out_given_single_in(balance, weight, supply, amount_in, 3, 1_000).unwrap();
+        assert!(lp > 0);
+        let back = single_out_given_lp_in(balance + amount_in, weight, supply + lp, lp, 3, 1_000).unwrap();
+        assert!(back < amount_in);
+
+        // Without fees the round trip is lossless up to rounding
+        let lp = lp_out_given_single_in(balance, weight, supply, amount_in, 0, 1).unwrap();
+        let back = single_out_given_lp_in(balance + amount_in, weight, supply + lp, lp, 0, 1).unwrap();
+        assert!(back <= amount_in && amount_in - back < amount_in / 100_000);
+    }
+
+    #[test]
+    fn proportional_amounts_round_toward_the_pool() {
+        assert_eq!(proportional_amount(1_000, 1, 3, true), Some(334));
+        assert_eq!(proportional_amount(1_000, 1, 3, false), Some(333));
+        assert_eq!(proportional_amount(1_000, 1, 0, false), None);
+    }
+}
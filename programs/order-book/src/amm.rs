@@ -0,0 +1,123 @@
+//! Pricing of a liquidity-pool constant-product pool, used by
+//! `route_hybrid` to decide how much of an order the pool should fill
+//! before the book becomes the cheaper venue.
+
+use liquidity_pool::{integer_sqrt, LiquidityPool};
+
+use crate::state::Side;
+
+/// Swap fee a pool charges, as `numerator / denominator`
+#[derive(Clone, Copy)]
+pub struct PoolFee {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl PoolFee {
+    /// Fee `pool` charges at `now`, dynamic fee included.
+    pub fn of(pool: &LiquidityPool, now: i64) -> PoolFee {
+        PoolFee {
+            numerator: pool.swap_fee_numerator(now),
+            denominator: pool.fee_denominator,
+        }
+    }
+
+    /// Gross amount a `side` taker can send the pool before its marginal
+    /// price is worse than `price_numerator / price_denominator` quote
+    /// tokens per base token.
+    pub fn input_at_price(
+        &self,
+        side: Side,
+        reserve_base: u64,
+        reserve_quote: u64,
+        price_numerator: u64,
+        price_denominator: u64,
+    ) -> u64 {
+        let fee_denominator = self.denominator as u128;
+        let fee_multiplier = fee_denominator.saturating_sub(self.numerator as u128);
+        if fee_multiplier == 0 || price_numerator == 0 || price_denominator == 0 {
+            return 0;
+        }
+
+        // The pool's marginal price after taking d is (y + γd)² / γxy for a
+        // buyer and γxy / (x + γd)² for a seller, so solve for the input
+        // reserve that reaches the target price
+        let (reserve_in, target_squared) = match side {
+            Side::Bid => {
+                let base_value = reserve_base as u128 * price_numerator as u128 / price_denominator as u128;
+                let quote_after_fee = reserve_quote as u128 * fee_multiplier / fee_denominator;
+                (reserve_quote, base_value.checked_mul(quote_after_fee))
+            }
+            Side::Ask => {
+                let quote_value = reserve_quote as u128 * price_denominator as u128 / price_numerator as u128;
+                let base_after_fee = reserve_base as u128 * fee_multiplier / fee_denominator;
+                (reserve_base, quote_value.checked_mul(base_after_fee))
+            }
+        };
+        let target = integer_sqrt(target_squared.unwrap_or(u128::MAX));
+        let input = target.saturating_sub(reserve_in as u128) * fee_denominator / fee_multiplier;
+        input.min(u64::MAX as u128) as u64
+    }
+
+    /// Moves `(reserve_base, reserve_quote)` the way a `side` taker swapping
+    /// `amount_in` would, returning the amount paid out. Fees stay in the
+    /// input reserve, as they do in the pool.
+    pub fn apply_swap(&self, side: Side, amount_in: u64, reserves: &mut (u64, u64)) -> Option<u64> {
+        let (reserve_in, reserve_out) = match side {
+            Side::Bid => (&mut reserves.1, &mut reserves.0),
+            Side::Ask => (&mut reserves.0, &mut reserves.1),
+        };
+        let fee_multiplier = self.denominator.checked_sub(self.numerator)? as u128;
+        let amount_in_with_fee = (amount_in as u128).checked_mul(fee_multiplier)?;
+        let denominator = (*reserve_in as u128)
+            .checked_mul(self.denominator as u128)?
+            .checked_add(amount_in_with_fee)?;
+        if denominator == 0 {
+            return None;
+        }
+        let amount_out = u64::try_from(amount_in_with_fee.checked_mul(*reserve_out as u128)? / denominator).ok()?;
+        *reserve_in = reserve_in.checked_add(amount_in)?;
+        *reserve_out -= amount_out;
+        Some(amount_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(numerator: u64, denominator: u64) -> PoolFee {
+        PoolFee { numerator, denominator }
+    }
+
+    #[test]
+    fn input_moves_the_pool_to_the_target_price() {
+        let pool = pool(0, 1);
+        // At 1:1, buying until the price is 4 doubles the quote reserve
+        assert_eq!(pool.input_at_price(Side::Bid, 1_000_000, 1_000_000, 4, 1), 1_000_000);
+        // Selling until the price is 1/4 doubles the base reserve
+        assert_eq!(pool.input_at_price(Side::Ask, 1_000_000, 1_000_000, 1, 4), 1_000_000);
+        // Targets on the wrong side of the current price take nothing
+        assert_eq!(pool.input_at_price(Side::Bid, 1_000_000, 1_000_000, 1, 4), 0);
+        assert_eq!(pool.input_at_price(Side::Ask, 1_000_000, 1_000_000, 4, 1), 0);
+    }
+
+    #[test]
+    fn fee_shrinks_the_pool_share() {
+        let with_fee = pool(30, 10_000).input_at_price(Side::Bid, 1_000_000, 1_000_000, 4, 1);
+        assert!(with_fee < 1_000_000);
+        // Just inside the fee band the pool is never cheaper
+        assert_eq!(pool(30, 10_000).input_at_price(Side::Bid, 1_000_000, 1_000_000, 1_002, 1_000), 0);
+    }
+
+    #[test]
+    fn applied_swap_lands_on_the_target_price() {
+        let pool = pool(0, 1);
+        let mut reserves = (1_000_000, 1_000_000);
+        let input = pool.input_at_price(Side::Bid, reserves.0, reserves.1, 4, 1);
+        assert_eq!(pool.apply_swap(Side::Bid, input, &mut reserves), Some(500_000));
+        assert_eq!(reserves, (500_000, 2_000_000));
+        // Nothing more is cheaper than the target
+        assert_eq!(pool.input_at_price(Side::Bid, reserves.0, reserves.1, 4, 1), 0);
+    }
+}
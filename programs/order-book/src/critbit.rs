@@ -0,0 +1,339 @@
+//! One side of a market's book, stored as a crit-bit tree in a fixed slab of
+//! nodes. Leaves are resting orders keyed by `(price << 64) | sequence`, so
+//! the best ask is the minimum key and the best bid the maximum; bids store
+//! the sequence number inverted so that older orders still win ties.
+
+use anchor_lang::prelude::*;
+
+use crate::state::Side;
+
+/// Nodes per slab, inner and leaf. A full book holds half this many orders.
+pub const SLAB_CAPACITY: usize = 1024;
+
+/// Node tags. Slots that have never been used are zeroed, tag 0.
+pub const NODE_INNER: u32 = 1;
+pub const NODE_LEAF: u32 = 2;
+pub const NODE_FREE: u32 = 3;
+
+/// Key of an order resting at `price` with sequence number `seq`.
+pub fn order_key(side: Side, price: u64, seq: u64) -> u128 {
+    let seq = match side {
+        Side::Bid => !seq,
+        Side::Ask => seq,
+    };
+    ((price as u128) << 64) | seq as u128
+}
+
+/// Price encoded in an order key, in quote lots per base lot.
+pub fn key_price(key: u128) -> u64 {
+    (key >> 64) as u64
+}
+
+/// Sequence number encoded in an order key. Lower is older.
+pub fn key_seq(side: Side, key: u128) -> u64 {
+    match side {
+        Side::Bid => !(key as u64),
+        Side::Ask => key as u64,
+    }
+}
+
+/// Bit `index` of `key`, counting from the most significant.
+fn crit_bit(key: u128, index: u32) -> usize {
+    ((key >> (127 - index)) & 1) as usize
+}
+
+/// A slab entry. Inner nodes use `key`, `prefix_len` and `children`; leaves
+/// use `key`, `owner`, `quantity` and `client_order_id`; free nodes link
+/// through `children[0]`.
+#[zero_copy]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct SlabNode {
+    /// Order key, stored as two halves to keep the slab 8-byte aligned
+    pub key: [u64; 2],
+    /// Open orders account of the order's owner
+    pub owner: Pubkey,
+    /// Unfilled quantity, in base lots
+    pub quantity: u64,
+    /// Caller-chosen id echoed back in fill events
+    pub client_order_id: u64,
+    pub tag: u32,
+    /// Number of leading key bits shared by everything under an inner node
+    pub prefix_len: u32,
+    pub children: [u32; 2],
+}
+
+impl SlabNode {
+    pub fn leaf(key: u128, owner: Pubkey, quantity: u64, client_order_id: u64) -> SlabNode {
+        SlabNode {
+            key: [(key >> 64) as u64, key as u64],
+            owner,
+            quantity,
+            client_order_id,
+            tag: NODE_LEAF,
+            ..Default::default()
+        }
+    }
+
+    pub fn key(&self) -> u128 {
+        ((self.key[0] as u128) << 64) | self.key[1] as u128
+    }
+
+    pub fn price(&self) -> u64 {
+        key_price(self.key())
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.tag == NODE_LEAF
+    }
+}
+
+#[account(zero_copy)]
+pub struct Slab {
+    pub market: Pubkey,
+    /// Slots below this index have been handed out at least once
+    pub bump_index: u32,
+    pub free_list_len: u32,
+    pub free_list_head: u32,
+    pub root: u32,
+    /// Number of resting orders
+    pub leaf_count: u64,
+    pub nodes: [SlabNode; SLAB_CAPACITY],
+}
+
+impl Slab {
+    pub fn node(&self, index: u32) -> &SlabNode {
+        &self.nodes[index as usize]
+    }
+
+    pub fn node_mut(&mut self, index: u32) -> &mut SlabNode {
+        &mut self.nodes[index as usize]
+    }
+
+    fn free_slots(&self) -> usize {
+        self.free_list_len as usize + SLAB_CAPACITY - self.bump_index as usize
+    }
+
+    fn allocate(&mut self, node: SlabNode) -> u32 {
+        let index = if self.free_list_len > 0 {
+            let index = self.free_list_head;
+            self.free_list_head = self.nodes[index as usize].children[0];
+            self.free_list_len -= 1;
+            index
+        } else {
+            self.bump_index += 1;
+            self.bump_index - 1
+        };
+        self.nodes[index as usize] = node;
+        index
+    }
+
+    fn release(&mut self, index: u32) {
+        self.nodes[index as usize] = SlabNode {
+            tag: NODE_FREE,
+            children: [self.free_list_head, 0],
+            ..Default::default()
+        };
+        self.free_list_head = index;
+        self.free_list_len += 1;
+    }
+
+    /// Adds a leaf and returns its slot, or `None` if the slab is full or
+    /// the key is already present.
+    pub fn insert(&mut self, leaf: SlabNode) -> Option<u32> {
+        let key = leaf.key();
+        if self.leaf_count == 0 {
+            if self.free_slots() == 0 {
+                return None;
+            }
+            self.root = self.allocate(leaf);
+            self.leaf_count = 1;
+            return Some(self.root);
+        }
+        // A split takes a new leaf and a new inner node
+        if self.free_slots() < 2 {
+            return None;
+        }
+
+        let mut parent: Option<(u32, usize)> = None;
+        let mut index = self.root;
+        loop {
+            let node = *self.node(index);
+            let shared = (node.key() ^ key).leading_zeros();
+            if !node.is_leaf() && shared >= node.prefix_len {
+                let child = crit_bit(key, node.prefix_len);
+                parent = Some((index, child));
+                index = node.children[child];
+                continue;
+            }
+            if shared == 128 {
+                return None;
+            }
+
+            let leaf_index = self.allocate(leaf);
+            let children = if crit_bit(key, shared) == 1 {
+                [index, leaf_index]
+            } else {
+                [leaf_index, index]
+            };
+            let inner_index = self.allocate(SlabNode {
+                key: leaf.key,
+                tag: NODE_INNER,
+                prefix_len: shared,
+                children,
+                ..Default::default()
+            });
+            match parent {
+                Some((parent, child)) => self.node_mut(parent).children[child] = inner_index,
+                None => self.root = inner_index,
+            }
+            self.leaf_count += 1;
+            return Some(leaf_index);
+        }
+    }
+
+    /// Slot of the leaf with `key`, if present.
+    pub fn find(&self, key: u128) -> Option<u32> {
+        if self.leaf_count == 0 {
+            return None;
+        }
+        let mut index = self.root;
+        loop {
+            let node = self.node(index);
+            if node.is_leaf() {
+                return (node.key() == key).then_some(index);
+            }
+            if (node.key() ^ key).leading_zeros() < node.prefix_len {
+                return None;
+            }
+            index = node.children[crit_bit(key, node.prefix_len)];
+        }
+    }
+
+    /// Removes the leaf with `key` and returns it.
+    pub fn remove(&mut self, key: u128) -> Option<SlabNode> {
+        let leaf_index = self.find(key)?;
+        let leaf = *self.node(leaf_index);
+        if self.leaf_count == 1 {
+            self.release(leaf_index);
+            self.leaf_count = 0;
+            return Some(leaf);
+        }
+
+        // Walk down again, remembering the leaf's parent and grandparent
+        let mut grandparent: Option<(u32, usize)> = None;
+        let mut parent = self.root;
+        loop {
+            let node = *self.node(parent);
+            let child = crit_bit(key, node.prefix_len);
+            if node.children[child] == leaf_index {
+                // The sibling takes the parent's place
+                let sibling = node.children[1 - child];
+                match grandparent {
+                    Some((grandparent, slot)) => self.node_mut(grandparent).children[slot] = sibling,
+                    None => self.root = sibling,
+                }
+                self.release(leaf_index);
+                self.release(parent);
+                self.leaf_count -= 1;
+                return Some(leaf);
+            }
+            grandparent = Some((parent, child));
+            parent = node.children[child];
+        }
+    }
+
+    fn find_extreme(&self, direction: usize) -> Option<u32> {
+        if self.leaf_count == 0 {
+            return None;
+        }
+        let mut index = self.root;
+        while !self.node(index).is_leaf() {
+            index = self.node(index).children[direction];
+        }
+        Some(index)
+    }
+
+    /// Slot of the leaf with the lowest key
+    pub fn find_min(&self) -> Option<u32> {
+        self.find_extreme(0)
+    }
+
+    /// Slot of the leaf with the highest key
+    pub fn find_max(&self) -> Option<u32> {
+        self.find_extreme(1)
+    }
+
+    /// Slot of the best order on this side of the book, treating the slab as
+    /// holding `side` orders.
+    pub fn best(&self, side: Side) -> Option<u32> {
+        match side {
+            Side::Bid => self.find_max(),
+            Side::Ask => self.find_min(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slab() -> Box<Slab> {
+        Box::new(bytemuck::Zeroable::zeroed())
+    }
+
+    fn leaf(key: u128) -> SlabNode {
+        SlabNode::leaf(key, Pubkey::default(), 1, 0)
+    }
+
+    #[test]
+    fn keys_order_by_price_then_age() {
+        assert!(order_key(Side::Ask, 10, 1) < order_key(Side::Ask, 10, 2));
+        assert!(order_key(Side::Ask, 10, 9) < order_key(Side::Ask, 11, 0));
+        // Higher bids and older bids both sort higher
+        assert!(order_key(Side::Bid, 10, 1) > order_key(Side::Bid, 10, 2));
+        assert!(order_key(Side::Bid, 11, 9) > order_key(Side::Bid, 10, 0));
+        assert_eq!(key_price(order_key(Side::Bid, 42, 7)), 42);
+        assert_eq!(key_seq(Side::Bid, order_key(Side::Bid, 42, 7)), 7);
+    }
+
+    #[test]
+    fn insert_find_and_remove() {
+        let mut slab = slab();
+        let keys = [50u128, 10, 30, 70, 20, 60, 40];
+        for key in keys {
+            assert!(slab.insert(leaf(key)).is_some());
+        }
+        assert_eq!(slab.insert(leaf(30)), None);
+        assert_eq!(slab.leaf_count, keys.len() as u64);
+        assert_eq!(slab.node(slab.find_min().unwrap()).key(), 10);
+        assert_eq!(slab.node(slab.find_max().unwrap()).key(), 70);
+
+        assert_eq!(slab.remove(10).map(|node| node.key()), Some(10));
+        assert_eq!(slab.remove(70).map(|node| node.key()), Some(70));
+        assert_eq!(slab.remove(70), None);
+        assert_eq!(slab.find(10), None);
+        assert!(slab.find(40).is_some());
+        assert_eq!(slab.node(slab.find_min().unwrap()).key(), 20);
+        assert_eq!(slab.node(slab.find_max().unwrap()).key(), 60);
+
+        for key in [20, 30, 40, 50, 60] {
+            assert!(slab.remove(key).is_some());
+        }
+        assert_eq!(slab.leaf_count, 0);
+        assert_eq!(slab.find_min(), None);
+    }
+
+    #[test]
+    fn full_slab_rejects_orders_and_reuses_freed_nodes() {
+        let mut slab = slab();
+        let mut inserted = 0u128;
+        while slab.insert(leaf(inserted)).is_some() {
+            inserted += 1;
+        }
+        // n leaves need n - 1 inner nodes
+        assert_eq!(inserted as usize, SLAB_CAPACITY / 2);
+        assert!(slab.remove(3).is_some());
+        assert!(slab.insert(leaf(1_000)).is_some());
+        assert_eq!(slab.insert(leaf(1_001)), None);
+    }
+}
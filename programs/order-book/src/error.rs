@@ -0,0 +1,40 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Lot sizes must be non-zero")]
+    InvalidLotSize,
+    
+    #[msg("Order price and quantity must be non-zero")]
+    InvalidOrder,
+    
+    #[msg("Post-only order would cross the book")]
+    PostOnlyWouldCross,
+    
+    #[msg("Order book side is full")]
+    BookFull,
+    
+    #[msg("Event queue is full")]
+    EventQueueFull,
+    
+    #[msg("Order not found")]
+    OrderNotFound,
+    
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Open orders account belongs to another market")]
+    InvalidOpenOrders,
+    
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
+    
+    #[msg("Account is not a constant-product pool for this market")]
+    InvalidAmmPool,
+    
+    #[msg("Slippage tolerance exceeded")]
+    SlippageExceeded,
+    
+    #[msg("Math overflow")]
+    MathOverflow,
+}
@@ -0,0 +1,91 @@
+//! Fills against resting orders are recorded here rather than settled
+//! inline, since the maker's open orders account is not part of the taker's
+//! transaction. `consume_events` later credits each maker.
+
+use anchor_lang::prelude::*;
+
+/// Events held per market before the queue must be cranked
+pub const EVENT_QUEUE_CAPACITY: usize = 512;
+
+/// A fill of one resting order. Amounts are native token units.
+#[zero_copy]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct FillEvent {
+    /// Open orders account of the filled order
+    pub owner: Pubkey,
+    /// Base tokens exchanged
+    pub base_quantity: u64,
+    /// Quote tokens exchanged
+    pub quote_quantity: u64,
+    /// Quote tokens released from a bid's reservation. At least
+    /// `quote_quantity`; the difference is price improvement.
+    pub quote_unlocked: u64,
+    pub client_order_id: u64,
+    /// `Side` of the filled order
+    pub side: u8,
+    /// Set when the fill removed the order from the book
+    pub out: u8,
+    pub padding: [u8; 6],
+}
+
+#[account(zero_copy)]
+pub struct EventQueue {
+    pub market: Pubkey,
+    /// Slot of the oldest unconsumed event
+    pub head: u32,
+    pub count: u32,
+    /// Total events ever pushed
+    pub seq_num: u64,
+    pub events: [FillEvent; EVENT_QUEUE_CAPACITY],
+}
+
+impl EventQueue {
+    pub fn is_full(&self) -> bool {
+        self.count as usize == EVENT_QUEUE_CAPACITY
+    }
+
+    /// Appends `event`, returning `false` if the queue is full.
+    pub fn push(&mut self, event: FillEvent) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let slot = (self.head as usize + self.count as usize) % EVENT_QUEUE_CAPACITY;
+        self.events[slot] = event;
+        self.count += 1;
+        self.seq_num += 1;
+        true
+    }
+
+    pub fn peek(&self) -> Option<&FillEvent> {
+        (self.count > 0).then(|| &self.events[self.head as usize])
+    }
+
+    pub fn pop(&mut self) -> Option<FillEvent> {
+        let event = *self.peek()?;
+        self.head = ((self.head as usize + 1) % EVENT_QUEUE_CAPACITY) as u32;
+        self.count -= 1;
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_is_first_in_first_out_and_bounded() {
+        let mut queue: Box<EventQueue> = Box::new(bytemuck::Zeroable::zeroed());
+        let event = |id| FillEvent { client_order_id: id, ..Default::default() };
+        for id in 0..EVENT_QUEUE_CAPACITY as u64 {
+            assert!(queue.push(event(id)));
+        }
+        assert!(!queue.push(event(u64::MAX)));
+        assert_eq!(queue.pop().map(|event| event.client_order_id), Some(0));
+        assert_eq!(queue.pop().map(|event| event.client_order_id), Some(1));
+        // Wraps around into the freed slots
+        assert!(queue.push(event(u64::MAX)));
+        assert_eq!(queue.count as usize, EVENT_QUEUE_CAPACITY - 1);
+        assert_eq!(queue.peek().map(|event| event.client_order_id), Some(2));
+        assert_eq!(queue.seq_num, EVENT_QUEUE_CAPACITY as u64 + 1);
+    }
+}
@@ -0,0 +1,883 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use liquidity_pool::program::LiquidityPool as LiquidityPoolProgram;
+use liquidity_pool::{CurveType, LiquidityPool};
+
+mod amm;
+mod critbit;
+mod error;
+mod event_queue;
+mod state;
+
+use amm::PoolFee;
+use critbit::*;
+use error::ErrorCode;
+use event_queue::*;
+use state::*;
+use transfer_fee::amount_before_fee;
+
+declare_id!("Eih7t5fwQAqQwSwH1cSBb28zSPd3QLx58VN1LMAWmjST");
+
+#[program]
+pub mod order_book {
+    use super::*;
+
+    /// Creates a market over caller-allocated bid, ask and event queue
+    /// accounts, which are too large to create through CPI.
+    pub fn initialize_market(
+        ctx: Context<InitializeMarket>,
+        base_lot_size: u64,
+        quote_lot_size: u64,
+    ) -> Result<()> {
+        require!(
+            base_lot_size > 0 && quote_lot_size > 0,
+            ErrorCode::InvalidLotSize
+        );
+        
+        let market_key = ctx.accounts.market.key();
+        let market = &mut ctx.accounts.market;
+        market.base_mint = ctx.accounts.base_mint.key();
+        market.quote_mint = ctx.accounts.quote_mint.key();
+        market.base_vault = ctx.accounts.base_vault.key();
+        market.quote_vault = ctx.accounts.quote_vault.key();
+        market.bids = ctx.accounts.bids.key();
+        market.asks = ctx.accounts.asks.key();
+        market.event_queue = ctx.accounts.event_queue.key();
+        market.base_lot_size = base_lot_size;
+        market.quote_lot_size = quote_lot_size;
+        market.seq_num = 0;
+        market.authority = ctx.accounts.authority.key();
+        market.bump = *ctx.bumps.get("vault_authority").unwrap();
+        
+        ctx.accounts.bids.load_init()?.market = market_key;
+        ctx.accounts.asks.load_init()?.market = market_key;
+        ctx.accounts.event_queue.load_init()?.market = market_key;
+        
+        Ok(())
+    }
+
+    pub fn init_open_orders(ctx: Context<InitOpenOrders>) -> Result<()> {
+        let open_orders = &mut ctx.accounts.open_orders;
+        open_orders.market = ctx.accounts.market.key();
+        open_orders.owner = ctx.accounts.owner.key();
+        
+        Ok(())
+    }
+
+    /// Places an order of up to `max_base_lots` at `limit_price` quote lots
+    /// per base lot. The order first takes crossing liquidity from at most
+    /// `match_limit` resting orders, then rests unless it is
+    /// immediate-or-cancel. Returns the resting order's id.
+    pub fn place_order(
+        ctx: Context<PlaceOrder>,
+        side: Side,
+        limit_price: u64,
+        max_base_lots: u64,
+        order_type: OrderType,
+        client_order_id: u64,
+        match_limit: u16,
+    ) -> Result<Option<u128>> {
+        require!(limit_price > 0 && max_base_lots > 0, ErrorCode::InvalidOrder);
+        
+        let market = &mut ctx.accounts.market;
+        let open_orders = &mut ctx.accounts.open_orders;
+        
+        // Reserve the most the order can cost, drawing on free balance first
+        let reserved = match side {
+            Side::Bid => market.quote_amount(max_base_lots, limit_price),
+            Side::Ask => market.base_amount(max_base_lots),
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+        let free = match side {
+            Side::Bid => &mut open_orders.quote_free,
+            Side::Ask => &mut open_orders.base_free,
+        };
+        let from_free = reserved.min(*free);
+        *free -= from_free;
+        let deposit = reserved - from_free;
+        
+        if deposit > 0 {
+            let (vault, mint, token_program) = match side {
+                Side::Bid => (
+                    ctx.accounts.quote_vault.to_account_info(),
+                    &ctx.accounts.quote_mint,
+                    ctx.accounts.quote_token_program.to_account_info(),
+                ),
+                Side::Ask => (
+                    ctx.accounts.base_vault.to_account_info(),
+                    &ctx.accounts.base_mint,
+                    ctx.accounts.base_token_program.to_account_info(),
+                ),
+            };
+            // Send enough that the vault receives the full deposit
+            let amount = amount_before_fee(&mint.to_account_info(), deposit)?;
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    token_program,
+                    TransferChecked {
+                        from: ctx.accounts.payer_token_account.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: vault,
+                        authority: ctx.accounts.owner.to_account_info(),
+                    },
+                ),
+                amount,
+                mint.decimals,
+            )?;
+        }
+        
+        let mut bids = ctx.accounts.bids.load_mut()?;
+        let mut asks = ctx.accounts.asks.load_mut()?;
+        let mut event_queue = ctx.accounts.event_queue.load_mut()?;
+        let (book, opposite) = match side {
+            Side::Bid => (&mut *bids, &mut *asks),
+            Side::Ask => (&mut *asks, &mut *bids),
+        };
+        
+        if order_type == OrderType::PostOnly {
+            if let Some(best) = opposite.best(side.opposite()) {
+                require!(
+                    !crosses(side, limit_price, opposite.node(best).price()),
+                    ErrorCode::PostOnlyWouldCross
+                );
+            }
+        }
+        
+        let (filled_lots, quote_amount) = take_liquidity(
+            market,
+            opposite,
+            &mut event_queue,
+            side,
+            limit_price,
+            max_base_lots,
+            match_limit,
+        )?;
+        
+        // Rest whatever is left
+        let remaining = max_base_lots - filled_lots;
+        let mut order_id = None;
+        if remaining > 0 && order_type != OrderType::ImmediateOrCancel {
+            let key = order_key(side, limit_price, market.seq_num);
+            book.insert(SlabNode::leaf(key, open_orders.key(), remaining, client_order_id))
+                .ok_or(ErrorCode::BookFull)?;
+            market.seq_num += 1;
+            order_id = Some(key);
+        }
+        let resting_lots = if order_id.is_some() { remaining } else { 0 };
+        
+        // Settle the taker's side now; only the resting order stays locked
+        let filled_base = market.base_amount(filled_lots).ok_or(ErrorCode::MathOverflow)?;
+        match side {
+            Side::Bid => {
+                let locked = market
+                    .quote_amount(resting_lots, limit_price)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                let refund = reserved
+                    .checked_sub(quote_amount)
+                    .and_then(|refund| refund.checked_sub(locked))
+                    .ok_or(ErrorCode::MathOverflow)?;
+                open_orders.quote_locked = open_orders.quote_locked.checked_add(locked).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.quote_free = open_orders.quote_free.checked_add(refund).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.base_free = open_orders.base_free.checked_add(filled_base).ok_or(ErrorCode::MathOverflow)?;
+            }
+            Side::Ask => {
+                let locked = market.base_amount(resting_lots).ok_or(ErrorCode::MathOverflow)?;
+                let refund = reserved
+                    .checked_sub(filled_base)
+                    .and_then(|refund| refund.checked_sub(locked))
+                    .ok_or(ErrorCode::MathOverflow)?;
+                open_orders.base_locked = open_orders.base_locked.checked_add(locked).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.base_free = open_orders.base_free.checked_add(refund).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.quote_free = open_orders.quote_free.checked_add(quote_amount).ok_or(ErrorCode::MathOverflow)?;
+            }
+        }
+        
+        Ok(order_id)
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>, side: Side, order_id: u128) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let open_orders = &mut ctx.accounts.open_orders;
+        let mut book = match side {
+            Side::Bid => ctx.accounts.bids.load_mut()?,
+            Side::Ask => ctx.accounts.asks.load_mut()?,
+        };
+        
+        let index = book.find(order_id).ok_or(ErrorCode::OrderNotFound)?;
+        require_keys_eq!(book.node(index).owner, open_orders.key(), ErrorCode::Unauthorized);
+        let order = book.remove(order_id).ok_or(ErrorCode::OrderNotFound)?;
+        
+        // Release what the unfilled part of the order had locked
+        match side {
+            Side::Bid => {
+                let amount = market
+                    .quote_amount(order.quantity, order.price())
+                    .ok_or(ErrorCode::MathOverflow)?;
+                open_orders.quote_locked = open_orders.quote_locked.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.quote_free = open_orders.quote_free.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+            }
+            Side::Ask => {
+                let amount = market.base_amount(order.quantity).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.base_locked = open_orders.base_locked.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+                open_orders.base_free = open_orders.base_free.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Spends `amount_in` on a `side` market order split between the book
+    /// and a liquidity-pool constant-product pool, filling from whichever is
+    /// cheaper: the pool takes input until its marginal price reaches the
+    /// best resting order, that order is filled, and so on for up to
+    /// `match_limit` orders. Whatever is left goes to the pool. The combined
+    /// output must reach `minimum_amount_out`.
+    pub fn route_hybrid<'info>(
+        ctx: Context<'_, '_, '_, 'info, RouteHybrid<'info>>,
+        side: Side,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        match_limit: u16,
+    ) -> Result<()> {
+        require!(amount_in > 0, ErrorCode::InvalidOrder);
+        
+        let market = &ctx.accounts.market;
+        let (mint_in, mint_out, vault_in, vault_out, token_program_in, token_program_out) = match side {
+            Side::Bid => (
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.base_mint,
+                &ctx.accounts.quote_vault,
+                &ctx.accounts.base_vault,
+                &ctx.accounts.quote_token_program,
+                &ctx.accounts.base_token_program,
+            ),
+            Side::Ask => (
+                &ctx.accounts.base_mint,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.base_vault,
+                &ctx.accounts.quote_vault,
+                &ctx.accounts.base_token_program,
+                &ctx.accounts.quote_token_program,
+            ),
+        };
+        require!(
+            ctx.accounts.user_token_in.mint == mint_in.key()
+                && ctx.accounts.user_token_out.mint == mint_out.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        
+        // The accounts constraint ties the pool to this market's pair
+        let pool = &ctx.accounts.pool;
+        let fee = PoolFee::of(pool, Clock::get()?.unix_timestamp);
+        let base_is_a = pool.token_a_mint == market.base_mint;
+        let (pool_vault_a, pool_vault_b) = (&ctx.accounts.pool_token_a_account, &ctx.accounts.pool_token_b_account);
+        let mut reserves = if base_is_a {
+            (pool_vault_a.amount, pool_vault_b.amount)
+        } else {
+            (pool_vault_b.amount, pool_vault_a.amount)
+        };
+        
+        let mut remaining = amount_in;
+        let mut pool_in: u64 = 0;
+        let mut book_in: u64 = 0;
+        let mut book_out: u64 = 0;
+        {
+            let mut bids = ctx.accounts.bids.load_mut()?;
+            let mut asks = ctx.accounts.asks.load_mut()?;
+            let mut event_queue = ctx.accounts.event_queue.load_mut()?;
+            let opposite = match side {
+                Side::Bid => &mut *asks,
+                Side::Ask => &mut *bids,
+            };
+        
+            for _ in 0..match_limit {
+                if remaining == 0 {
+                    break;
+                }
+                let Some(best) = opposite.best(side.opposite()) else {
+                    break;
+                };
+                let price = opposite.node(best).price();
+        
+                // Quote tokens per base token at the resting order's price
+                let price_numerator = price
+                    .checked_mul(market.quote_lot_size)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                let to_pool = fee
+                    .input_at_price(side, reserves.0, reserves.1, price_numerator, market.base_lot_size)
+                    .min(remaining);
+                if to_pool > 0 {
+                    fee.apply_swap(side, to_pool, &mut reserves).ok_or(ErrorCode::MathOverflow)?;
+                    pool_in += to_pool;
+                    remaining -= to_pool;
+                }
+        
+                let lot_cost = match side {
+                    Side::Bid => market.quote_amount(1, price),
+                    Side::Ask => market.base_amount(1),
+                }
+                .ok_or(ErrorCode::MathOverflow)?;
+                let lots = remaining / lot_cost;
+                if lots == 0 {
+                    break;
+                }
+                let (filled_lots, quote_amount) =
+                    take_liquidity(market, opposite, &mut event_queue, side, price, lots, 1)?;
+                let base_amount = market.base_amount(filled_lots).ok_or(ErrorCode::MathOverflow)?;
+                let (spent, received) = match side {
+                    Side::Bid => (quote_amount, base_amount),
+                    Side::Ask => (base_amount, quote_amount),
+                };
+                remaining -= spent;
+                book_in += spent;
+                book_out = book_out.checked_add(received).ok_or(ErrorCode::MathOverflow)?;
+            }
+        }
+        // Past the last order the book can fill, the pool takes the rest
+        pool_in += remaining;
+        
+        let balance_before = ctx.accounts.user_token_out.amount;
+        if book_in > 0 {
+            // Send enough that the vault receives what the makers are owed
+            let amount = amount_before_fee(&mint_in.to_account_info(), book_in)?;
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    token_program_in.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.user_token_in.to_account_info(),
+                        mint: mint_in.to_account_info(),
+                        to: vault_in.to_account_info(),
+                        authority: ctx.accounts.owner.to_account_info(),
+                    },
+                ),
+                amount,
+                mint_in.decimals,
+            )?;
+        }
+        if book_out > 0 {
+            let market_key = market.key();
+            let vault_authority_seeds = &[market_key.as_ref(), &[market.bump]];
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    token_program_out.to_account_info(),
+                    TransferChecked {
+                        from: vault_out.to_account_info(),
+                        mint: mint_out.to_account_info(),
+                        to: ctx.accounts.user_token_out.to_account_info(),
+                        authority: ctx.accounts.vault_authority.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                book_out,
+                mint_out.decimals,
+            )?;
+        }
+        if pool_in > 0 {
+            let (token_a_mint, token_b_mint, token_a_program, token_b_program) = if base_is_a {
+                (
+                    &ctx.accounts.base_mint,
+                    &ctx.accounts.quote_mint,
+                    &ctx.accounts.base_token_program,
+                    &ctx.accounts.quote_token_program,
+                )
+            } else {
+                (
+                    &ctx.accounts.quote_mint,
+                    &ctx.accounts.base_mint,
+                    &ctx.accounts.quote_token_program,
+                    &ctx.accounts.base_token_program,
+                )
+            };
+            // The pool's own slippage check is disabled; the combined
+            // output is checked below
+            liquidity_pool::cpi::swap(
+                CpiContext::new(
+                    ctx.accounts.liquidity_pool_program.to_account_info(),
+                    liquidity_pool::cpi::accounts::Swap {
+                        pool: ctx.accounts.pool.to_account_info(),
+                        token_a_account: ctx.accounts.pool_token_a_account.to_account_info(),
+                        token_b_account: ctx.accounts.pool_token_b_account.to_account_info(),
+                        lp_mint: ctx.accounts.pool_lp_mint.to_account_info(),
+                        user_token_in: ctx.accounts.user_token_in.to_account_info(),
+                        user_token_out: ctx.accounts.user_token_out.to_account_info(),
+                        protocol_fee_account: ctx
+                            .accounts
+                            .pool_protocol_fee_account
+                            .as_ref()
+                            .map(|account| account.to_account_info()),
+                        oracle: ctx.accounts.pool_oracle.as_ref().map(|oracle| oracle.to_account_info()),
+                        pool_authority: ctx.accounts.pool_authority.to_account_info(),
+                        user: ctx.accounts.owner.to_account_info(),
+                        token_a_mint: token_a_mint.to_account_info(),
+                        token_b_mint: token_b_mint.to_account_info(),
+                        token_a_program: token_a_program.to_account_info(),
+                        token_b_program: token_b_program.to_account_info(),
+                        token_program: ctx.accounts.lp_token_program.to_account_info(),
+                    },
+                ),
+                pool_in,
+                0,
+            )?;
+        }
+        
+        // Transfer fees on either leg come out of the slippage budget
+        ctx.accounts.user_token_out.reload()?;
+        let amount_out = ctx.accounts.user_token_out.amount - balance_before;
+        require!(amount_out >= minimum_amount_out, ErrorCode::SlippageExceeded);
+        
+        Ok(())
+    }
+
+    /// Fills crossing resting orders against each other, up to `limit`
+    /// pairs. The book only crosses when a limit order ran out of
+    /// `match_limit` before it ran out of crossing liquidity; the older of
+    /// each pair was resting first and sets the price.
+    pub fn match_orders(ctx: Context<MatchOrders>, limit: u16) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let mut bids = ctx.accounts.bids.load_mut()?;
+        let mut asks = ctx.accounts.asks.load_mut()?;
+        let mut event_queue = ctx.accounts.event_queue.load_mut()?;
+        
+        for _ in 0..limit {
+            let (Some(bid_index), Some(ask_index)) = (bids.find_max(), asks.find_min()) else {
+                break;
+            };
+            let bid = *bids.node(bid_index);
+            let ask = *asks.node(ask_index);
+            if bid.price() < ask.price() {
+                break;
+            }
+        
+            let price = if key_seq(Side::Bid, bid.key()) < key_seq(Side::Ask, ask.key()) {
+                bid.price()
+            } else {
+                ask.price()
+            };
+            let fill = bid.quantity.min(ask.quantity);
+            let base_quantity = market.base_amount(fill).ok_or(ErrorCode::MathOverflow)?;
+            let quote_quantity = market.quote_amount(fill, price).ok_or(ErrorCode::MathOverflow)?;
+            let bid_unlocked = market.quote_amount(fill, bid.price()).ok_or(ErrorCode::MathOverflow)?;
+        
+            require!(
+                event_queue.count as usize + 2 <= EVENT_QUEUE_CAPACITY,
+                ErrorCode::EventQueueFull
+            );
+            let bid_out = fill_order(&mut bids, bid_index, fill);
+            let ask_out = fill_order(&mut asks, ask_index, fill);
+            event_queue.push(FillEvent {
+                owner: bid.owner,
+                base_quantity,
+                quote_quantity,
+                quote_unlocked: bid_unlocked,
+                client_order_id: bid.client_order_id,
+                side: Side::Bid as u8,
+                out: bid_out as u8,
+                ..Default::default()
+            });
+            event_queue.push(FillEvent {
+                owner: ask.owner,
+                base_quantity,
+                quote_quantity,
+                client_order_id: ask.client_order_id,
+                side: Side::Ask as u8,
+                out: ask_out as u8,
+                ..Default::default()
+            });
+        }
+        
+        Ok(())
+    }
+
+    /// Credits up to `limit` queued fills to their open orders accounts,
+    /// which are passed as remaining accounts. Stops early at the first fill
+    /// whose account was not supplied.
+    pub fn consume_events<'info>(
+        ctx: Context<'_, '_, '_, 'info, ConsumeEvents<'info>>,
+        limit: u16,
+    ) -> Result<()> {
+        let mut event_queue = ctx.accounts.event_queue.load_mut()?;
+        
+        for _ in 0..limit {
+            let Some(event) = event_queue.peek().copied() else {
+                break;
+            };
+            let Some(account) = ctx
+                .remaining_accounts
+                .iter()
+                .find(|account| account.key() == event.owner)
+            else {
+                break;
+            };
+        
+            let mut open_orders = Account::<OpenOrders>::try_from(account)?;
+            require_keys_eq!(
+                open_orders.market,
+                ctx.accounts.market.key(),
+                ErrorCode::InvalidOpenOrders
+            );
+            open_orders.apply_fill(&event).ok_or(ErrorCode::MathOverflow)?;
+            open_orders.exit(ctx.program_id)?;
+            event_queue.pop();
+        }
+        
+        Ok(())
+    }
+
+    /// Withdraws the free balances of an open orders account.
+    pub fn settle_funds(ctx: Context<SettleFunds>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let open_orders = &mut ctx.accounts.open_orders;
+        let market_key = market.key();
+        let vault_authority_seeds = &[market_key.as_ref(), &[market.bump]];
+        
+        if open_orders.base_free > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.base_token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.base_vault.to_account_info(),
+                        mint: ctx.accounts.base_mint.to_account_info(),
+                        to: ctx.accounts.user_base_account.to_account_info(),
+                        authority: ctx.accounts.vault_authority.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                open_orders.base_free,
+                ctx.accounts.base_mint.decimals,
+            )?;
+            open_orders.base_free = 0;
+        }
+        
+        if open_orders.quote_free > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.quote_token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.quote_vault.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: ctx.accounts.user_quote_account.to_account_info(),
+                        authority: ctx.accounts.vault_authority.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                open_orders.quote_free,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+            open_orders.quote_free = 0;
+        }
+        
+        Ok(())
+    }
+}
+
+/// Whether a `side` order limited at `limit_price` trades with a resting
+/// order at `resting_price`.
+fn crosses(side: Side, limit_price: u64, resting_price: u64) -> bool {
+    match side {
+        Side::Bid => resting_price <= limit_price,
+        Side::Ask => resting_price >= limit_price,
+    }
+}
+
+/// Takes `base_lots` from the resting order in slot `index`, removing it
+/// once empty. Returns whether it was removed.
+fn fill_order(book: &mut Slab, index: u32, base_lots: u64) -> bool {
+    let order = book.node_mut(index);
+    order.quantity -= base_lots;
+    if order.quantity > 0 {
+        return false;
+    }
+    let key = order.key();
+    book.remove(key);
+    true
+}
+
+/// Fills up to `max_base_lots` of an incoming `side` order from the best
+/// opposing orders priced no worse than `limit_price`, taking at most
+/// `match_limit` of them. Makers are credited through the event queue; the
+/// base lots filled and quote tokens exchanged are returned for the caller
+/// to settle with the taker.
+fn take_liquidity(
+    market: &Market,
+    opposite: &mut Slab,
+    event_queue: &mut EventQueue,
+    side: Side,
+    limit_price: u64,
+    max_base_lots: u64,
+    match_limit: u16,
+) -> Result<(u64, u64)> {
+    let maker_side = side.opposite();
+    let mut remaining = max_base_lots;
+    let mut quote_amount: u64 = 0;
+
+    for _ in 0..match_limit {
+        if remaining == 0 {
+            break;
+        }
+        let Some(best) = opposite.best(maker_side) else {
+            break;
+        };
+        let maker = *opposite.node(best);
+        if !crosses(side, limit_price, maker.price()) {
+            break;
+        }
+
+        let fill = remaining.min(maker.quantity);
+        let base_quantity = market.base_amount(fill).ok_or(ErrorCode::MathOverflow)?;
+        let quote_quantity = market
+            .quote_amount(fill, maker.price())
+            .ok_or(ErrorCode::MathOverflow)?;
+        let out = fill_order(opposite, best, fill);
+        // A resting bid is locked at its own price, which is the fill price
+        let quote_unlocked = match maker_side {
+            Side::Bid => quote_quantity,
+            Side::Ask => 0,
+        };
+        require!(
+            event_queue.push(FillEvent {
+                owner: maker.owner,
+                base_quantity,
+                quote_quantity,
+                quote_unlocked,
+                client_order_id: maker.client_order_id,
+                side: maker_side as u8,
+                out: out as u8,
+                ..Default::default()
+            }),
+            ErrorCode::EventQueueFull
+        );
+
+        remaining -= fill;
+        quote_amount = quote_amount.checked_add(quote_quantity).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    Ok((max_base_lots - remaining, quote_amount))
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarket<'info> {
+    #[account(init, payer = authority, space = 8 + Market::LEN)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(zero)]
+    pub bids: AccountLoader<'info, Slab>,
+    #[account(zero)]
+    pub asks: AccountLoader<'info, Slab>,
+    #[account(zero)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+    pub base_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"base_vault", market.key().as_ref()],
+        bump,
+        token::mint = base_mint,
+        token::authority = vault_authority,
+        token::token_program = base_token_program
+    )]
+    pub base_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"quote_vault", market.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority,
+        token::token_program = quote_token_program
+    )]
+    pub quote_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA that owns the market vaults
+    #[account(seeds = [market.key().as_ref()], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub base_token_program: Interface<'info, TokenInterface>,
+    pub quote_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitOpenOrders<'info> {
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + OpenOrders::LEN,
+        seeds = [b"open_orders", market.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(
+        mut,
+        has_one = bids,
+        has_one = asks,
+        has_one = event_queue,
+        has_one = base_vault,
+        has_one = quote_vault
+    )]
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut)]
+    pub bids: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub asks: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+    #[account(
+        mut,
+        has_one = market @ ErrorCode::InvalidOpenOrders,
+        has_one = owner @ ErrorCode::Unauthorized
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    /// Funds the order: quote tokens for a bid, base tokens for an ask
+    #[account(mut)]
+    pub payer_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub base_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub quote_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = market.base_mint)]
+    pub base_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub owner: Signer<'info>,
+    pub base_token_program: Interface<'info, TokenInterface>,
+    pub quote_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(has_one = bids, has_one = asks)]
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub bids: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub asks: AccountLoader<'info, Slab>,
+    #[account(
+        mut,
+        has_one = market @ ErrorCode::InvalidOpenOrders,
+        has_one = owner @ ErrorCode::Unauthorized
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RouteHybrid<'info> {
+    #[account(
+        has_one = bids,
+        has_one = asks,
+        has_one = event_queue,
+        has_one = base_vault,
+        has_one = quote_vault
+    )]
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut)]
+    pub bids: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub asks: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+    /// Quote tokens for a bid, base tokens for an ask
+    #[account(mut)]
+    pub user_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub base_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub quote_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = market.base_mint)]
+    pub base_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA that owns the market vaults
+    #[account(seeds = [market.key().as_ref()], bump = market.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+    pub base_token_program: Interface<'info, TokenInterface>,
+    pub quote_token_program: Interface<'info, TokenInterface>,
+    /// Constant-product pool trading the market's pair
+    #[account(
+        mut,
+        constraint = pool.curve_type == CurveType::ConstantProduct @ ErrorCode::InvalidAmmPool,
+        constraint = (pool.token_a_mint == market.base_mint && pool.token_b_mint == market.quote_mint)
+            || (pool.token_a_mint == market.quote_mint && pool.token_b_mint == market.base_mint)
+            @ ErrorCode::InvalidAmmPool
+    )]
+    pub pool: Box<Account<'info, LiquidityPool>>,
+    #[account(mut, address = pool.token_a_account @ ErrorCode::InvalidAmmPool)]
+    pub pool_token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool.token_b_account @ ErrorCode::InvalidAmmPool)]
+    pub pool_token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Pool's LP mint, checked by the liquidity-pool program
+    pub pool_lp_mint: UncheckedAccount<'info>,
+    /// CHECK: Protocol fee account for the input token, checked by the liquidity-pool program
+    #[account(mut)]
+    pub pool_protocol_fee_account: Option<UncheckedAccount<'info>>,
+    /// CHECK: Pool's observation ring buffer, checked by the liquidity-pool program
+    #[account(mut)]
+    pub pool_oracle: Option<UncheckedAccount<'info>>,
+    /// CHECK: PDA that owns the pool's vaults, checked by the liquidity-pool program
+    pub pool_authority: UncheckedAccount<'info>,
+    /// Token program of the pool's LP mint
+    pub lp_token_program: Program<'info, Token>,
+    pub liquidity_pool_program: Program<'info, LiquidityPoolProgram>,
+}
+
+#[derive(Accounts)]
+pub struct MatchOrders<'info> {
+    #[account(has_one = bids, has_one = asks, has_one = event_queue)]
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub bids: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub asks: AccountLoader<'info, Slab>,
+    #[account(mut)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+}
+
+#[derive(Accounts)]
+pub struct ConsumeEvents<'info> {
+    #[account(has_one = event_queue)]
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+}
+
+#[derive(Accounts)]
+pub struct SettleFunds<'info> {
+    #[account(has_one = base_vault, has_one = quote_vault)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(
+        mut,
+        has_one = market @ ErrorCode::InvalidOpenOrders,
+        has_one = owner @ ErrorCode::Unauthorized
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    #[account(mut)]
+    pub base_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub quote_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_base_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_quote_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = market.base_mint)]
+    pub base_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA that owns the market vaults
+    #[account(seeds = [market.key().as_ref()], bump = market.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+    pub base_token_program: Interface<'info, TokenInterface>,
+    pub quote_token_program: Interface<'info, TokenInterface>,
+}
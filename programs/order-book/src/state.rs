@@ -0,0 +1,121 @@
+use anchor_lang::prelude::*;
+
+use crate::event_queue::FillEvent;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderType {
+    /// Takes what crosses, rests the remainder
+    Limit,
+    /// Takes what crosses, drops the remainder
+    ImmediateOrCancel,
+    /// Rests in full or fails if it would cross
+    PostOnly,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Market {
+    /// Base token mint address
+    pub base_mint: Pubkey,
+    /// Quote token mint address
+    pub quote_mint: Pubkey,
+    /// Vault holding deposited base tokens
+    pub base_vault: Pubkey,
+    /// Vault holding deposited quote tokens
+    pub quote_vault: Pubkey,
+    /// Bid side of the book
+    pub bids: Pubkey,
+    /// Ask side of the book
+    pub asks: Pubkey,
+    /// Fills waiting to be credited to makers
+    pub event_queue: Pubkey,
+    /// Base tokens per base lot; order sizes are in base lots
+    pub base_lot_size: u64,
+    /// Quote tokens per quote lot; prices are quote lots per base lot
+    pub quote_lot_size: u64,
+    /// Sequence number of the next order
+    pub seq_num: u64,
+    /// Authority of the market
+    pub authority: Pubkey,
+    /// Bump seed for the vault authority PDA
+    pub bump: u8,
+}
+
+impl Market {
+    pub const LEN: usize = 32 + // base_mint
+                           32 + // quote_mint
+                           32 + // base_vault
+                           32 + // quote_vault
+                           32 + // bids
+                           32 + // asks
+                           32 + // event_queue
+                           8 +  // base_lot_size
+                           8 +  // quote_lot_size
+                           8 +  // seq_num
+                           32 + // authority
+                           1;   // bump
+
+    /// Base tokens in `base_lots`
+    pub fn base_amount(&self, base_lots: u64) -> Option<u64> {
+        base_lots.checked_mul(self.base_lot_size)
+    }
+
+    /// Quote tokens paid for `base_lots` at `price`
+    pub fn quote_amount(&self, base_lots: u64, price: u64) -> Option<u64> {
+        let amount = (base_lots as u128)
+            .checked_mul(price as u128)?
+            .checked_mul(self.quote_lot_size as u128)?;
+        u64::try_from(amount).ok()
+    }
+}
+
+/// A user's balances on one market. Locked funds back resting orders; free
+/// funds are withdrawn with `settle_funds`.
+#[account]
+#[derive(Default)]
+pub struct OpenOrders {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub base_free: u64,
+    pub base_locked: u64,
+    pub quote_free: u64,
+    pub quote_locked: u64,
+}
+
+impl OpenOrders {
+    pub const LEN: usize = 32 + // market
+                           32 + // owner
+                           8 +  // base_free
+                           8 +  // base_locked
+                           8 +  // quote_free
+                           8;   // quote_locked
+
+    /// Credits the fill of one of this account's resting orders.
+    pub fn apply_fill(&mut self, event: &FillEvent) -> Option<()> {
+        if event.side == Side::Bid as u8 {
+            let refund = event.quote_unlocked.checked_sub(event.quote_quantity)?;
+            self.quote_locked = self.quote_locked.checked_sub(event.quote_unlocked)?;
+            self.quote_free = self.quote_free.checked_add(refund)?;
+            self.base_free = self.base_free.checked_add(event.base_quantity)?;
+        } else {
+            self.base_locked = self.base_locked.checked_sub(event.base_quantity)?;
+            self.quote_free = self.quote_free.checked_add(event.quote_quantity)?;
+        }
+        Some(())
+    }
+}
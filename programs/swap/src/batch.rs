@@ -0,0 +1,189 @@
+//! Frequent batch auctions. Swap intents queued during one slot clear
+//! together at a single price: flow in opposite directions is matched
+//! against itself, and only the imbalance trades against the curve, so
+//! ordering within the slot is worth nothing to a searcher.
+
+use anchor_lang::prelude::*;
+
+/// Intents a batch can hold before it must be cleared
+pub const BATCH_CAPACITY: usize = 32;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct BatchIntent {
+    /// User who queued the intent, and may claim what cannot be paid
+    pub owner: Pubkey,
+    /// Refunded to if the clearing price misses the limit
+    pub user_token_in_account: Pubkey,
+    /// Paid the intent's output
+    pub user_token_out_account: Pubkey,
+    /// Input that reached the pool vault
+    pub amount_in: u64,
+    /// Least output the user accepts, after any transfer fee
+    pub minimum_amount_out: u64,
+    /// Direction of the swap
+    pub a_to_b: bool,
+}
+
+impl BatchIntent {
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 1;
+}
+
+/// Proceeds `clear_batch` could not pay to the account an intent named,
+/// because it was closed, frozen or reassigned since queuing. They wait in
+/// the batch escrow until `owner` claims them. Each owner has their own,
+/// opened before they can queue, so one owner's claims never crowd out
+/// another's.
+#[account]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct BatchClaim {
+    pub swap: Pubkey,
+    pub owner: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+impl BatchClaim {
+    pub const LEN: usize = 32 + // swap
+                           32 + // owner
+                           8 +  // amount_a
+                           8;   // amount_b
+
+    /// Holds `amount` more of token A, or of token B
+    pub fn credit(&mut self, token_a: bool, amount: u64) -> Option<()> {
+        let held = if token_a { &mut self.amount_a } else { &mut self.amount_b };
+        *held = held.checked_add(amount)?;
+        Some(())
+    }
+}
+
+/// Open batch of a pool in batch-auction mode. Queued input already sits in
+/// the pool vaults, so the pool's reserves are the vault balances less the
+/// pending amounts.
+#[account]
+pub struct SwapBatch {
+    pub swap: Pubkey,
+    /// Slot the queued intents were submitted in
+    pub slot: u64,
+    /// Queued input per token
+    pub pending_a: u64,
+    pub pending_b: u64,
+    pub intents: Vec<BatchIntent>,
+}
+
+impl SwapBatch {
+    pub const LEN: usize = 32 + // swap
+                           8 +  // slot
+                           8 +  // pending_a
+                           8 +  // pending_b
+                           4 + BatchIntent::LEN * BATCH_CAPACITY; // intents
+}
+
+/// Output of each of `intents` when they all clear at one price against a
+/// constant-product pool. Each A seller is paid `p` token B per token A and
+/// each B seller `1 / p` token A per token B, where `p` is chosen so the
+/// curve absorbs the net imbalance at exactly that average price. With
+/// more A sold than B bought that is
+/// `p = γ(reserve_b + b_in) / (reserve_a + γ a_in)`, `γ` the fee multiplier,
+/// and symmetrically for B. Outputs round down, in the pool's favour.
+pub fn clear_intents(
+    intents: &[BatchIntent],
+    reserve_a: u64,
+    reserve_b: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<Vec<u64>> {
+    let (mut a_in, mut b_in) = (0u128, 0u128);
+    for intent in intents {
+        if intent.a_to_b {
+            a_in += intent.amount_in as u128;
+        } else {
+            b_in += intent.amount_in as u128;
+        }
+    }
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator)? as u128;
+    let fee_denominator = fee_denominator as u128;
+    let (reserve_a, reserve_b) = (reserve_a as u128, reserve_b as u128);
+
+    // Price of A in B as numerator / denominator, taking the A-heavy side
+    // first and falling back to the B-heavy one if A sellers cannot cover
+    // the B sellers at it
+    let numerator = fee_multiplier.checked_mul(reserve_b.checked_add(b_in)?)?;
+    let denominator = reserve_a
+        .checked_mul(fee_denominator)?
+        .checked_add(fee_multiplier.checked_mul(a_in)?)?;
+    let (numerator, denominator) = if a_in.checked_mul(numerator)? >= b_in.checked_mul(denominator)? {
+        (numerator, denominator)
+    } else {
+        (
+            reserve_b
+                .checked_mul(fee_denominator)?
+                .checked_add(fee_multiplier.checked_mul(b_in)?)?,
+            fee_multiplier.checked_mul(reserve_a.checked_add(a_in)?)?,
+        )
+    };
+    if numerator == 0 || denominator == 0 {
+        return None;
+    }
+
+    intents
+        .iter()
+        .map(|intent| {
+            let amount = intent.amount_in as u128;
+            let out = if intent.a_to_b {
+                amount.checked_mul(numerator)? / denominator
+            } else {
+                amount.checked_mul(denominator)? / numerator
+            };
+            u64::try_from(out).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(a_to_b: bool, amount_in: u64) -> BatchIntent {
+        BatchIntent { a_to_b, amount_in, ..Default::default() }
+    }
+
+    #[test]
+    fn one_sided_batch_matches_the_curve() {
+        // Same as a single constant-product swap of the combined input
+        let outputs = clear_intents(&[intent(true, 600_000), intent(true, 400_000)], 1_000_000, 1_000_000, 0, 1);
+        assert_eq!(outputs, Some(vec![300_000, 200_000]));
+        let outputs = clear_intents(&[intent(false, 1_000_000)], 1_000_000, 1_000_000, 0, 1);
+        assert_eq!(outputs, Some(vec![500_000]));
+    }
+
+    #[test]
+    fn opposing_flow_nets_before_the_curve() {
+        // Balanced flow clears at the spot price without moving the pool
+        let outputs = clear_intents(&[intent(true, 1_000), intent(false, 2_000)], 1_000_000, 2_000_000, 0, 1);
+        assert_eq!(outputs, Some(vec![2_000, 1_000]));
+
+        // Only the imbalance pays price impact, so both sides beat trading alone
+        let netted = clear_intents(&[intent(true, 500_000), intent(false, 250_000)], 1_000_000, 1_000_000, 0, 1).unwrap();
+        let a_alone = clear_intents(&[intent(true, 500_000)], 1_000_000, 1_000_000, 0, 1).unwrap();
+        let b_alone = clear_intents(&[intent(false, 250_000)], 1_000_000, 1_000_000, 0, 1).unwrap();
+        assert!(netted[0] > a_alone[0]);
+        assert!(netted[1] > b_alone[0]);
+    }
+
+    #[test]
+    fn claim_accumulates_both_tokens() {
+        let mut claim = BatchClaim::default();
+        assert_eq!(claim.credit(true, 100), Some(()));
+        assert_eq!(claim.credit(false, 50), Some(()));
+        assert_eq!(claim.credit(true, 1), Some(()));
+        assert_eq!((claim.amount_a, claim.amount_b), (101, 50));
+        assert_eq!(claim.credit(false, u64::MAX), None);
+    }
+
+    #[test]
+    fn fee_is_charged_on_the_imbalance() {
+        let outputs = clear_intents(&[intent(true, 1_000)], 1_000_000, 1_000_000, 30, 10_000).unwrap();
+        assert!(outputs[0] < 999);
+        assert_eq!(clear_intents(&[], 1_000_000, 1_000_000, 30, 10_000), Some(vec![]));
+    }
+}
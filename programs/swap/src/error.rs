@@ -19,4 +19,70 @@ pub enum ErrorCode {
     
     #[msg("Invalid mint")]
     InvalidMint,
+    
+    #[msg("Math overflow")]
+    MathOverflow,
+    
+    #[msg("Route accounts are malformed")]
+    InvalidRoute,
+    
+    #[msg("Requested observation is older than the oracle's history")]
+    ObservationTooOld,
+    
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Pool is paused")]
+    PoolPaused,
+    
+    #[msg("Order amount must be non-zero and within what remains")]
+    InvalidOrderAmount,
+    
+    #[msg("Limit order has expired")]
+    OrderExpired,
+    
+    #[msg("Pool price has not reached the order's limit")]
+    LimitPriceNotReached,
+    
+    #[msg("Revealed swap does not match the commitment")]
+    CommitmentMismatch,
+    
+    #[msg("Commitment cannot be revealed yet")]
+    RevealTooEarly,
+    
+    #[msg("Commitment has expired")]
+    CommitmentExpired,
+    
+    #[msg("Pool only trades through batch auctions")]
+    BatchAuctionsOnly,
+    
+    #[msg("Batch is full")]
+    BatchFull,
+    
+    #[msg("Previous batch has not been cleared")]
+    BatchNotCleared,
+    
+    #[msg("Batch is still accepting intents")]
+    BatchStillOpen,
+    
+    #[msg("Batch has no intents")]
+    BatchEmpty,
+    
+    #[msg("Token account of a queued intent was not supplied")]
+    MissingBatchAccount,
+    
+    #[msg("Guardrail deviation must be non-zero and its window non-zero and within what the oracle keeps")]
+    InvalidGuardrail,
+    
+    #[msg("Pool's guardrail oracle or reference pool accounts were not supplied")]
+    GuardrailOracleMissing,
+    
+    #[msg("Guardrail oracle has no TWAP over its window")]
+    GuardrailOracleStale,
+    
+    #[msg("Swap moves the price too far from the guardrail reference")]
+    PriceDeviationExceeded,
+    
+    #[msg("No batch proceeds to claim")]
+    NothingToClaim,
 }
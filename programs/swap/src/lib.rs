@@ -1,11 +1,20 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer, MintTo};
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::system_program;
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token::{self, Token};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 
+mod batch;
 mod error;
+mod oracle;
 mod state;
 
+pub use batch::*;
 use error::ErrorCode;
-use state::*;
+pub use oracle::*;
+pub use state::*;
+use transfer_fee::{amount_after_fee, harvest_withheld_fees};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
@@ -27,6 +36,12 @@ pub mod swap {
         swap.fee_denominator = fee_denominator;
         swap.authority = ctx.accounts.authority.key();
         swap.bump = *ctx.bumps.get("swap_authority").unwrap();
+        // Start the price clock now, so the first reading does not
+        // integrate from the Unix epoch
+        swap.last_observation = Observation {
+            timestamp: Clock::get()?.unix_timestamp,
+            ..Default::default()
+        };
         
         // Validate fee
         require!(
@@ -37,78 +52,1549 @@ pub mod swap {
         Ok(())
     }
 
-    pub fn swap_tokens(ctx: Context<Swap>, amount_in: u64, minimum_amount_out: u64) -> Result<()> {
+    pub fn swap_tokens<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<()> {
+        execute_swap(
+            &mut ctx.accounts.pool,
+            &ctx.accounts.user_token_in_account,
+            &ctx.accounts.user_token_out_account,
+            &ctx.accounts.user.to_account_info(),
+            ctx.remaining_accounts,
+            amount_in,
+            minimum_amount_out,
+        )
+    }
+
+    /// `swap_tokens` with SOL on one side. When SOL is the input, `amount_in`
+    /// lamports are wrapped into a temporary wSOL account first; when it is
+    /// the output, the swap pays into that account. Either way the account is
+    /// closed before returning, so the user only ever holds lamports.
+    pub fn swap_tokens_native<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapNative<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<()> {
+        let sol_in = ctx.accounts.pool.mint_in.key() == native_mint::ID;
+        if sol_in {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.wsol_account.to_account_info(),
+                    },
+                ),
+                amount_in,
+            )?;
+            token::sync_native(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::SyncNative {
+                    account: ctx.accounts.wsol_account.to_account_info(),
+                },
+            ))?;
+        }
+        
+        let (user_token_in_account, user_token_out_account) = if sol_in {
+            (&*ctx.accounts.wsol_account, &ctx.accounts.user_token_account)
+        } else {
+            (&ctx.accounts.user_token_account, &*ctx.accounts.wsol_account)
+        };
+        execute_swap(
+            &mut ctx.accounts.pool,
+            user_token_in_account,
+            user_token_out_account,
+            &ctx.accounts.user.to_account_info(),
+            ctx.remaining_accounts,
+            amount_in,
+            minimum_amount_out,
+        )?;
+        
+        token::close_account(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ))?;
+        
+        Ok(())
+    }
+
+    /// Records `hash`, the `commitment_hash` of a swap to be executed later
+    /// with `reveal_swap`. Nothing about the swap is visible until then.
+    pub fn commit_swap(ctx: Context<CommitSwap>, hash: [u8; 32]) -> Result<()> {
+        let commitment = &mut ctx.accounts.commitment;
+        commitment.owner = ctx.accounts.user.key();
+        commitment.hash = hash;
+        commitment.slot = Clock::get()?.slot;
+        
+        Ok(())
+    }
+
+    /// Executes a committed swap like `swap_tokens` once the commitment
+    /// is `MIN_REVEAL_DELAY_SLOTS` old and at most `REVEAL_WINDOW_SLOTS`
+    /// old, then closes the commitment.
+    pub fn reveal_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, RevealSwap<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let commitment = &ctx.accounts.commitment;
+        let slot = Clock::get()?.slot;
+        require!(
+            slot >= commitment.slot + MIN_REVEAL_DELAY_SLOTS,
+            ErrorCode::RevealTooEarly
+        );
+        require!(
+            slot <= commitment.slot + REVEAL_WINDOW_SLOTS,
+            ErrorCode::CommitmentExpired
+        );
+        let hash = commitment_hash(
+            &ctx.accounts.pool.swap.key(),
+            &ctx.accounts.user_token_in_account.key(),
+            &ctx.accounts.user_token_out_account.key(),
+            amount_in,
+            minimum_amount_out,
+            &salt,
+        );
+        require!(hash == commitment.hash, ErrorCode::CommitmentMismatch);
+        
+        execute_swap(
+            &mut ctx.accounts.pool,
+            &ctx.accounts.user_token_in_account,
+            &ctx.accounts.user_token_out_account,
+            &ctx.accounts.user.to_account_info(),
+            ctx.remaining_accounts,
+            amount_in,
+            minimum_amount_out,
+        )
+    }
+
+    /// Closes an unrevealed commitment, returning its rent.
+    pub fn close_commitment(_ctx: Context<CloseCommitment>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Swaps through an ordered list of pools in one instruction. Each hop
+    /// takes `HOP_ACCOUNTS` remaining accounts:
+    /// `[swap, pool token in, pool token out, swap authority, user token out,
+    /// mint in, mint out, token program in, token program out]`.
+    /// A hop's output lands in the user's account and becomes the next hop's
+    /// input; only the final output is checked against `minimum_amount_out`.
+    /// The `hops` hops are followed by whatever the pools' guardrails read:
+    /// each guarded pool's oracle, plus the pool and vaults behind it when
+    /// the oracle belongs to another pool.
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, RouteSwap<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        hops: u8,
+    ) -> Result<()> {
+        let hop_accounts_len = hops as usize * HOP_ACCOUNTS;
+        require!(
+            hops > 0 && ctx.remaining_accounts.len() >= hop_accounts_len,
+            ErrorCode::InvalidRoute
+        );
+        let (hop_accounts, guardrail_accounts) = ctx.remaining_accounts.split_at(hop_accounts_len);
+
+        let mut user_token_in_account = ctx.accounts.user_token_in_account.clone();
+        let mut amount = amount_in;
+        for hop in hop_accounts.chunks_exact(HOP_ACCOUNTS) {
+            let mut swap = Account::<SwapInfo>::try_from(&hop[0])?;
+            require!(!swap.paused, ErrorCode::PoolPaused);
+            require!(!swap.batch_auctions, ErrorCode::BatchAuctionsOnly);
+            let token_in_account = InterfaceAccount::<TokenAccount>::try_from(&hop[1])?;
+            let token_out_account = InterfaceAccount::<TokenAccount>::try_from(&hop[2])?;
+            let swap_authority = &hop[3];
+            let user_token_out_account = InterfaceAccount::<TokenAccount>::try_from(&hop[4])?;
+            let mint_in = InterfaceAccount::<Mint>::try_from(&hop[5])?;
+            let mint_out = InterfaceAccount::<Mint>::try_from(&hop[6])?;
+            let token_in_program = Interface::<TokenInterface>::try_from(&hop[7])?;
+            let token_out_program = Interface::<TokenInterface>::try_from(&hop[8])?;
+
+            let pool_accounts = [swap.token_a_account, swap.token_b_account];
+            require!(
+                token_in_account.key() != token_out_account.key()
+                    && pool_accounts.contains(&token_in_account.key())
+                    && pool_accounts.contains(&token_out_account.key()),
+                ErrorCode::InvalidTokenAccount
+            );
+            require!(
+                user_token_in_account.mint == token_in_account.mint
+                    && user_token_out_account.mint == token_out_account.mint
+                    && mint_in.key() == token_in_account.mint
+                    && mint_out.key() == token_out_account.mint,
+                ErrorCode::InvalidMint
+            );
+            let (reserve_a, reserve_b) = if token_in_account.key() == swap.token_a_account {
+                (token_in_account.amount, token_out_account.amount)
+            } else {
+                (token_out_account.amount, token_in_account.amount)
+            };
+            // A guardrail reading the pool's own oracle needs it brought up to now
+            let mut pool_oracle = match swap.guardrail {
+                Some(guardrail) => {
+                    let oracle = Account::<PriceOracle>::try_from(find_guardrail_account(
+                        guardrail_accounts,
+                        guardrail.oracle,
+                    )?)?;
+                    (oracle.pool == swap.key()).then_some(oracle)
+                }
+                None => None,
+            };
+            record_observation(&mut swap, pool_oracle.as_mut(), reserve_a, reserve_b)?;
+            swap.exit(ctx.program_id)?;
+            if let Some(oracle) = &pool_oracle {
+                oracle.exit(ctx.program_id)?;
+            }
+            
+            let swap_key = swap.key();
+            let swap_authority_seeds = &[swap_key.as_ref(), &[swap.bump]];
+            let expected_authority =
+                Pubkey::create_program_address(swap_authority_seeds, ctx.program_id)
+                    .map_err(|_| ErrorCode::InvalidRoute)?;
+            require_keys_eq!(swap_authority.key(), expected_authority, ErrorCode::InvalidRoute);
+
+            let received_in = amount_after_fee(&mint_in.to_account_info(), amount)?;
+            let amount_out = calculate_swap_amount(
+                token_in_account.amount,
+                token_out_account.amount,
+                received_in,
+                swap.fee_numerator,
+                swap.fee_denominator,
+            )
+            .ok_or(ErrorCode::MathOverflow)?;
+            require!(amount_out > 0, ErrorCode::InsufficientLiquidity);
+
+            let reserve_in_after = token_in_account
+                .amount
+                .checked_add(received_in)
+                .ok_or(ErrorCode::MathOverflow)?;
+            let reserve_out_after = token_out_account.amount - amount_out;
+            let (reserve_a_after, reserve_b_after) = if token_in_account.key() == swap.token_a_account {
+                (reserve_in_after, reserve_out_after)
+            } else {
+                (reserve_out_after, reserve_in_after)
+            };
+            require!(
+                guardrail_allows(
+                    &swap,
+                    pool_oracle.as_ref(),
+                    guardrail_accounts,
+                    reserve_a_after,
+                    reserve_b_after,
+                )?,
+                ErrorCode::PriceDeviationExceeded
+            );
+
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    token_in_program.to_account_info(),
+                    TransferChecked {
+                        from: user_token_in_account.to_account_info(),
+                        mint: mint_in.to_account_info(),
+                        to: token_in_account.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount,
+                mint_in.decimals,
+            )?;
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    token_out_program.to_account_info(),
+                    TransferChecked {
+                        from: token_out_account.to_account_info(),
+                        mint: mint_out.to_account_info(),
+                        to: user_token_out_account.to_account_info(),
+                        authority: swap_authority.clone(),
+                    },
+                    &[swap_authority_seeds],
+                ),
+                amount_out,
+                mint_out.decimals,
+            )?;
+
+            // The next hop spends what actually arrived
+            user_token_in_account = user_token_out_account;
+            amount = amount_after_fee(&mint_out.to_account_info(), amount_out)?;
+        }
+
+        require!(
+            amount >= minimum_amount_out,
+            ErrorCode::SlippageExceeded
+        );
+
+        Ok(())
+    }
+
+    pub fn set_fees(ctx: Context<UpdateSwap>, fee_numerator: u64, fee_denominator: u64) -> Result<()> {
+        require!(
+            fee_denominator > 0 && fee_numerator < fee_denominator,
+            ErrorCode::InvalidFee
+        );
+        let swap = &mut ctx.accounts.swap;
+        swap.fee_numerator = fee_numerator;
+        swap.fee_denominator = fee_denominator;
+        
+        Ok(())
+    }
+
+    /// First step of an authority transfer; takes effect once
+    /// `new_authority` calls `accept_authority`.
+    pub fn set_authority(ctx: Context<UpdateSwap>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.swap.pending_authority = new_authority;
+        
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let swap = &mut ctx.accounts.swap;
+        swap.authority = swap.pending_authority;
+        swap.pending_authority = Pubkey::default();
+        
+        Ok(())
+    }
+
+    pub fn pause(ctx: Context<UpdateSwap>) -> Result<()> {
+        ctx.accounts.swap.paused = true;
+        
+        Ok(())
+    }
+
+    pub fn unpause(ctx: Context<UpdateSwap>) -> Result<()> {
+        ctx.accounts.swap.paused = false;
+        
+        Ok(())
+    }
+
+    /// Bounds every later swap so the pool's post-trade price stays within
+    /// `max_deviation_bps` of the `twap_window`-second TWAP read from
+    /// `reference_oracle`, which belongs to this pool or to another pool
+    /// for the same pair. Swaps against another pool's oracle pass that
+    /// oracle, the pool and its two vaults as remaining accounts. The
+    /// window may not exceed `MAX_TWAP_WINDOW`, the history an oracle is
+    /// guaranteed to hold.
+    pub fn set_guardrail(ctx: Context<SetGuardrail>, twap_window: u32, max_deviation_bps: u16) -> Result<()> {
+        require!(
+            twap_window > 0 && twap_window <= MAX_TWAP_WINDOW && max_deviation_bps > 0,
+            ErrorCode::InvalidGuardrail
+        );
         let swap = &ctx.accounts.swap;
-        let token_in_account = &ctx.accounts.token_in_account;
-        let token_out_account = &ctx.accounts.token_out_account;
-        let user_token_in_account = &ctx.accounts.user_token_in_account;
-        let user_token_out_account = &ctx.accounts.user_token_out_account;
+        let reference = &ctx.accounts.reference_swap;
+        let inverted = if reference.token_a_mint == swap.token_a_mint && reference.token_b_mint == swap.token_b_mint {
+            false
+        } else if reference.token_a_mint == swap.token_b_mint && reference.token_b_mint == swap.token_a_mint {
+            true
+        } else {
+            return err!(ErrorCode::InvalidMint);
+        };
+        
+        ctx.accounts.swap.guardrail = Some(PriceGuardrail {
+            oracle: ctx.accounts.reference_oracle.key(),
+            inverted,
+            twap_window,
+            max_deviation_bps,
+        });
+        
+        Ok(())
+    }
 
-        // Calculate the amount out using constant product formula
-        let amount_out = calculate_swap_amount(
-            token_in_account.amount,
-            token_out_account.amount,
+    pub fn clear_guardrail(ctx: Context<UpdateSwap>) -> Result<()> {
+        ctx.accounts.swap.guardrail = None;
+        
+        Ok(())
+    }
+
+    pub fn initialize_oracle(ctx: Context<InitializeOracle>) -> Result<()> {
+        let oracle = &mut ctx.accounts.oracle;
+        oracle.pool = ctx.accounts.swap.key();
+        oracle.ring = ObservationRing::new();
+        oracle.ring.write(ctx.accounts.swap.last_observation);
+        
+        Ok(())
+    }
+
+    /// Cumulative prices as of `seconds_ago` seconds before now, for each
+    /// entry. Callers derive a TWAP by differencing two readings with `twap`.
+    pub fn observe(ctx: Context<Observe>, seconds_ago: Vec<u32>) -> Result<Vec<Observation>> {
+        let now = Clock::get()?.unix_timestamp;
+        let latest = ctx.accounts.swap.last_observation.accumulate(
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+            now,
+        );
+        
+        seconds_ago
+            .iter()
+            .map(|seconds| {
+                ctx.accounts
+                    .oracle
+                    .ring
+                    .observe(&latest, now - *seconds as i64)
+                    .ok_or_else(|| error!(ErrorCode::ObservationTooOld))
+            })
+            .collect()
+    }
+
+    /// Escrows `amount_in` of one pool token until the pool pays at least
+    /// `min_amount_out` of the other for it, or `expires_at` passes.
+    pub fn place_limit_order(
+        ctx: Context<PlaceLimitOrder>,
+        order_id: u64,
+        amount_in: u64,
+        min_amount_out: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(
+            amount_in > 0 && min_amount_out > 0,
+            ErrorCode::InvalidOrderAmount
+        );
+        require!(
+            expires_at > Clock::get()?.unix_timestamp,
+            ErrorCode::OrderExpired
+        );
+        
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_in_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_in_account.to_account_info(),
+                    mint: ctx.accounts.mint_in.to_account_info(),
+                    to: ctx.accounts.escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
             amount_in,
+            ctx.accounts.mint_in.decimals,
+        )?;
+        ctx.accounts.escrow.reload()?;
+        
+        let order = &mut ctx.accounts.order;
+        order.owner = ctx.accounts.owner.key();
+        order.swap = ctx.accounts.swap.key();
+        order.mint_in = ctx.accounts.mint_in.key();
+        order.mint_out = ctx.accounts.mint_out.key();
+        order.escrow = ctx.accounts.escrow.key();
+        order.amount_in = amount_in;
+        order.min_amount_out = min_amount_out;
+        // Only what survived any transfer fee can be filled
+        order.remaining_in = ctx.accounts.escrow.amount;
+        order.expires_at = expires_at;
+        order.order_id = order_id;
+        order.bump = *ctx.bumps.get("order").unwrap();
+        
+        Ok(())
+    }
+
+    /// Swaps `amount` of a limit order's escrow through its pool, paying the
+    /// owner. Anyone may fill, but only at the order's price or better.
+    pub fn fill_limit_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, FillLimitOrder<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let order = &ctx.accounts.order;
+        require!(
+            Clock::get()?.unix_timestamp < order.expires_at,
+            ErrorCode::OrderExpired
+        );
+        require!(
+            amount > 0 && amount <= order.remaining_in,
+            ErrorCode::InvalidOrderAmount
+        );
+        
+        let (reserve_a, reserve_b) = if ctx.accounts.token_in_account.key() == ctx.accounts.swap.token_a_account {
+            (ctx.accounts.token_in_account.amount, ctx.accounts.token_out_account.amount)
+        } else {
+            (ctx.accounts.token_out_account.amount, ctx.accounts.token_in_account.amount)
+        };
+        record_observation(&mut ctx.accounts.swap, ctx.accounts.oracle.as_mut(), reserve_a, reserve_b)?;
+        
+        let swap = &ctx.accounts.swap;
+        let received_in = amount_after_fee(&ctx.accounts.mint_in.to_account_info(), amount)?;
+        let amount_out = calculate_swap_amount(
+            ctx.accounts.token_in_account.amount,
+            ctx.accounts.token_out_account.amount,
+            received_in,
             swap.fee_numerator,
             swap.fee_denominator,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        let amount_received = amount_after_fee(&ctx.accounts.mint_out.to_account_info(), amount_out)?;
+        require!(
+            amount_received >= order.min_fill_out(amount).ok_or(ErrorCode::MathOverflow)?,
+            ErrorCode::LimitPriceNotReached
         );
         
-        // Check slippage tolerance
+        let reserve_in_after = ctx
+            .accounts
+            .token_in_account
+            .amount
+            .checked_add(received_in)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let reserve_out_after = ctx.accounts.token_out_account.amount - amount_out;
+        let (reserve_a_after, reserve_b_after) = if ctx.accounts.token_in_account.key() == swap.token_a_account {
+            (reserve_in_after, reserve_out_after)
+        } else {
+            (reserve_out_after, reserve_in_after)
+        };
         require!(
-            amount_out >= minimum_amount_out,
-            ErrorCode::SlippageExceeded
+            guardrail_allows(
+                swap,
+                ctx.accounts.oracle.as_ref(),
+                ctx.remaining_accounts,
+                reserve_a_after,
+                reserve_b_after,
+            )?,
+            ErrorCode::PriceDeviationExceeded
         );
+        
+        let owner = order.owner;
+        let order_id = order.order_id.to_le_bytes();
+        let order_seeds = &[b"limit_order".as_ref(), owner.as_ref(), order_id.as_ref(), &[order.bump]];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_in_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    mint: ctx.accounts.mint_in.to_account_info(),
+                    to: ctx.accounts.token_in_account.to_account_info(),
+                    authority: order.to_account_info(),
+                },
+                &[order_seeds],
+            ),
+            amount,
+            ctx.accounts.mint_in.decimals,
+        )?;
+        
+        let swap_key = swap.key();
+        let swap_authority_seeds = &[swap_key.as_ref(), &[swap.bump]];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_out_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.token_out_account.to_account_info(),
+                    mint: ctx.accounts.mint_out.to_account_info(),
+                    to: ctx.accounts.owner_token_out_account.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[swap_authority_seeds],
+            ),
+            amount_out,
+            ctx.accounts.mint_out.decimals,
+        )?;
+        
+        ctx.accounts.order.remaining_in -= amount;
+        
+        Ok(())
+    }
+
+    /// Refunds what is left of a limit order to its owner and closes it.
+    /// Transfer fees withheld in the escrow go to the mint first, since
+    /// Token-2022 will not close the escrow while it holds any.
+    pub fn cancel_limit_order(ctx: Context<CancelLimitOrder>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let order_id = order.order_id.to_le_bytes();
+        let order_seeds = &[b"limit_order".as_ref(), order.owner.as_ref(), order_id.as_ref(), &[order.bump]];
+        
+        if ctx.accounts.escrow.amount > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_in_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        mint: ctx.accounts.mint_in.to_account_info(),
+                        to: ctx.accounts.user_token_in_account.to_account_info(),
+                        authority: order.to_account_info(),
+                    },
+                    &[order_seeds],
+                ),
+                ctx.accounts.escrow.amount,
+                ctx.accounts.mint_in.decimals,
+            )?;
+        }
+        harvest_withheld_fees(
+            &ctx.accounts.token_in_program.to_account_info(),
+            &ctx.accounts.mint_in.to_account_info(),
+            &ctx.accounts.escrow.to_account_info(),
+        )?;
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_in_program.to_account_info(),
+            token_interface::CloseAccount {
+                account: ctx.accounts.escrow.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: order.to_account_info(),
+            },
+            &[order_seeds],
+        ))?;
+        
+        Ok(())
+    }
+
+    /// Creates the pool's batch and its escrow accounts and switches it to
+    /// batch-auction mode, in which swaps are queued with `queue_batch_swap`
+    /// and settled together by `clear_batch`.
+    pub fn initialize_batch(ctx: Context<InitializeBatch>) -> Result<()> {
+        ctx.accounts.batch.swap = ctx.accounts.swap.key();
+        ctx.accounts.swap.batch_auctions = true;
+        
+        Ok(())
+    }
+
+    /// Turns batch-auction mode on or off. It can only be turned off with
+    /// no intents waiting to clear.
+    pub fn set_batch_auctions(ctx: Context<SetBatchAuctions>, enabled: bool) -> Result<()> {
+        require!(
+            enabled || ctx.accounts.batch.intents.is_empty(),
+            ErrorCode::BatchNotCleared
+        );
+        ctx.accounts.swap.batch_auctions = enabled;
+        
+        Ok(())
+    }
+
+    /// Opens the signer's claim on a batch-auction pool, where `clear_batch`
+    /// holds proceeds it cannot pay them. Required before queuing.
+    pub fn open_batch_claim(ctx: Context<OpenBatchClaim>) -> Result<()> {
+        let claim = &mut ctx.accounts.claim;
+        claim.swap = ctx.accounts.swap.key();
+        claim.owner = ctx.accounts.user.key();
+        
+        Ok(())
+    }
 
-        // Transfer tokens in
-        let cpi_accounts = Transfer {
-            from: user_token_in_account.to_account_info(),
-            to: token_in_account.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
+    /// Queues a swap of `amount_in` into the current slot's batch. The input
+    /// moves into the pool vault now; the output, or a refund if the
+    /// clearing price misses `minimum_amount_out`, is paid by `clear_batch`.
+    pub fn queue_batch_swap(
+        ctx: Context<QueueBatchSwap>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<()> {
+        let swap = &ctx.accounts.swap;
+        let a_to_b = ctx.accounts.token_in_account.key() == swap.token_a_account;
+        let mint_out = if a_to_b { swap.token_b_mint } else { swap.token_a_mint };
+        require!(
+            ctx.accounts.user_token_out_account.mint == mint_out,
+            ErrorCode::InvalidMint
+        );
+        
+        let slot = Clock::get()?.slot;
+        let batch = &mut ctx.accounts.batch;
+        if batch.intents.is_empty() {
+            batch.slot = slot;
+        }
+        require!(batch.slot == slot, ErrorCode::BatchNotCleared);
+        require!(batch.intents.len() < BATCH_CAPACITY, ErrorCode::BatchFull);
+        
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_in_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_in_account.to_account_info(),
+                    mint: ctx.accounts.mint_in.to_account_info(),
+                    to: ctx.accounts.token_in_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+            ctx.accounts.mint_in.decimals,
+        )?;
+        
+        let received_in = amount_after_fee(&ctx.accounts.mint_in.to_account_info(), amount_in)?;
+        let pending = if a_to_b { &mut batch.pending_a } else { &mut batch.pending_b };
+        *pending = pending.checked_add(received_in).ok_or(ErrorCode::MathOverflow)?;
+        batch.intents.push(BatchIntent {
+            owner: ctx.accounts.user.key(),
+            user_token_in_account: ctx.accounts.user_token_in_account.key(),
+            user_token_out_account: ctx.accounts.user_token_out_account.key(),
+            amount_in: received_in,
+            minimum_amount_out,
+            a_to_b,
+        });
+        
+        Ok(())
+    }
+
+    /// Settles a batch from an earlier slot at one uniform price. Intents
+    /// whose limit the price misses are refunded and the price recomputed
+    /// without them. The intents' token accounts are passed as remaining
+    /// accounts, along with the claim of any owner whose account cannot be
+    /// paid.
+    pub fn clear_batch<'info>(ctx: Context<'_, '_, '_, 'info, ClearBatch<'info>>) -> Result<()> {
+        let batch = &ctx.accounts.batch;
+        require!(!batch.intents.is_empty(), ErrorCode::BatchEmpty);
+        require!(Clock::get()?.slot > batch.slot, ErrorCode::BatchStillOpen);
+        
+        let reserve_a = ctx.accounts.token_a_account.amount - batch.pending_a;
+        let reserve_b = ctx.accounts.token_b_account.amount - batch.pending_b;
+        record_observation(&mut ctx.accounts.swap, ctx.accounts.oracle.as_mut(), reserve_a, reserve_b)?;
+        
+        // Dropping an intent moves the price, so repeat until every
+        // remaining intent is satisfied
+        let swap = &ctx.accounts.swap;
+        let mint_a = ctx.accounts.token_a_mint.to_account_info();
+        let mint_b = ctx.accounts.token_b_mint.to_account_info();
+        let mut filled = ctx.accounts.batch.intents.clone();
+        let mut refunded = Vec::new();
+        let outputs = loop {
+            let outputs = clear_intents(&filled, reserve_a, reserve_b, swap.fee_numerator, swap.fee_denominator)
+                .ok_or(ErrorCode::MathOverflow)?;
+            let mut keep = Vec::with_capacity(filled.len());
+            for (intent, amount_out) in filled.iter().zip(&outputs) {
+                let mint_out = if intent.a_to_b { &mint_b } else { &mint_a };
+                if amount_after_fee(mint_out, *amount_out)? >= intent.minimum_amount_out {
+                    keep.push(*intent);
+                } else {
+                    refunded.push(*intent);
+                }
+            }
+            if keep.len() == filled.len() {
+                break outputs;
+            }
+            filled = keep;
         };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, amount_in)?;
-
-        // Transfer tokens out
-        let cpi_accounts = Transfer {
-            from: token_out_account.to_account_info(),
-            to: user_token_out_account.to_account_info(),
-            authority: ctx.accounts.pool_authority.to_account_info(),
+        
+        // A clearing price the guardrail rejects fills nobody
+        let (mut reserve_a_after, mut reserve_b_after) = (reserve_a, reserve_b);
+        for (intent, amount_out) in filled.iter().zip(&outputs) {
+            let (reserve_in, reserve_out) = if intent.a_to_b {
+                (&mut reserve_a_after, &mut reserve_b_after)
+            } else {
+                (&mut reserve_b_after, &mut reserve_a_after)
+            };
+            *reserve_in = reserve_in.checked_add(intent.amount_in).ok_or(ErrorCode::MathOverflow)?;
+            *reserve_out = reserve_out.checked_sub(*amount_out).ok_or(ErrorCode::MathOverflow)?;
+        }
+        let outputs = if guardrail_allows(
+            swap,
+            ctx.accounts.oracle.as_ref(),
+            ctx.remaining_accounts,
+            reserve_a_after,
+            reserve_b_after,
+        )? {
+            outputs
+        } else {
+            refunded.append(&mut filled);
+            Vec::new()
         };
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, amount_out)?;
+        
+        let swap_key = swap.key();
+        let swap_authority_seeds = &[swap_key.as_ref(), &[swap.bump]];
+        let payouts = filled
+            .iter()
+            .zip(outputs)
+            .map(|(intent, amount_out)| (intent.owner, intent.user_token_out_account, !intent.a_to_b, amount_out))
+            .chain(
+                refunded
+                    .iter()
+                    .map(|intent| (intent.owner, intent.user_token_in_account, intent.a_to_b, intent.amount_in)),
+            );
+        for (owner, recipient, pay_a, amount) in payouts {
+            if amount == 0 {
+                continue;
+            }
+            let recipient = ctx
+                .remaining_accounts
+                .iter()
+                .find(|account| account.key() == recipient)
+                .ok_or(ErrorCode::MissingBatchAccount)?;
+            let (vault, escrow, mint, token_program) = if pay_a {
+                (
+                    &ctx.accounts.token_a_account,
+                    &ctx.accounts.escrow_a,
+                    &ctx.accounts.token_a_mint,
+                    &ctx.accounts.token_a_program,
+                )
+            } else {
+                (
+                    &ctx.accounts.token_b_account,
+                    &ctx.accounts.escrow_b,
+                    &ctx.accounts.token_b_mint,
+                    &ctx.accounts.token_b_program,
+                )
+            };
+            // A recipient closed, frozen or recreated under another mint since
+            // queuing would fail the transfer and every other payout with it,
+            // so its tokens go to escrow, credited to the owner's claim
+            let payable = *recipient.owner == token_program.key()
+                && InterfaceAccount::<TokenAccount>::try_from(recipient)
+                    .is_ok_and(|account| account.mint == mint.key() && !account.is_frozen());
+            let to = if payable {
+                recipient.clone()
+            } else {
+                let mut claim = ctx
+                    .remaining_accounts
+                    .iter()
+                    .filter_map(|account| Account::<BatchClaim>::try_from(account).ok())
+                    .find(|claim| claim.swap == swap_key && claim.owner == owner)
+                    .ok_or(ErrorCode::MissingBatchAccount)?;
+                let held = amount_after_fee(&mint.to_account_info(), amount)?;
+                claim.credit(pay_a, held).ok_or(ErrorCode::MathOverflow)?;
+                claim.exit(ctx.program_id)?;
+                escrow.to_account_info()
+            };
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    TransferChecked {
+                        from: vault.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to,
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    &[swap_authority_seeds],
+                ),
+                amount,
+                mint.decimals,
+            )?;
+        }
+        
+        let batch = &mut ctx.accounts.batch;
+        batch.intents.clear();
+        batch.pending_a = 0;
+        batch.pending_b = 0;
+        
+        Ok(())
+    }
 
+    /// Pays the signer what `clear_batch` held in escrow for them because
+    /// the account their intent named could not take the tokens.
+    pub fn claim_batch_payout(ctx: Context<ClaimBatchPayout>) -> Result<()> {
+        let claim = &mut ctx.accounts.claim;
+        let (amount_a, amount_b) = (claim.amount_a, claim.amount_b);
+        require!(amount_a > 0 || amount_b > 0, ErrorCode::NothingToClaim);
+        claim.amount_a = 0;
+        claim.amount_b = 0;
+        
+        let swap_key = ctx.accounts.swap.key();
+        let swap_authority_seeds = &[swap_key.as_ref(), &[ctx.accounts.swap.bump]];
+        for (amount, escrow, destination, mint, token_program) in [
+            (
+                amount_a,
+                &ctx.accounts.escrow_a,
+                &ctx.accounts.owner_token_a_account,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.token_a_program,
+            ),
+            (
+                amount_b,
+                &ctx.accounts.escrow_b,
+                &ctx.accounts.owner_token_b_account,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.token_b_program,
+            ),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    TransferChecked {
+                        from: escrow.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: destination.to_account_info(),
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    &[swap_authority_seeds],
+                ),
+                amount,
+                mint.decimals,
+            )?;
+        }
+        
         Ok(())
     }
+}
 
-    fn calculate_swap_amount(
-        reserve_in: u64, 
-        reserve_out: u64, 
-        amount_in: u64,
-        fee_numerator: u64,
-        fee_denominator: u64
-    ) -> u64 {
-        // Calculate fee based on provided fee parameters
-        let fee_multiplier = fee_denominator - fee_numerator;
-        let amount_in_with_fee = amount_in * fee_multiplier;
-        let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in * fee_denominator + amount_in_with_fee;
-        numerator / denominator
+/// Trades `amount_in` from `user_token_in_account` against the pool and
+/// pays `user_token_out_account`, for every instruction that swaps. The
+/// accounts come validated by `SwapPool` and the caller's own struct.
+fn execute_swap<'info>(
+    pool: &mut SwapPool<'info>,
+    user_token_in_account: &InterfaceAccount<'info, TokenAccount>,
+    user_token_out_account: &InterfaceAccount<'info, TokenAccount>,
+    user: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<()> {
+    let (reserve_a, reserve_b) = if pool.token_in_account.key() == pool.swap.token_a_account {
+        (pool.token_in_account.amount, pool.token_out_account.amount)
+    } else {
+        (pool.token_out_account.amount, pool.token_in_account.amount)
+    };
+    record_observation(&mut pool.swap, pool.oracle.as_mut(), reserve_a, reserve_b)?;
+    
+    let swap = &pool.swap;
+    let token_in_account = &pool.token_in_account;
+    let token_out_account = &pool.token_out_account;
+
+    // Price on what the pool receives after any Token-2022 transfer fee
+    let received_in = amount_after_fee(&pool.mint_in.to_account_info(), amount_in)?;
+
+    // Calculate the amount out using constant product formula
+    let amount_out = calculate_swap_amount(
+        token_in_account.amount,
+        token_out_account.amount,
+        received_in,
+        swap.fee_numerator,
+        swap.fee_denominator,
+    )
+    .ok_or(ErrorCode::MathOverflow)?;
+    
+    // Check slippage tolerance against what reaches the user
+    let amount_received = amount_after_fee(&pool.mint_out.to_account_info(), amount_out)?;
+    require!(
+        amount_received >= minimum_amount_out,
+        ErrorCode::SlippageExceeded
+    );
+    
+    let reserve_in_after = token_in_account.amount.checked_add(received_in).ok_or(ErrorCode::MathOverflow)?;
+    let reserve_out_after = token_out_account.amount - amount_out;
+    let (reserve_a_after, reserve_b_after) = if token_in_account.key() == swap.token_a_account {
+        (reserve_in_after, reserve_out_after)
+    } else {
+        (reserve_out_after, reserve_in_after)
+    };
+    require!(
+        guardrail_allows(
+            swap,
+            pool.oracle.as_ref(),
+            remaining_accounts,
+            reserve_a_after,
+            reserve_b_after,
+        )?,
+        ErrorCode::PriceDeviationExceeded
+    );
+
+    // Transfer tokens in
+    let cpi_accounts = TransferChecked {
+        from: user_token_in_account.to_account_info(),
+        mint: pool.mint_in.to_account_info(),
+        to: token_in_account.to_account_info(),
+        authority: user.clone(),
+    };
+    let cpi_ctx = CpiContext::new(pool.token_in_program.to_account_info(), cpi_accounts);
+    token_interface::transfer_checked(cpi_ctx, amount_in, pool.mint_in.decimals)?;
+
+    // Transfer tokens out
+    let swap_key = swap.key();
+    let swap_authority_seeds = &[swap_key.as_ref(), &[swap.bump]];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            pool.token_out_program.to_account_info(),
+            TransferChecked {
+                from: token_out_account.to_account_info(),
+                mint: pool.mint_out.to_account_info(),
+                to: user_token_out_account.to_account_info(),
+                authority: pool.pool_authority.to_account_info(),
+            },
+            &[swap_authority_seeds],
+        ),
+        amount_out,
+        pool.mint_out.decimals,
+    )?;
+
+    Ok(())
+}
+
+/// Brings the pool's price accumulators up to now using the reserves
+/// before this instruction moves them, and stores the reading in the oracle
+/// when one is supplied.
+fn record_observation(
+    swap: &mut SwapInfo,
+    oracle: Option<&mut Account<PriceOracle>>,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    swap.last_observation = swap.last_observation.accumulate(reserve_a, reserve_b, now);
+    if let Some(oracle) = oracle {
+        oracle.ring.write(swap.last_observation);
     }
+    Ok(())
+}
+
+/// Whether reserves of `reserve_a` and `reserve_b` after a trade keep the
+/// pool's spot price within its guardrail, if it has one. The reference
+/// oracle is `pool_oracle` when the guardrail uses the pool's own TWAP, and
+/// is otherwise looked up among `remaining_accounts`. A reference belonging
+/// to another pool also needs that pool and its two vaults there, so its
+/// TWAP runs up to now however long the pool has been quiet.
+fn guardrail_allows<'info>(
+    swap: &Account<'info, SwapInfo>,
+    pool_oracle: Option<&Account<'info, PriceOracle>>,
+    remaining_accounts: &[AccountInfo<'info>],
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Result<bool> {
+    let Some(guardrail) = swap.guardrail else {
+        return Ok(true);
+    };
+    let oracle = match pool_oracle.filter(|oracle| oracle.key() == guardrail.oracle) {
+        Some(oracle) => oracle.clone(),
+        None => Account::<PriceOracle>::try_from(find_guardrail_account(remaining_accounts, guardrail.oracle)?)?,
+    };
+    
+    // The pool's own reading was just brought up to now, before this trade
+    let latest = if oracle.pool == swap.key() {
+        swap.last_observation
+    } else {
+        reference_observation(&oracle, remaining_accounts)?
+    };
+    let window = guardrail.twap_window as i64;
+    let start = oracle
+        .ring
+        .observe(&latest, latest.timestamp - window)
+        .ok_or(ErrorCode::ObservationTooOld)?;
+    let (price_a, price_b) = twap(&start, &latest).ok_or(ErrorCode::GuardrailOracleStale)?;
+    let reference = if guardrail.inverted { price_b } else { price_a };
+    
+    let price = spot_price(reserve_a, reserve_b).ok_or(ErrorCode::InsufficientLiquidity)?;
+    Ok(within_deviation(price, reference, guardrail.max_deviation_bps))
+}
+
+/// Reading of the pool behind `oracle` as of now, brought forward from the
+/// reserves in its vaults. Swaps record before they move the reserves, so
+/// only a donation to the vaults changes what the quiet stretch is priced
+/// at, and the donor gives it up.
+fn reference_observation<'info>(
+    oracle: &PriceOracle,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<Observation> {
+    let reference = Account::<SwapInfo>::try_from(find_guardrail_account(remaining_accounts, oracle.pool)?)?;
+    let vault_a = InterfaceAccount::<TokenAccount>::try_from(find_guardrail_account(
+        remaining_accounts,
+        reference.token_a_account,
+    )?)?;
+    let vault_b = InterfaceAccount::<TokenAccount>::try_from(find_guardrail_account(
+        remaining_accounts,
+        reference.token_b_account,
+    )?)?;
+    Ok(reference
+        .last_observation
+        .accumulate(vault_a.amount, vault_b.amount, Clock::get()?.unix_timestamp))
+}
+
+fn find_guardrail_account<'a, 'info>(
+    remaining_accounts: &'a [AccountInfo<'info>],
+    key: Pubkey,
+) -> Result<&'a AccountInfo<'info>> {
+    Ok(remaining_accounts
+        .iter()
+        .find(|account| account.key() == key)
+        .ok_or(ErrorCode::GuardrailOracleMissing)?)
+}
+
+/// Hash committed by `commit_swap` for a swap of `amount_in` on `swap`
+/// between the user's two token accounts.
+pub fn commitment_hash(
+    swap: &Pubkey,
+    user_token_in_account: &Pubkey,
+    user_token_out_account: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    salt: &[u8; 32],
+) -> [u8; 32] {
+    hashv(&[
+        swap.as_ref(),
+        user_token_in_account.as_ref(),
+        user_token_out_account.as_ref(),
+        &amount_in.to_le_bytes(),
+        &minimum_amount_out.to_le_bytes(),
+        salt,
+    ])
+    .to_bytes()
+}
+
+/// Remaining accounts consumed by each hop of `route_swap`
+pub const HOP_ACCOUNTS: usize = 9;
+
+/// Constant product output for `amount_in`, net of the pool fee.
+fn calculate_swap_amount(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u64> {
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator)? as u128;
+    let amount_in_with_fee = (amount_in as u128).checked_mul(fee_multiplier)?;
+    let numerator = amount_in_with_fee.checked_mul(reserve_out as u128)?;
+    let denominator = (reserve_in as u128)
+        .checked_mul(fee_denominator as u128)?
+        .checked_add(amount_in_with_fee)?;
+    u64::try_from(numerator.checked_div(denominator)?).ok()
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = authority, space = 8 + SwapInfo::LEN)]
+    pub swap: Account<'info, SwapInfo>,
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = token_a_account.mint == token_a_mint.key() @ ErrorCode::InvalidMint,
+        constraint = token_a_account.owner == swap_authority.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_b_account.mint == token_b_mint.key() @ ErrorCode::InvalidMint,
+        constraint = token_b_account.owner == swap_authority.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns the pool token accounts
+    #[account(seeds = [swap.key().as_ref()], bump)]
+    pub swap_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Pool side of a swap, shared by every instruction that trades against
+/// the pool
+#[derive(Accounts)]
+pub struct SwapPool<'info> {
+    #[account(
+        mut,
+        constraint = !swap.paused @ ErrorCode::PoolPaused,
+        constraint = !swap.batch_auctions @ ErrorCode::BatchAuctionsOnly
+    )]
+    pub swap: Account<'info, SwapInfo>,
+    #[account(
+        mut,
+        constraint = token_in_account.key() == swap.token_a_account
+            || token_in_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount
+    )]
+    pub token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_out_account.key() == swap.token_a_account
+            || token_out_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_out_account.key() != token_in_account.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub token_out_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = token_in_account.mint @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = token_out_account.mint @ ErrorCode::InvalidMint)]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", swap.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: PDA that owns the pool token accounts
+    #[account(seeds = [swap.key().as_ref()], bump = swap.bump)]
+    pub pool_authority: AccountInfo<'info>,
+    /// Token program of `mint_in`, SPL Token or Token-2022
+    pub token_in_program: Interface<'info, TokenInterface>,
+    /// Token program of `mint_out`, SPL Token or Token-2022
+    pub token_out_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
 pub struct Swap<'info> {
+    pub pool: SwapPool<'info>,
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub user_token_out_account: InterfaceAccount<'info, TokenAccount>,
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SwapNative<'info> {
+    pub pool: SwapPool<'info>,
+    /// User's account for the non-SOL side of the swap
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// Temporary wSOL account, closed again before the instruction returns
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump,
+        token::mint = native_mint,
+        token::authority = user,
+        token::token_program = token_program
+    )]
+    pub wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        address = native_mint::ID,
+        constraint = pool.mint_in.key() == native_mint::ID
+            || pool.mint_out.key() == native_mint::ID @ ErrorCode::InvalidMint
+    )]
+    pub native_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// Token program of wSOL
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(hash: [u8; 32])]
+pub struct CommitSwap<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = 8 + SwapCommitment::LEN,
+        seeds = [b"commitment", user.key().as_ref(), hash.as_ref()],
+        bump
+    )]
+    pub commitment: Account<'info, SwapCommitment>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealSwap<'info> {
+    #[account(mut, close = user, constraint = commitment.owner == user.key() @ ErrorCode::Unauthorized)]
+    pub commitment: Account<'info, SwapCommitment>,
+    pub pool: SwapPool<'info>,
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub user_token_out_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseCommitment<'info> {
+    #[account(mut, close = user, constraint = commitment.owner == user.key() @ ErrorCode::Unauthorized)]
+    pub commitment: Account<'info, SwapCommitment>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    /// Source of the first hop's input
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSwap<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub swap: Account<'info, SwapInfo>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        constraint = swap.pending_authority == new_authority.key() @ ErrorCode::Unauthorized
+    )]
     pub swap: Account<'info, SwapInfo>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetGuardrail<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub swap: Account<'info, SwapInfo>,
+    /// Pool whose TWAP is the reference, possibly `swap` itself
+    pub reference_swap: Account<'info, SwapInfo>,
+    #[account(seeds = [b"oracle", reference_swap.key().as_ref()], bump)]
+    pub reference_oracle: Box<Account<'info, PriceOracle>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeOracle<'info> {
+    pub swap: Account<'info, SwapInfo>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PriceOracle::LEN,
+        seeds = [b"oracle", swap.key().as_ref()],
+        bump
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
     #[account(mut)]
-    pub token_in_account: Account<'info, TokenAccount>,
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Observe<'info> {
+    pub swap: Account<'info, SwapInfo>,
+    #[account(seeds = [b"oracle", swap.key().as_ref()], bump)]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+    #[account(address = swap.token_a_account)]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = swap.token_b_account)]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct PlaceLimitOrder<'info> {
+    pub swap: Account<'info, SwapInfo>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + LimitOrder::LEN,
+        seeds = [b"limit_order", owner.key().as_ref(), order_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order: Account<'info, LimitOrder>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"limit_escrow", order.key().as_ref()],
+        bump,
+        token::mint = mint_in,
+        token::authority = order,
+        token::token_program = token_in_program
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut)]
-    pub token_out_account: Account<'info, TokenAccount>,
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = mint_in.key() == swap.token_a_mint
+            || mint_in.key() == swap.token_b_mint @ ErrorCode::InvalidMint
+    )]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        constraint = mint_out.key() == swap.token_a_mint
+            || mint_out.key() == swap.token_b_mint @ ErrorCode::InvalidMint,
+        constraint = mint_out.key() != mint_in.key() @ ErrorCode::InvalidMint
+    )]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
-    pub user_token_in_account: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FillLimitOrder<'info> {
+    #[account(mut, has_one = swap, has_one = escrow)]
+    pub order: Account<'info, LimitOrder>,
+    #[account(
+        mut,
+        constraint = !swap.paused @ ErrorCode::PoolPaused,
+        constraint = !swap.batch_auctions @ ErrorCode::BatchAuctionsOnly
+    )]
+    pub swap: Account<'info, SwapInfo>,
+    #[account(mut)]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = token_in_account.key() == swap.token_a_account
+            || token_in_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_in_account.mint == order.mint_in @ ErrorCode::InvalidMint
+    )]
+    pub token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_out_account.key() == swap.token_a_account
+            || token_out_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_out_account.mint == order.mint_out @ ErrorCode::InvalidMint
+    )]
+    pub token_out_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = owner_token_out_account.owner == order.owner @ ErrorCode::InvalidTokenAccount,
+        constraint = owner_token_out_account.mint == order.mint_out @ ErrorCode::InvalidMint
+    )]
+    pub owner_token_out_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = order.mint_in @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = order.mint_out @ ErrorCode::InvalidMint)]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", swap.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: PDA that owns the pool token accounts
+    #[account(seeds = [swap.key().as_ref()], bump = swap.bump)]
+    pub pool_authority: AccountInfo<'info>,
+    pub keeper: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+    pub token_out_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLimitOrder<'info> {
+    #[account(mut, close = owner, has_one = owner @ ErrorCode::Unauthorized, has_one = escrow)]
+    pub order: Account<'info, LimitOrder>,
+    #[account(mut)]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the refund
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    /// Writable to take the escrow's withheld transfer fees
+    #[account(mut, address = order.mint_in @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
-    pub user_token_out_account: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeBatch<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub swap: Account<'info, SwapInfo>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + SwapBatch::LEN,
+        seeds = [b"batch", swap.key().as_ref()],
+        bump
+    )]
+    pub batch: Box<Account<'info, SwapBatch>>,
+    #[account(address = swap.token_a_mint @ ErrorCode::InvalidMint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = swap.token_b_mint @ ErrorCode::InvalidMint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Holds token A proceeds awaiting `claim_batch_payout`
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"batch_escrow", swap.key().as_ref(), token_a_mint.key().as_ref()],
+        bump,
+        token::mint = token_a_mint,
+        token::authority = pool_authority,
+        token::token_program = token_a_program
+    )]
+    pub escrow_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Holds token B proceeds awaiting `claim_batch_payout`
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"batch_escrow", swap.key().as_ref(), token_b_mint.key().as_ref()],
+        bump,
+        token::mint = token_b_mint,
+        token::authority = pool_authority,
+        token::token_program = token_b_program
+    )]
+    pub escrow_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA that owns the pool token accounts
+    #[account(seeds = [swap.key().as_ref()], bump = swap.bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetBatchAuctions<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub swap: Account<'info, SwapInfo>,
+    #[account(seeds = [b"batch", swap.key().as_ref()], bump)]
+    pub batch: Box<Account<'info, SwapBatch>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenBatchClaim<'info> {
+    #[account(constraint = swap.batch_auctions @ ErrorCode::BatchAuctionsOnly)]
+    pub swap: Account<'info, SwapInfo>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + BatchClaim::LEN,
+        seeds = [b"batch_claim", swap.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub claim: Account<'info, BatchClaim>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct QueueBatchSwap<'info> {
+    #[account(
+        constraint = !swap.paused @ ErrorCode::PoolPaused,
+        constraint = swap.batch_auctions @ ErrorCode::BatchAuctionsOnly
+    )]
+    pub swap: Account<'info, SwapInfo>,
+    #[account(mut, seeds = [b"batch", swap.key().as_ref()], bump)]
+    pub batch: Box<Account<'info, SwapBatch>>,
+    /// Where the proceeds wait if `user_token_out_account` cannot take them
+    #[account(seeds = [b"batch_claim", swap.key().as_ref(), user.key().as_ref()], bump)]
+    pub claim: Account<'info, BatchClaim>,
+    #[account(
+        mut,
+        constraint = token_in_account.key() == swap.token_a_account
+            || token_in_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount
+    )]
+    pub token_in_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub user_token_in_account: InterfaceAccount<'info, TokenAccount>,
+    /// Where the output will be paid
+    pub user_token_out_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = token_in_account.mint @ ErrorCode::InvalidMint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
     pub user: Signer<'info>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ClearBatch<'info> {
+    #[account(mut, constraint = !swap.paused @ ErrorCode::PoolPaused)]
+    pub swap: Box<Account<'info, SwapInfo>>,
+    #[account(mut, seeds = [b"batch", swap.key().as_ref()], bump)]
+    pub batch: Box<Account<'info, SwapBatch>>,
+    #[account(mut, address = swap.token_a_account @ ErrorCode::InvalidTokenAccount)]
+    pub token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = swap.token_b_account @ ErrorCode::InvalidTokenAccount)]
+    pub token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives token A proceeds that cannot be paid out
+    #[account(mut, seeds = [b"batch_escrow", swap.key().as_ref(), swap.token_a_mint.as_ref()], bump)]
+    pub escrow_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives token B proceeds that cannot be paid out
+    #[account(mut, seeds = [b"batch_escrow", swap.key().as_ref(), swap.token_b_mint.as_ref()], bump)]
+    pub escrow_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = swap.token_a_mint @ ErrorCode::InvalidMint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = swap.token_b_mint @ ErrorCode::InvalidMint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Observation ring buffer, written when supplied
+    #[account(mut, seeds = [b"oracle", swap.key().as_ref()], bump)]
+    pub oracle: Option<Account<'info, PriceOracle>>,
+    /// CHECK: PDA that owns the pool token accounts
+    #[account(seeds = [swap.key().as_ref()], bump = swap.bump)]
     pub pool_authority: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimBatchPayout<'info> {
+    pub swap: Box<Account<'info, SwapInfo>>,
+    #[account(mut, seeds = [b"batch_claim", swap.key().as_ref(), owner.key().as_ref()], bump)]
+    pub claim: Account<'info, BatchClaim>,
+    #[account(mut, seeds = [b"batch_escrow", swap.key().as_ref(), swap.token_a_mint.as_ref()], bump)]
+    pub escrow_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, seeds = [b"batch_escrow", swap.key().as_ref(), swap.token_b_mint.as_ref()], bump)]
+    pub escrow_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = owner_token_a_account.mint == swap.token_a_mint @ ErrorCode::InvalidMint)]
+    pub owner_token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = owner_token_b_account.mint == swap.token_b_mint @ ErrorCode::InvalidMint)]
+    pub owner_token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = swap.token_a_mint @ ErrorCode::InvalidMint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = swap.token_b_mint @ ErrorCode::InvalidMint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA that owns the pool token accounts
+    #[account(seeds = [swap.key().as_ref()], bump = swap.bump)]
+    pub pool_authority: AccountInfo<'info>,
+    pub owner: Signer<'info>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
 }
@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
 
+use crate::oracle::Observation;
+
 #[account]
 #[derive(Default)]
 pub struct SwapInfo {
@@ -19,6 +21,130 @@ pub struct SwapInfo {
     pub authority: Pubkey,
     /// Bump seed for authority PDA
     pub bump: u8,
+    /// Price accumulators as of the last swap
+    pub last_observation: Observation,
+    /// Proposed authority awaiting `accept_authority`
+    pub pending_authority: Pubkey,
+    /// Swaps are rejected while set
+    pub paused: bool,
+    /// Swaps only clear through the pool's batch auction while set
+    pub batch_auctions: bool,
+    /// Bound on how far a swap may move the price from a TWAP reference
+    pub guardrail: Option<PriceGuardrail>,
+}
+
+impl SwapInfo {
+    pub const LEN: usize = 32 + // token_a_mint
+                           32 + // token_b_mint
+                           32 + // token_a_account
+                           32 + // token_b_account
+                           8 +  // fee_numerator
+                           8 +  // fee_denominator
+                           32 + // authority
+                           1 +  // bump
+                           Observation::LEN + // last_observation
+                           32 + // pending_authority
+                           1 +  // paused
+                           1 +  // batch_auctions
+                           1 + PriceGuardrail::LEN; // guardrail
+}
+
+/// Swaps leaving the pool's spot price more than `max_deviation_bps` from
+/// the reference TWAP are rejected, so a single trade cannot push the price
+/// integrators read from the pool far off the market.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PriceGuardrail {
+    /// Oracle supplying the reference TWAP: this pool's own, or that of
+    /// another pool trading the same pair, whose swap account and vaults
+    /// then travel with it so the TWAP can run up to now
+    pub oracle: Pubkey,
+    /// Set when the reference pool lists the pair the other way round
+    pub inverted: bool,
+    /// Length of the reference TWAP, in seconds
+    pub twap_window: u32,
+    /// Furthest the post-trade price may sit from the reference, in basis points
+    pub max_deviation_bps: u16,
+}
+
+impl PriceGuardrail {
+    pub const LEN: usize = 32 + 1 + 4 + 2;
+}
+
+/// Input tokens escrowed until the pool pays at least the limit price.
+/// Keepers fill it in parts with `fill_limit_order`; the owner cancels it
+/// for a refund of whatever is left.
+#[account]
+#[derive(Default)]
+pub struct LimitOrder {
+    /// Owner of the order, paid its output and refunds
+    pub owner: Pubkey,
+    /// Pool the order fills against
+    pub swap: Pubkey,
+    /// Mint of the escrowed input
+    pub mint_in: Pubkey,
+    /// Mint the owner receives
+    pub mint_out: Pubkey,
+    /// Token account holding the escrowed input
+    pub escrow: Pubkey,
+    /// Input the limit price is quoted against
+    pub amount_in: u64,
+    /// Least output for `amount_in`; partial fills pay pro rata
+    pub min_amount_out: u64,
+    /// Input still in escrow
+    pub remaining_in: u64,
+    /// Unix timestamp after which the order no longer fills
+    pub expires_at: i64,
+    /// Caller-chosen id, part of the order's address
+    pub order_id: u64,
+    /// Bump seed for the order PDA
+    pub bump: u8,
+}
+
+impl LimitOrder {
+    pub const LEN: usize = 32 + // owner
+                           32 + // swap
+                           32 + // mint_in
+                           32 + // mint_out
+                           32 + // escrow
+                           8 +  // amount_in
+                           8 +  // min_amount_out
+                           8 +  // remaining_in
+                           8 +  // expires_at
+                           8 +  // order_id
+                           1;   // bump
+
+    /// Least output a fill of `amount` input must pay, rounded up
+    pub fn min_fill_out(&self, amount: u64) -> Option<u64> {
+        let numerator = (amount as u128).checked_mul(self.min_amount_out as u128)?;
+        let amount_in = self.amount_in as u128;
+        u64::try_from(numerator.checked_add(amount_in.checked_sub(1)?)? / amount_in).ok()
+    }
+}
+
+/// Slots a commitment must age before it can be revealed, so the reveal
+/// cannot land in the same block as a front-runner's view of the commit
+pub const MIN_REVEAL_DELAY_SLOTS: u64 = 2;
+
+/// Slots after which an unrevealed commitment can no longer be used
+pub const REVEAL_WINDOW_SLOTS: u64 = 150;
+
+/// Hash of a swap's parameters, published ahead of the swap so its size,
+/// direction and limit stay hidden until `reveal_swap` executes it.
+#[account]
+#[derive(Default)]
+pub struct SwapCommitment {
+    /// User who committed, and who must reveal
+    pub owner: Pubkey,
+    /// `commitment_hash` of the swap parameters and salt
+    pub hash: [u8; 32],
+    /// Slot the commitment was made in
+    pub slot: u64,
+}
+
+impl SwapCommitment {
+    pub const LEN: usize = 32 + // owner
+                           32 + // hash
+                           8;   // slot
 }
 
 #[account]
@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Performance fee exceeds the maximum")]
+    InvalidPerformanceFee,
+    
+    #[msg("Slippage allowance must be below 10000 basis points and the TWAP window non-zero and within what the oracle keeps")]
+    InvalidSlippage,
+    
+    #[msg("Amount must be non-zero")]
+    InvalidAmount,
+    
+    #[msg("Deposit would mint fewer shares than the minimum")]
+    SlippageExceeded,
+    
+    #[msg("Pool oracle has no TWAP over the vault's window")]
+    TwapUnavailable,
+
+    
+    #[msg("Invalid liquidity-pool pool")]
+    InvalidPool,
+    
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
+    
+    #[msg("Invalid mint")]
+    InvalidMint,
+    
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Math overflow")]
+    MathOverflow,
+    
+    #[msg("First deposit must mint more than the locked minimum shares")]
+    InitialDepositTooLow,
+}
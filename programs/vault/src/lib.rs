@@ -0,0 +1,763 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Burn, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked};
+use farm::program::Farm as FarmProgram;
+use farm::{Farm, UserStake};
+use liquidity_pool::program::LiquidityPool as LiquidityPoolProgram;
+use liquidity_pool::LiquidityPool;
+use swap::program::Swap;
+use swap::{min_amount_out, PriceOracle, SwapInfo, MAX_TWAP_WINDOW};
+use transfer_fee::amount_after_fee;
+
+mod error;
+mod state;
+
+use error::ErrorCode;
+use state::*;
+
+declare_id!("8yWqbCChaptrvD7MqBGVUfEGoThCxfAaFa6AeN7Bkk2L");
+
+#[program]
+pub mod vault {
+    use super::*;
+
+    /// Creates a vault compounding into `pool`, staking in `farm`. Reward
+    /// swaps must pay at least the swap pool's TWAP over `twap_window`
+    /// seconds, less `max_slippage_bps`, and compounds must mint at least
+    /// the LP the pool's own TWAP implies, less the same. Fees go to the
+    /// authority until `set_vault_config` says otherwise.
+    pub fn initialize_vault(
+        ctx: Context<InitializeVault>,
+        performance_fee_bps: u16,
+        twap_window: u32,
+        max_slippage_bps: u16,
+    ) -> Result<()> {
+        require!(
+            performance_fee_bps <= MAX_PERFORMANCE_FEE_BPS,
+            ErrorCode::InvalidPerformanceFee
+        );
+        require!(
+            twap_window > 0
+                && twap_window <= MAX_TWAP_WINDOW
+                && (max_slippage_bps as u64) < BPS_DENOMINATOR,
+            ErrorCode::InvalidSlippage
+        );
+
+        let vault_key = ctx.accounts.vault.key();
+        let bump = *ctx.bumps.get("vault_authority").unwrap();
+        let vault_authority_seeds = &[vault_key.as_ref(), &[bump]];
+        farm::cpi::create_stake(CpiContext::new_with_signer(
+            ctx.accounts.farm_program.to_account_info(),
+            farm::cpi::accounts::CreateStake {
+                farm: ctx.accounts.farm.to_account_info(),
+                stake: ctx.accounts.stake.to_account_info(),
+                owner: ctx.accounts.vault_authority.to_account_info(),
+                payer: ctx.accounts.authority.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            &[vault_authority_seeds],
+        ))?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.authority = ctx.accounts.authority.key();
+        vault.pool = ctx.accounts.pool.key();
+        vault.farm = ctx.accounts.farm.key();
+        vault.stake = ctx.accounts.stake.key();
+        vault.lp_mint = ctx.accounts.lp_mint.key();
+        vault.share_mint = ctx.accounts.share_mint.key();
+        vault.lp_account = ctx.accounts.lp_account.key();
+        vault.token_a_account = ctx.accounts.token_a_account.key();
+        vault.token_b_account = ctx.accounts.token_b_account.key();
+        vault.fee_recipient = ctx.accounts.authority.key();
+        vault.performance_fee_bps = performance_fee_bps;
+        vault.twap_window = twap_window;
+        vault.max_slippage_bps = max_slippage_bps;
+        vault.bump = bump;
+
+        Ok(())
+    }
+
+    /// Stakes `amount` LP tokens through the vault, minting shares at the
+    /// current LP per share. Shares are priced on the LP the vault has
+    /// staked less the compounded profit still unlocking, so neither LP
+    /// sent to the vault's accounts directly nor a compound cranked right
+    /// after the deposit moves the price. The first deposit locks
+    /// `MINIMUM_SHARES` of its shares.
+    pub fn deposit(ctx: Context<Deposit>, amount: u64, minimum_shares_out: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let balance_before = ctx.accounts.lp_account.amount;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.lp_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_lp_account.to_account_info(),
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.lp_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.lp_mint.decimals,
+        )?;
+        ctx.accounts.lp_account.reload()?;
+        let received = ctx.accounts.lp_account.amount - balance_before;
+
+        let now = Clock::get()?.unix_timestamp;
+        let total_lp = ctx.accounts.vault.unlocked_lp(ctx.accounts.stake.amount, now);
+        let total_shares = ctx.accounts.share_mint.supply;
+        let shares = shares_for_deposit(received, total_lp, total_shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let locked_shares = if total_shares == 0 { MINIMUM_SHARES } else { 0 };
+        let user_shares = shares
+            .checked_sub(locked_shares)
+            .filter(|shares| *shares > 0)
+            .ok_or(ErrorCode::InitialDepositTooLow)?;
+        require!(user_shares >= minimum_shares_out, ErrorCode::SlippageExceeded);
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault_authority_seeds = &[vault_key.as_ref(), &[ctx.accounts.vault.bump]];
+        farm::cpi::stake(
+            CpiContext::new_with_signer(
+                ctx.accounts.farm_program.to_account_info(),
+                farm::cpi::accounts::Stake {
+                    farm: ctx.accounts.farm.to_account_info(),
+                    stake: ctx.accounts.stake.to_account_info(),
+                    lp_mint: ctx.accounts.lp_mint.to_account_info(),
+                    lp_vault: ctx.accounts.farm_lp_vault.to_account_info(),
+                    owner_lp_account: ctx.accounts.lp_account.to_account_info(),
+                    owner: ctx.accounts.vault_authority.to_account_info(),
+                    lp_token_program: ctx.accounts.lp_token_program.to_account_info(),
+                },
+                &[vault_authority_seeds],
+            ),
+            received,
+        )?;
+
+        for (to, amount) in [
+            (ctx.accounts.user_share_account.to_account_info(), user_shares),
+            (ctx.accounts.locked_share_account.to_account_info(), locked_shares),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            token_interface::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.lp_token_program.to_account_info(),
+                    MintTo {
+                        mint: ctx.accounts.share_mint.to_account_info(),
+                        to,
+                        authority: ctx.accounts.vault_authority.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                amount,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Burns `shares` and unstakes the LP tokens they redeem for straight
+    /// to the user, leaving out the compounded profit still unlocking.
+    pub fn withdraw(ctx: Context<Withdraw>, shares: u64) -> Result<()> {
+        require!(shares > 0, ErrorCode::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let total_lp = ctx.accounts.vault.unlocked_lp(ctx.accounts.stake.amount, now);
+        let amount = lp_for_shares(shares, total_lp, ctx.accounts.share_mint.supply)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        token_interface::burn(
+            CpiContext::new(
+                ctx.accounts.lp_token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.user_share_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault_authority_seeds = &[vault_key.as_ref(), &[ctx.accounts.vault.bump]];
+        farm::cpi::unstake(
+            CpiContext::new_with_signer(
+                ctx.accounts.farm_program.to_account_info(),
+                farm::cpi::accounts::Unstake {
+                    farm: ctx.accounts.farm.to_account_info(),
+                    farm_authority: ctx.accounts.farm_authority.to_account_info(),
+                    stake: ctx.accounts.stake.to_account_info(),
+                    lp_mint: ctx.accounts.lp_mint.to_account_info(),
+                    lp_vault: ctx.accounts.farm_lp_vault.to_account_info(),
+                    owner_lp_account: ctx.accounts.user_lp_account.to_account_info(),
+                    owner: ctx.accounts.vault_authority.to_account_info(),
+                    lp_token_program: ctx.accounts.lp_token_program.to_account_info(),
+                },
+                &[vault_authority_seeds],
+            ),
+            amount,
+        )?;
+
+        Ok(())
+    }
+
+    /// Claims the vault's farm reward `index` into `reward_account` and
+    /// pays the performance fee out of it. Permissionless.
+    pub fn harvest(ctx: Context<Harvest>, index: u8) -> Result<()> {
+        let balance_before = ctx.accounts.reward_account.amount;
+        let vault_key = ctx.accounts.vault.key();
+        let vault_authority_seeds = &[vault_key.as_ref(), &[ctx.accounts.vault.bump]];
+        farm::cpi::claim_reward(
+            CpiContext::new_with_signer(
+                ctx.accounts.farm_program.to_account_info(),
+                farm::cpi::accounts::ClaimReward {
+                    farm: ctx.accounts.farm.to_account_info(),
+                    farm_authority: ctx.accounts.farm_authority.to_account_info(),
+                    stake: ctx.accounts.stake.to_account_info(),
+                    reward_mint: ctx.accounts.reward_mint.to_account_info(),
+                    reward_vault: ctx.accounts.farm_reward_vault.to_account_info(),
+                    owner_reward_account: ctx.accounts.reward_account.to_account_info(),
+                    owner: ctx.accounts.vault_authority.to_account_info(),
+                    reward_token_program: ctx.accounts.reward_token_program.to_account_info(),
+                },
+                &[vault_authority_seeds],
+            ),
+            index,
+        )?;
+        ctx.accounts.reward_account.reload()?;
+
+        // Fee on what actually arrived, net of any transfer fee
+        let harvested = ctx.accounts.reward_account.amount - balance_before;
+        let fee = ctx.accounts.vault.performance_fee(harvested);
+        if fee > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.reward_token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.reward_account.to_account_info(),
+                        mint: ctx.accounts.reward_mint.to_account_info(),
+                        to: ctx.accounts.fee_account.to_account_info(),
+                        authority: ctx.accounts.vault_authority.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                fee,
+                ctx.accounts.reward_mint.decimals,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Swaps `amount` harvested reward tokens into one of the pool's tokens
+    /// through a swap pool. Permissionless; the TWAP floor keeps a cranker
+    /// from timing the swap into a manipulated price, and how the rewards
+    /// are split between the pool's tokens only decides how much `compound`
+    /// can add at once, since it carries the rest over. Remaining accounts
+    /// are passed on to the swap, for pools whose guardrail reads another
+    /// pool's oracle.
+    pub fn swap_rewards<'info>(ctx: Context<'_, '_, '_, 'info, SwapRewards<'info>>, amount: u64) -> Result<()> {
+        require!(
+            amount > 0 && amount <= ctx.accounts.reward_account.amount,
+            ErrorCode::InvalidAmount
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &ctx.accounts.vault;
+
+        // TWAP up to now, from the reserves the swap is about to trade against
+        let swap = &ctx.accounts.swap;
+        let (reserve_a, reserve_b) = if ctx.accounts.token_in_account.key() == swap.token_a_account {
+            (ctx.accounts.token_in_account.amount, ctx.accounts.token_out_account.amount)
+        } else {
+            (ctx.accounts.token_out_account.amount, ctx.accounts.token_in_account.amount)
+        };
+        let (price_a, price_b) = ctx
+            .accounts
+            .oracle
+            .ring
+            .twap_to_now(&swap.last_observation, reserve_a, reserve_b, now, vault.twap_window)
+            .ok_or(ErrorCode::TwapUnavailable)?;
+        let twap_price = if ctx.accounts.mint_in.key() == swap.token_a_mint { price_a } else { price_b };
+        let minimum_amount_out = min_amount_out(amount, twap_price, vault.max_slippage_bps)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let vault_key = vault.key();
+        let vault_authority_seeds = &[vault_key.as_ref(), &[vault.bump]];
+        swap::cpi::swap_tokens(
+            CpiContext::new_with_signer(
+                ctx.accounts.swap_program.to_account_info(),
+                swap::cpi::accounts::Swap {
+                    pool: swap::cpi::accounts::SwapPool {
+                        swap: ctx.accounts.swap.to_account_info(),
+                        token_in_account: ctx.accounts.token_in_account.to_account_info(),
+                        token_out_account: ctx.accounts.token_out_account.to_account_info(),
+                        mint_in: ctx.accounts.mint_in.to_account_info(),
+                        mint_out: ctx.accounts.mint_out.to_account_info(),
+                        oracle: Some(ctx.accounts.oracle.to_account_info()),
+                        pool_authority: ctx.accounts.pool_authority.to_account_info(),
+                        token_in_program: ctx.accounts.token_in_program.to_account_info(),
+                        token_out_program: ctx.accounts.token_out_program.to_account_info(),
+                    },
+                    user_token_in_account: ctx.accounts.reward_account.to_account_info(),
+                    user_token_out_account: ctx.accounts.vault_token_out_account.to_account_info(),
+                    user: ctx.accounts.vault_authority.to_account_info(),
+                },
+                &[vault_authority_seeds],
+            )
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec()),
+            amount,
+            minimum_amount_out,
+        )?;
+
+        Ok(())
+    }
+
+    /// Adds the vault's pool tokens to the pool as liquidity and stakes
+    /// the LP tokens it mints, raising every share's LP as they unlock over
+    /// `PROFIT_UNLOCK_PERIOD`. Whatever the pool ratio leaves of the larger
+    /// side waits for the next compound.
+    /// Permissionless; the pool must mint at least what its TWAP over the
+    /// vault's window implies, so a cranker cannot compound into a pool it
+    /// has just pushed off its price.
+    pub fn compound(ctx: Context<Compound>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let vault_key = vault.key();
+        let vault_authority_seeds = &[vault_key.as_ref(), &[vault.bump]];
+        let (amount_a, amount_b) = (ctx.accounts.token_a_account.amount, ctx.accounts.token_b_account.amount);
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut minted = 0;
+        if amount_a > 0 && amount_b > 0 {
+            // TWAP up to now, from the reserves the deposit is about to price against
+            let (reserve_a, reserve_b) = (
+                ctx.accounts.pool_token_a_account.amount,
+                ctx.accounts.pool_token_b_account.amount,
+            );
+            let (price_a, _) = ctx
+                .accounts
+                .pool_oracle
+                .ring
+                .twap_to_now(&ctx.accounts.pool.last_observation, reserve_a, reserve_b, now, vault.twap_window)
+                .ok_or(ErrorCode::TwapUnavailable)?;
+            let min_lp_tokens = vault
+                .min_compound_lp(
+                    amount_after_fee(&ctx.accounts.token_a_mint.to_account_info(), amount_a)?,
+                    amount_after_fee(&ctx.accounts.token_b_mint.to_account_info(), amount_b)?,
+                    reserve_a,
+                    reserve_b,
+                    ctx.accounts.lp_mint.supply,
+                    price_a,
+                )
+                .ok_or(ErrorCode::MathOverflow)?;
+
+            let balance_before = ctx.accounts.lp_account.amount;
+            liquidity_pool::cpi::add_liquidity(
+                CpiContext::new_with_signer(
+                    ctx.accounts.liquidity_pool_program.to_account_info(),
+                    liquidity_pool::cpi::accounts::AddLiquidity {
+                        pool: ctx.accounts.pool.to_account_info(),
+                        token_a_account: ctx.accounts.pool_token_a_account.to_account_info(),
+                        token_b_account: ctx.accounts.pool_token_b_account.to_account_info(),
+                        lp_mint: ctx.accounts.lp_mint.to_account_info(),
+                        user_token_a: ctx.accounts.token_a_account.to_account_info(),
+                        user_token_b: ctx.accounts.token_b_account.to_account_info(),
+                        user_lp_token: ctx.accounts.lp_account.to_account_info(),
+                        locked_lp_token: ctx.accounts.locked_lp_account.to_account_info(),
+                        oracle: Some(ctx.accounts.pool_oracle.to_account_info()),
+                        pool_authority: ctx.accounts.pool_authority.to_account_info(),
+                        user: ctx.accounts.vault_authority.to_account_info(),
+                        token_a_mint: ctx.accounts.token_a_mint.to_account_info(),
+                        token_b_mint: ctx.accounts.token_b_mint.to_account_info(),
+                        token_a_program: ctx.accounts.token_a_program.to_account_info(),
+                        token_b_program: ctx.accounts.token_b_program.to_account_info(),
+                        token_program: ctx.accounts.lp_token_program.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                amount_a,
+                amount_b,
+                min_lp_tokens,
+            )?;
+            ctx.accounts.lp_account.reload()?;
+            minted = ctx.accounts.lp_account.amount - balance_before;
+        }
+
+        if minted > 0 {
+            farm::cpi::stake(
+                CpiContext::new_with_signer(
+                    ctx.accounts.farm_program.to_account_info(),
+                    farm::cpi::accounts::Stake {
+                        farm: ctx.accounts.farm.to_account_info(),
+                        stake: ctx.accounts.stake.to_account_info(),
+                        lp_mint: ctx.accounts.lp_mint.to_account_info(),
+                        lp_vault: ctx.accounts.farm_lp_vault.to_account_info(),
+                        owner_lp_account: ctx.accounts.lp_account.to_account_info(),
+                        owner: ctx.accounts.vault_authority.to_account_info(),
+                        lp_token_program: ctx.accounts.lp_token_program.to_account_info(),
+                    },
+                    &[vault_authority_seeds],
+                ),
+                minted,
+            )?;
+
+            // Whatever is still unlocking from earlier compounds unlocks
+            // along with this one
+            let vault = &mut ctx.accounts.vault;
+            vault.locked_profit = vault
+                .locked_profit(now)
+                .checked_add(minted)
+                .ok_or(ErrorCode::MathOverflow)?;
+            vault.profit_locked_at = now;
+        }
+
+        Ok(())
+    }
+
+    /// Updates the performance fee, its recipient and the reward swap
+    /// price bounds.
+    pub fn set_vault_config(
+        ctx: Context<UpdateVault>,
+        performance_fee_bps: u16,
+        fee_recipient: Pubkey,
+        twap_window: u32,
+        max_slippage_bps: u16,
+    ) -> Result<()> {
+        require!(
+            performance_fee_bps <= MAX_PERFORMANCE_FEE_BPS,
+            ErrorCode::InvalidPerformanceFee
+        );
+        require!(
+            twap_window > 0
+                && twap_window <= MAX_TWAP_WINDOW
+                && (max_slippage_bps as u64) < BPS_DENOMINATOR,
+            ErrorCode::InvalidSlippage
+        );
+        let vault = &mut ctx.accounts.vault;
+        vault.performance_fee_bps = performance_fee_bps;
+        vault.fee_recipient = fee_recipient;
+        vault.twap_window = twap_window;
+        vault.max_slippage_bps = max_slippage_bps;
+        Ok(())
+    }
+
+    /// Hands the vault to `new_authority`.
+    pub fn set_vault_authority(ctx: Context<UpdateVault>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.vault.authority = new_authority;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeVault<'info> {
+    #[account(init, payer = authority, space = 8 + Vault::LEN)]
+    pub vault: Box<Account<'info, Vault>>,
+    /// CHECK: PDA that owns the vault's token accounts and farm stake
+    #[account(seeds = [vault.key().as_ref()], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        constraint = pool.lp_mint == lp_mint.key()
+            && pool.token_a_mint == token_a_mint.key()
+            && pool.token_b_mint == token_b_mint.key() @ ErrorCode::InvalidPool
+    )]
+    pub pool: Box<Account<'info, LiquidityPool>>,
+    #[account(constraint = farm.lp_mint == lp_mint.key() @ ErrorCode::InvalidMint)]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: The vault's stake account, created by the farm program
+    #[account(mut)]
+    pub stake: UncheckedAccount<'info>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"shares", vault.key().as_ref()],
+        bump,
+        mint::decimals = lp_mint.decimals,
+        mint::authority = vault_authority,
+        mint::token_program = lp_token_program
+    )]
+    pub share_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Holds the MINIMUM_SHARES locked on the first deposit
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"locked_shares", vault.key().as_ref()],
+        bump,
+        token::mint = share_mint,
+        token::authority = vault_authority,
+        token::token_program = lp_token_program
+    )]
+    pub locked_share_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault_lp", vault.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = vault_authority,
+        token::token_program = lp_token_program
+    )]
+    pub lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault_token", vault.key().as_ref(), token_a_mint.key().as_ref()],
+        bump,
+        token::mint = token_a_mint,
+        token::authority = vault_authority,
+        token::token_program = token_a_program
+    )]
+    pub token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault_token", vault.key().as_ref(), token_b_mint.key().as_ref()],
+        bump,
+        token::mint = token_b_mint,
+        token::authority = vault_authority,
+        token::token_program = token_b_program
+    )]
+    pub token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub farm_program: Program<'info, FarmProgram>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(
+        has_one = farm,
+        has_one = stake,
+        has_one = lp_mint @ ErrorCode::InvalidMint,
+        has_one = share_mint @ ErrorCode::InvalidMint,
+        has_one = lp_account @ ErrorCode::InvalidTokenAccount
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+    /// CHECK: PDA that owns the vault's token accounts and farm stake
+    #[account(seeds = [vault.key().as_ref()], bump = vault.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(mut)]
+    pub stake: Box<Account<'info, UserStake>>,
+    /// CHECK: Farm's LP vault, checked by the farm program
+    #[account(mut)]
+    pub farm_lp_vault: UncheckedAccount<'info>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub share_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the shares
+    #[account(mut)]
+    pub user_share_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Holds the MINIMUM_SHARES locked on the first deposit. Nothing ever
+    /// transfers out of it.
+    #[account(mut, seeds = [b"locked_shares", vault.key().as_ref()], bump)]
+    pub locked_share_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub user: Signer<'info>,
+    pub farm_program: Program<'info, FarmProgram>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        has_one = farm,
+        has_one = stake,
+        has_one = lp_mint @ ErrorCode::InvalidMint,
+        has_one = share_mint @ ErrorCode::InvalidMint
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+    /// CHECK: PDA that owns the vault's token accounts and farm stake
+    #[account(seeds = [vault.key().as_ref()], bump = vault.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: PDA that owns the farm's vaults, checked by the farm program
+    pub farm_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub stake: Box<Account<'info, UserStake>>,
+    /// CHECK: Farm's LP vault, checked by the farm program
+    #[account(mut)]
+    pub farm_lp_vault: UncheckedAccount<'info>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub share_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Receives the LP tokens
+    #[account(mut)]
+    pub user_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user_share_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub user: Signer<'info>,
+    pub farm_program: Program<'info, FarmProgram>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Harvest<'info> {
+    #[account(has_one = farm, has_one = stake)]
+    pub vault: Box<Account<'info, Vault>>,
+    /// CHECK: PDA that owns the vault's token accounts and farm stake
+    #[account(seeds = [vault.key().as_ref()], bump = vault.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: PDA that owns the farm's vaults, checked by the farm program
+    pub farm_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub stake: Box<Account<'info, UserStake>>,
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: Farm's reward vault, checked by the farm program
+    #[account(mut)]
+    pub farm_reward_vault: UncheckedAccount<'info>,
+    /// Vault account the rewards are claimed into
+    #[account(
+        mut,
+        constraint = reward_account.owner == vault_authority.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = reward_account.mint == reward_mint.key() @ ErrorCode::InvalidMint
+    )]
+    pub reward_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the performance fee
+    #[account(
+        mut,
+        constraint = fee_account.owner == vault.fee_recipient @ ErrorCode::InvalidTokenAccount,
+        constraint = fee_account.mint == reward_mint.key() @ ErrorCode::InvalidMint
+    )]
+    pub fee_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub farm_program: Program<'info, FarmProgram>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SwapRewards<'info> {
+    pub vault: Box<Account<'info, Vault>>,
+    /// CHECK: PDA that owns the vault's token accounts and farm stake
+    #[account(seeds = [vault.key().as_ref()], bump = vault.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub swap: Box<Account<'info, SwapInfo>>,
+    /// Swap pool's observation ring buffer; the swap writes to it too
+    #[account(
+        mut,
+        seeds = [b"oracle", swap.key().as_ref()],
+        bump,
+        seeds::program = swap_program.key()
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+    #[account(
+        mut,
+        constraint = token_in_account.key() == swap.token_a_account
+            || token_in_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_in_account.mint == mint_in.key() @ ErrorCode::InvalidMint
+    )]
+    pub token_in_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = token_out_account.key() == swap.token_a_account
+            || token_out_account.key() == swap.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = token_out_account.mint == mint_out.key() @ ErrorCode::InvalidMint
+    )]
+    pub token_out_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Harvested rewards; the vault's pool token and LP accounts are not
+    /// rewards and may not be swapped away
+    #[account(
+        mut,
+        constraint = reward_account.owner == vault_authority.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = reward_account.key() != vault.token_a_account
+            && reward_account.key() != vault.token_b_account
+            && reward_account.key() != vault.lp_account @ ErrorCode::InvalidTokenAccount,
+        constraint = reward_account.mint == mint_in.key() @ ErrorCode::InvalidMint
+    )]
+    pub reward_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = vault_token_out_account.key() == vault.token_a_account
+            || vault_token_out_account.key() == vault.token_b_account @ ErrorCode::InvalidTokenAccount,
+        constraint = vault_token_out_account.mint == mint_out.key() @ ErrorCode::InvalidMint
+    )]
+    pub vault_token_out_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA that owns the swap pool token accounts, checked by the swap program
+    pub pool_authority: UncheckedAccount<'info>,
+    pub swap_program: Program<'info, Swap>,
+    pub token_in_program: Interface<'info, TokenInterface>,
+    pub token_out_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Compound<'info> {
+    #[account(
+        mut,
+        has_one = pool @ ErrorCode::InvalidPool,
+        has_one = farm,
+        has_one = stake,
+        has_one = lp_mint @ ErrorCode::InvalidMint,
+        has_one = lp_account @ ErrorCode::InvalidTokenAccount,
+        has_one = token_a_account @ ErrorCode::InvalidTokenAccount,
+        has_one = token_b_account @ ErrorCode::InvalidTokenAccount
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+    /// CHECK: PDA that owns the vault's token accounts and farm stake; the
+    /// depositing user of `add_liquidity`
+    #[account(mut, seeds = [vault.key().as_ref()], bump = vault.bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, LiquidityPool>>,
+    #[account(mut, address = pool.token_a_account @ ErrorCode::InvalidTokenAccount)]
+    pub pool_token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool.token_b_account @ ErrorCode::InvalidTokenAccount)]
+    pub pool_token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Pool's locked LP account, checked by the liquidity-pool program
+    #[account(mut)]
+    pub locked_lp_account: UncheckedAccount<'info>,
+    /// Pool's observation ring buffer; `add_liquidity` writes to it too
+    #[account(
+        mut,
+        seeds = [b"oracle", pool.key().as_ref()],
+        bump,
+        seeds::program = liquidity_pool_program.key()
+    )]
+    pub pool_oracle: Box<Account<'info, liquidity_pool::PriceOracle>>,
+    /// CHECK: PDA that owns the pool's vaults, checked by the liquidity-pool program
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = token_a_account.mint @ ErrorCode::InvalidMint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = token_b_account.mint @ ErrorCode::InvalidMint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(mut)]
+    pub stake: Box<Account<'info, UserStake>>,
+    /// CHECK: Farm's LP vault, checked by the farm program
+    #[account(mut)]
+    pub farm_lp_vault: UncheckedAccount<'info>,
+    pub liquidity_pool_program: Program<'info, LiquidityPoolProgram>,
+    pub farm_program: Program<'info, FarmProgram>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVault<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub vault: Box<Account<'info, Vault>>,
+    pub authority: Signer<'info>,
+}
@@ -0,0 +1,213 @@
+use anchor_lang::prelude::*;
+use liquidity_pool::integer_sqrt;
+
+/// Basis points in one whole
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Largest performance fee the authority may set, in basis points
+pub const MAX_PERFORMANCE_FEE_BPS: u16 = 2_000;
+
+/// Shares locked away on a vault's first deposit, as the pool does with
+/// its own LP. Without them the first depositor could hold a single share
+/// and donate LP to price every later deposit down to zero shares.
+pub const MINIMUM_SHARES: u64 = 1_000;
+
+/// Seconds over which each compound's LP is released into the share
+/// price. Harvesting and compounding are permissionless, so LP counted the
+/// moment it is staked could be captured by depositing just before a
+/// compound and withdrawing just after it.
+pub const PROFIT_UNLOCK_PERIOD: i64 = 6 * 60 * 60;
+
+/// Auto-compounding vault over one liquidity-pool pool. Deposited LP tokens
+/// are staked in the pool's farm; harvested rewards are swapped into the
+/// pool's tokens and added back as liquidity, so each share is redeemable
+/// for a growing amount of LP.
+#[account]
+#[derive(Default)]
+pub struct Vault {
+    /// Authority that sets the fee and slippage parameters
+    pub authority: Pubkey,
+    /// liquidity-pool pool the vault provides liquidity to
+    pub pool: Pubkey,
+    /// Farm the vault's LP tokens are staked in
+    pub farm: Pubkey,
+    /// The vault's stake account in the farm
+    pub stake: Pubkey,
+    /// LP mint of the pool
+    pub lp_mint: Pubkey,
+    /// Mint of the vault's shares
+    pub share_mint: Pubkey,
+    /// LP tokens between being minted and staked
+    pub lp_account: Pubkey,
+    /// Pool tokens waiting to be added as liquidity
+    pub token_a_account: Pubkey,
+    pub token_b_account: Pubkey,
+    /// Owner of the token accounts performance fees are paid to
+    pub fee_recipient: Pubkey,
+    /// Share of harvested rewards taken as a fee, in basis points
+    pub performance_fee_bps: u16,
+    /// Seconds of TWAP reward swaps and compounds are priced against
+    pub twap_window: u32,
+    /// Most a reward swap or compound may lose to the TWAP, in basis points
+    pub max_slippage_bps: u16,
+    /// Bump seed for the vault authority PDA
+    pub bump: u8,
+    /// Compounded LP not yet counted in the share price as of
+    /// `profit_locked_at`
+    pub locked_profit: u64,
+    /// When `locked_profit` was last topped up
+    pub profit_locked_at: i64,
+}
+
+impl Vault {
+    pub const LEN: usize = 32 + // authority
+                           32 + // pool
+                           32 + // farm
+                           32 + // stake
+                           32 + // lp_mint
+                           32 + // share_mint
+                           32 + // lp_account
+                           32 + // token_a_account
+                           32 + // token_b_account
+                           32 + // fee_recipient
+                           2 +  // performance_fee_bps
+                           4 +  // twap_window
+                           2 +  // max_slippage_bps
+                           1 +  // bump
+                           8 +  // locked_profit
+                           8;   // profit_locked_at
+
+    /// Performance fee due on `harvested` reward tokens
+    pub fn performance_fee(&self, harvested: u64) -> u64 {
+        (harvested as u128 * self.performance_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+
+    /// Compounded LP still locked at `now`, released linearly over
+    /// `PROFIT_UNLOCK_PERIOD` from the last compound.
+    pub fn locked_profit(&self, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.profit_locked_at);
+        if elapsed >= PROFIT_UNLOCK_PERIOD {
+            return 0;
+        }
+        let remaining = (PROFIT_UNLOCK_PERIOD - elapsed.max(0)) as u128;
+        (self.locked_profit as u128 * remaining / PROFIT_UNLOCK_PERIOD as u128) as u64
+    }
+
+    /// LP that shares are priced on at `now`: the vault's stake less the
+    /// profit still unlocking.
+    pub fn unlocked_lp(&self, staked: u64, now: i64) -> u64 {
+        staked.saturating_sub(self.locked_profit(now))
+    }
+
+    /// Least LP a compound of `amount_a` and `amount_b` must mint, given
+    /// the pool's reserves and LP supply and the TWAP of token A in token B
+    /// as Q64.64. The deposit is priced against reserves with the live
+    /// product but the TWAP ratio, so a pool pushed off its price just
+    /// before the compound fails it rather than minting the vault less.
+    pub fn min_compound_lp(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        lp_supply: u64,
+        twap_price: u128,
+    ) -> Option<u64> {
+        // Reserves with product k at the TWAP price are sqrt(k / price) and
+        // sqrt(k * price); the square root of a Q64.64 price is Q32.32
+        let root_k = integer_sqrt(reserve_a as u128 * reserve_b as u128);
+        let root_price = integer_sqrt(twap_price);
+        if root_price == 0 {
+            return None;
+        }
+        let fair_a = (root_k << 32) / root_price;
+        let fair_b = root_k.checked_mul(root_price)? >> 32;
+        if fair_a == 0 || fair_b == 0 {
+            return None;
+        }
+        let lp = (amount_a as u128 * lp_supply as u128 / fair_a)
+            .min(amount_b as u128 * lp_supply as u128 / fair_b);
+        let min = lp.checked_mul((BPS_DENOMINATOR - self.max_slippage_bps as u64) as u128)?
+            / BPS_DENOMINATOR as u128;
+        u64::try_from(min).ok()
+    }
+}
+
+/// Shares minted for depositing `amount` LP into a vault holding
+/// `total_lp` against `total_shares`. The first deposit mints one share
+/// per LP token. Rounds down, in the vault's favour.
+pub fn shares_for_deposit(amount: u64, total_lp: u64, total_shares: u64) -> Option<u64> {
+    if total_shares == 0 || total_lp == 0 {
+        return Some(amount);
+    }
+    u64::try_from((amount as u128).checked_mul(total_shares as u128)? / total_lp as u128).ok()
+}
+
+/// LP tokens `shares` redeem for. Rounds down, in the vault's favour.
+pub fn lp_for_shares(shares: u64, total_lp: u64, total_shares: u64) -> Option<u64> {
+    if total_shares == 0 {
+        return None;
+    }
+    u64::try_from((shares as u128).checked_mul(total_lp as u128)? / total_shares as u128).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compounding_raises_the_share_price() {
+        assert_eq!(shares_for_deposit(1_000, 0, 0), Some(1_000));
+        // After compounding 1_000 LP into 1_500, later deposits get fewer shares
+        assert_eq!(shares_for_deposit(300, 1_500, 1_000), Some(200));
+        assert_eq!(lp_for_shares(200, 1_800, 1_200), Some(300));
+        // Dust rounds against the depositor and the redeemer
+        assert_eq!(shares_for_deposit(1, 1_500, 1_000), Some(0));
+        assert_eq!(lp_for_shares(1, 1_500, 1_000), Some(1));
+        assert_eq!(lp_for_shares(1, 1_000, 0), None);
+    }
+
+    #[test]
+    fn fee_uses_basis_points() {
+        let vault = Vault { performance_fee_bps: 450, ..Default::default() };
+        assert_eq!(vault.performance_fee(10_000), 450);
+        assert_eq!(vault.performance_fee(10), 0);
+    }
+
+    #[test]
+    fn compounded_lp_unlocks_over_the_period() {
+        let vault = Vault { locked_profit: 600, profit_locked_at: 1_000, ..Default::default() };
+        // Nothing of a fresh compound counts towards the share price
+        assert_eq!(vault.unlocked_lp(10_600, 1_000), 10_000);
+        assert_eq!(vault.unlocked_lp(10_600, 1_000 + PROFIT_UNLOCK_PERIOD / 3), 10_200);
+        assert_eq!(vault.unlocked_lp(10_600, 1_000 + PROFIT_UNLOCK_PERIOD), 10_600);
+        assert_eq!(vault.locked_profit(1_000 + 2 * PROFIT_UNLOCK_PERIOD), 0);
+
+        // A deposit just before that compound and a withdrawal just after it
+        // gets back only what it put in
+        let shares = shares_for_deposit(10_000, Vault::default().unlocked_lp(10_000, 1_000), 10_000).unwrap();
+        assert_eq!(lp_for_shares(shares, vault.unlocked_lp(20_600, 1_000), 20_000), Some(10_000));
+    }
+
+    #[test]
+    fn compound_floor_follows_the_twap_not_the_spot_price() {
+        let vault = Vault { max_slippage_bps: 100, ..Default::default() };
+        // 1% of a 1:1 pool mints 1% of the LP supply
+        assert_eq!(
+            vault.min_compound_lp(10_000, 10_000, 1_000_000, 1_000_000, 1_000_000, 1 << 64),
+            Some(9_900)
+        );
+        // Pushed to 4:1 with the same product, the pool would only mint
+        // 5_000 for the same deposit, so the floor stops the compound
+        assert_eq!(
+            vault.min_compound_lp(10_000, 10_000, 500_000, 2_000_000, 1_000_000, 1 << 64),
+            Some(9_900)
+        );
+        // At a TWAP of 4 the same reserves are fair
+        assert_eq!(
+            vault.min_compound_lp(10_000, 40_000, 500_000, 2_000_000, 1_000_000, 4 << 64),
+            Some(19_800)
+        );
+        assert_eq!(vault.min_compound_lp(10_000, 10_000, 1_000_000, 1_000_000, 1_000_000, 0), None);
+    }
+}
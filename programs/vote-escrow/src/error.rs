@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be non-zero")]
+    InvalidAmount,
+    
+    #[msg("Lock must end after the current epoch and within four years")]
+    InvalidLockEnd,
+    
+    #[msg("Lock has expired")]
+    LockExpired,
+    
+    #[msg("Lock has not expired yet")]
+    LockNotExpired,
+    
+    #[msg("Lock still has gauge votes")]
+    LockHasVotes,
+    
+    #[msg("Votes would exceed the lock's power")]
+    VoteOverallocated,
+    
+    #[msg("Gauge emissions were already distributed this epoch")]
+    AlreadyDistributed,
+    
+    #[msg("Farm must be run by the locker authority and pay the emission mint")]
+    InvalidGaugeFarm,
+    
+    #[msg("Invalid mint")]
+    InvalidMint,
+    
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Math overflow")]
+    MathOverflow,
+}
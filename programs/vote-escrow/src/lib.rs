@@ -0,0 +1,523 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use farm::program::Farm as FarmProgram;
+use farm::{Farm, UserStake};
+
+mod error;
+mod state;
+
+use error::ErrorCode;
+use state::*;
+
+declare_id!("AYAQk76BtUq7aDhqdPtAjuzXUXxkKHd6kN7ijdhi39iY");
+
+#[program]
+pub mod vote_escrow {
+    use super::*;
+
+    /// Creates a locker for `token_mint` that emits `emissions_per_epoch`
+    /// of `emission_mint` through its gauges. Emissions are funded by
+    /// transferring into the emission vault.
+    pub fn initialize_locker(ctx: Context<InitializeLocker>, emissions_per_epoch: u64) -> Result<()> {
+        let locker = &mut ctx.accounts.locker;
+        locker.authority = ctx.accounts.authority.key();
+        locker.token_mint = ctx.accounts.token_mint.key();
+        locker.token_vault = ctx.accounts.token_vault.key();
+        locker.emission_mint = ctx.accounts.emission_mint.key();
+        locker.emission_vault = ctx.accounts.emission_vault.key();
+        locker.emissions_per_epoch = emissions_per_epoch;
+        locker.bump = *ctx.bumps.get("locker_authority").unwrap();
+        Ok(())
+    }
+
+    /// Locks `amount` until `end_time`, rounded down to an epoch boundary
+    /// and at most four years out.
+    pub fn create_lock(ctx: Context<CreateLock>, amount: u64, end_time: i64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let end_time = epoch_start(end_time);
+        require!(
+            end_time > now && end_time <= now + MAX_LOCK_SECONDS,
+            ErrorCode::InvalidLockEnd
+        );
+        let received = deposit(&mut ctx.accounts.deposit, amount)?;
+
+        ctx.accounts
+            .deposit
+            .locker
+            .add_lock(received, end_time)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let lock = &mut ctx.accounts.lock;
+        lock.locker = ctx.accounts.deposit.locker.key();
+        lock.owner = ctx.accounts.deposit.owner.key();
+        lock.amount = received;
+        lock.end_time = end_time;
+        lock.vote_bps = 0;
+
+        Ok(())
+    }
+
+    /// Adds `amount` to a live lock. Its gauge votes and farm boosts pick
+    /// up the extra power when next poked.
+    pub fn increase_lock(ctx: Context<IncreaseLock>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let end_time = ctx.accounts.lock.end_time;
+        require!(end_time > now, ErrorCode::LockExpired);
+        let received = deposit(&mut ctx.accounts.deposit, amount)?;
+
+        ctx.accounts
+            .deposit
+            .locker
+            .add_lock(received, end_time)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let lock = &mut ctx.accounts.lock;
+        lock.amount = lock.amount.checked_add(received).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Pushes a live lock's end out to `end_time`, rounded down to an
+    /// epoch boundary and at most four years out.
+    pub fn extend_lock(ctx: Context<ExtendLock>, end_time: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock = &mut ctx.accounts.lock;
+        require!(lock.end_time > now, ErrorCode::LockExpired);
+        let end_time = epoch_start(end_time);
+        require!(
+            end_time > lock.end_time && end_time <= now + MAX_LOCK_SECONDS,
+            ErrorCode::InvalidLockEnd
+        );
+
+        let locker = &mut ctx.accounts.locker;
+        locker.remove_lock(lock.amount, lock.end_time).ok_or(ErrorCode::MathOverflow)?;
+        locker.add_lock(lock.amount, end_time).ok_or(ErrorCode::MathOverflow)?;
+        lock.end_time = end_time;
+
+        Ok(())
+    }
+
+    /// Returns an expired lock's tokens and rent to its owner. Anyone may
+    /// call it, which keeps expired locks out of the total voting power;
+    /// the lock's votes must be poked away first.
+    pub fn withdraw_lock(ctx: Context<WithdrawLock>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock = &ctx.accounts.lock;
+        require!(lock.end_time <= now, ErrorCode::LockNotExpired);
+        require!(lock.vote_bps == 0, ErrorCode::LockHasVotes);
+
+        let locker = &mut ctx.accounts.locker;
+        locker.remove_lock(lock.amount, lock.end_time).ok_or(ErrorCode::MathOverflow)?;
+
+        let locker_key = locker.key();
+        let locker_authority_seeds = &[locker_key.as_ref(), &[locker.bump]];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.token_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.owner_token_account.to_account_info(),
+                    authority: ctx.accounts.locker_authority.to_account_info(),
+                },
+                &[locker_authority_seeds],
+            ),
+            lock.amount,
+            ctx.accounts.token_mint.decimals,
+        )?;
+
+        Ok(())
+    }
+
+    /// Adds a gauge for `farm`, emitting into its reward slot
+    /// `reward_index`.
+    pub fn create_gauge(ctx: Context<CreateGauge>, reward_index: u8) -> Result<()> {
+        let gauge = &mut ctx.accounts.gauge;
+        gauge.locker = ctx.accounts.locker.key();
+        gauge.farm = ctx.accounts.farm.key();
+        gauge.reward_index = reward_index;
+        gauge.weight = 0;
+        gauge.epoch_weight = 0;
+        gauge.weight_epoch = 0;
+        gauge.last_epoch = 0;
+        Ok(())
+    }
+
+    /// Opens `owner`'s vote account for a gauge.
+    pub fn create_gauge_vote(ctx: Context<CreateGaugeVote>) -> Result<()> {
+        let vote = &mut ctx.accounts.gauge_vote;
+        vote.gauge = ctx.accounts.gauge.key();
+        vote.owner = ctx.accounts.owner.key();
+        Ok(())
+    }
+
+    /// Allots `weight_bps` of the lock's current power to the gauge,
+    /// replacing the previous vote from the next epoch on. Votes across
+    /// gauges may not exceed the whole power; zero withdraws the vote.
+    pub fn vote(ctx: Context<Vote>, weight_bps: u16) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock = &mut ctx.accounts.lock;
+        require!(weight_bps == 0 || lock.end_time > now, ErrorCode::LockExpired);
+        let vote = &mut ctx.accounts.gauge_vote;
+        let vote_bps = (lock.vote_bps - vote.weight_bps) as u64 + weight_bps as u64;
+        require!(vote_bps <= BPS_DENOMINATOR, ErrorCode::VoteOverallocated);
+
+        vote.set_power(
+            GaugeVote::power_at(lock, weight_bps, now),
+            &mut ctx.accounts.gauge,
+            &mut ctx.accounts.locker,
+            epoch_of(now),
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        vote.weight_bps = weight_bps;
+        lock.vote_bps = vote_bps as u16;
+
+        Ok(())
+    }
+
+    /// Brings a vote's power down to what its lock has decayed to, or
+    /// clears it once the lock has expired. Permissionless.
+    pub fn poke_vote(ctx: Context<PokeVote>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock = &mut ctx.accounts.lock;
+        let vote = &mut ctx.accounts.gauge_vote;
+        if lock.end_time <= now {
+            lock.vote_bps -= vote.weight_bps;
+            vote.weight_bps = 0;
+        }
+
+        let power = GaugeVote::power_at(lock, vote.weight_bps, now);
+        vote.set_power(power, &mut ctx.accounts.gauge, &mut ctx.accounts.locker, epoch_of(now))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Funds the gauge's farm with its share of this epoch's emissions,
+    /// streamed over at least the next epoch. Permissionless, once per
+    /// gauge per epoch. Shares are taken at the weights frozen at the start
+    /// of the epoch, so votes moved during it cannot be paid twice, and are
+    /// capped by what the emission vault holds.
+    pub fn distribute_emissions(ctx: Context<DistributeEmissions>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let epoch = epoch_of(now);
+        let gauge = &mut ctx.accounts.gauge;
+        require!(gauge.last_epoch < epoch, ErrorCode::AlreadyDistributed);
+        gauge.last_epoch = epoch;
+        gauge.checkpoint(epoch);
+        ctx.accounts.locker.checkpoint(epoch);
+
+        let amount = ctx
+            .accounts
+            .locker
+            .gauge_emissions(gauge.epoch_weight)
+            .min(ctx.accounts.emission_vault.amount);
+        if amount == 0 {
+            return Ok(());
+        }
+        let reward_index = gauge.reward_index;
+        let end_time = (now + EPOCH_SECONDS).max(ctx.accounts.farm.rewards[reward_index as usize].end_time);
+
+        let locker_key = ctx.accounts.locker.key();
+        let locker_authority_seeds = &[locker_key.as_ref(), &[ctx.accounts.locker.bump]];
+        farm::cpi::fund_reward(
+            CpiContext::new_with_signer(
+                ctx.accounts.farm_program.to_account_info(),
+                farm::cpi::accounts::FundReward {
+                    farm: ctx.accounts.farm.to_account_info(),
+                    reward_mint: ctx.accounts.emission_mint.to_account_info(),
+                    reward_vault: ctx.accounts.farm_reward_vault.to_account_info(),
+                    funder_reward_account: ctx.accounts.emission_vault.to_account_info(),
+                    authority: ctx.accounts.locker_authority.to_account_info(),
+                    reward_token_program: ctx.accounts.emission_token_program.to_account_info(),
+                },
+                &[locker_authority_seeds],
+            ),
+            reward_index,
+            amount,
+            end_time,
+        )?;
+
+        Ok(())
+    }
+
+    /// Reports the stake owner's current voting power to a farm that takes
+    /// its boosts from this locker. Permissionless, so decayed boosts can
+    /// be brought down by anyone.
+    pub fn update_boost(ctx: Context<UpdateBoost>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let power = if ctx.accounts.lock.data_is_empty() {
+            0
+        } else {
+            Account::<Lock>::try_from(&ctx.accounts.lock)?.voting_power(now)
+        };
+        // Expired locks not yet withdrawn can leave the total short
+        let total_power = ctx.accounts.locker.total_power(now).max(power);
+
+        let locker_key = ctx.accounts.locker.key();
+        let locker_authority_seeds = &[locker_key.as_ref(), &[ctx.accounts.locker.bump]];
+        farm::cpi::update_boost(
+            CpiContext::new_with_signer(
+                ctx.accounts.farm_program.to_account_info(),
+                farm::cpi::accounts::UpdateBoost {
+                    farm: ctx.accounts.farm.to_account_info(),
+                    stake: ctx.accounts.stake.to_account_info(),
+                    boost_authority: ctx.accounts.locker_authority.to_account_info(),
+                },
+                &[locker_authority_seeds],
+            ),
+            power,
+            total_power,
+        )?;
+
+        Ok(())
+    }
+
+    /// Sets the emissions shared between gauges each epoch.
+    pub fn set_emissions(ctx: Context<UpdateLocker>, emissions_per_epoch: u64) -> Result<()> {
+        ctx.accounts.locker.emissions_per_epoch = emissions_per_epoch;
+        Ok(())
+    }
+}
+
+/// Moves `amount` of the owner's tokens into the locker, returning what
+/// arrived net of any transfer fee.
+fn deposit(accounts: &mut LockDeposit, amount: u64) -> Result<u64> {
+    let balance_before = accounts.token_vault.amount;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: accounts.owner_token_account.to_account_info(),
+                mint: accounts.token_mint.to_account_info(),
+                to: accounts.token_vault.to_account_info(),
+                authority: accounts.owner.to_account_info(),
+            },
+        ),
+        amount,
+        accounts.token_mint.decimals,
+    )?;
+    accounts.token_vault.reload()?;
+    Ok(accounts.token_vault.amount - balance_before)
+}
+
+#[derive(Accounts)]
+pub struct InitializeLocker<'info> {
+    #[account(init, payer = authority, space = 8 + Locker::LEN)]
+    pub locker: Box<Account<'info, Locker>>,
+    /// CHECK: PDA that owns the locker's vaults and runs its gauged farms
+    #[account(seeds = [locker.key().as_ref()], bump)]
+    pub locker_authority: UncheckedAccount<'info>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"ve_vault", locker.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = locker_authority,
+        token::token_program = token_program
+    )]
+    pub token_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub emission_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"emission_vault", locker.key().as_ref()],
+        bump,
+        token::mint = emission_mint,
+        token::authority = locker_authority,
+        token::token_program = emission_token_program
+    )]
+    pub emission_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub emission_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts shared by instructions that lock more tokens
+#[derive(Accounts)]
+pub struct LockDeposit<'info> {
+    #[account(mut, has_one = token_mint @ ErrorCode::InvalidMint, has_one = token_vault)]
+    pub locker: Box<Account<'info, Locker>>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub token_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub owner_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateLock<'info> {
+    pub deposit: LockDeposit<'info>,
+    #[account(
+        init,
+        payer = deposit.owner,
+        space = 8 + Lock::LEN,
+        seeds = [b"lock", deposit.locker.key().as_ref(), deposit.owner.key().as_ref()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct IncreaseLock<'info> {
+    pub deposit: LockDeposit<'info>,
+    #[account(
+        mut,
+        constraint = lock.locker == deposit.locker.key(),
+        constraint = lock.owner == deposit.owner.key() @ ErrorCode::Unauthorized
+    )]
+    pub lock: Account<'info, Lock>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendLock<'info> {
+    #[account(mut)]
+    pub locker: Box<Account<'info, Locker>>,
+    #[account(mut, has_one = locker, has_one = owner @ ErrorCode::Unauthorized)]
+    pub lock: Account<'info, Lock>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawLock<'info> {
+    #[account(mut, has_one = token_mint @ ErrorCode::InvalidMint, has_one = token_vault)]
+    pub locker: Box<Account<'info, Locker>>,
+    /// CHECK: PDA that owns the locker's vaults and runs its gauged farms
+    #[account(seeds = [locker.key().as_ref()], bump = locker.bump)]
+    pub locker_authority: UncheckedAccount<'info>,
+    #[account(mut, close = owner, has_one = locker, has_one = owner)]
+    pub lock: Account<'info, Lock>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub token_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the unlocked tokens
+    #[account(mut, constraint = owner_token_account.owner == lock.owner @ ErrorCode::Unauthorized)]
+    pub owner_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Lock owner, receives the lock's rent
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(reward_index: u8)]
+pub struct CreateGauge<'info> {
+    #[account(has_one = authority @ ErrorCode::Unauthorized)]
+    pub locker: Box<Account<'info, Locker>>,
+    /// CHECK: PDA that owns the locker's vaults and runs its gauged farms
+    #[account(seeds = [locker.key().as_ref()], bump = locker.bump)]
+    pub locker_authority: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Gauge::LEN,
+        seeds = [b"gauge", locker.key().as_ref(), farm.key().as_ref()],
+        bump
+    )]
+    pub gauge: Account<'info, Gauge>,
+    #[account(
+        constraint = farm.authority == locker_authority.key() @ ErrorCode::InvalidGaugeFarm,
+        constraint = farm
+            .rewards
+            .get(reward_index as usize)
+            .is_some_and(|reward| reward.mint == locker.emission_mint) @ ErrorCode::InvalidGaugeFarm
+    )]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateGaugeVote<'info> {
+    pub gauge: Account<'info, Gauge>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GaugeVote::LEN,
+        seeds = [b"gauge_vote", gauge.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub gauge_vote: Account<'info, GaugeVote>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Vote<'info> {
+    #[account(mut)]
+    pub locker: Box<Account<'info, Locker>>,
+    #[account(mut, has_one = locker, has_one = owner @ ErrorCode::Unauthorized)]
+    pub lock: Account<'info, Lock>,
+    #[account(mut, has_one = locker)]
+    pub gauge: Account<'info, Gauge>,
+    #[account(mut, has_one = gauge, has_one = owner @ ErrorCode::Unauthorized)]
+    pub gauge_vote: Account<'info, GaugeVote>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PokeVote<'info> {
+    #[account(mut)]
+    pub locker: Box<Account<'info, Locker>>,
+    #[account(mut, has_one = locker, constraint = lock.owner == gauge_vote.owner)]
+    pub lock: Account<'info, Lock>,
+    #[account(mut, has_one = locker)]
+    pub gauge: Account<'info, Gauge>,
+    #[account(mut, has_one = gauge)]
+    pub gauge_vote: Account<'info, GaugeVote>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeEmissions<'info> {
+    #[account(mut, has_one = emission_mint @ ErrorCode::InvalidMint, has_one = emission_vault)]
+    pub locker: Box<Account<'info, Locker>>,
+    /// CHECK: PDA that owns the locker's vaults and runs its gauged farms
+    #[account(seeds = [locker.key().as_ref()], bump = locker.bump)]
+    pub locker_authority: UncheckedAccount<'info>,
+    #[account(mut, has_one = locker, has_one = farm)]
+    pub gauge: Account<'info, Gauge>,
+    #[account(mut)]
+    pub farm: Box<Account<'info, Farm>>,
+    /// CHECK: Farm's reward vault, checked by the farm program
+    #[account(mut)]
+    pub farm_reward_vault: UncheckedAccount<'info>,
+    pub emission_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub emission_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub farm_program: Program<'info, FarmProgram>,
+    pub emission_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBoost<'info> {
+    pub locker: Box<Account<'info, Locker>>,
+    /// CHECK: PDA that owns the locker's vaults and runs its gauged farms
+    #[account(seeds = [locker.key().as_ref()], bump = locker.bump)]
+    pub locker_authority: UncheckedAccount<'info>,
+    /// CHECK: The stake owner's lock address, which holds no lock if they
+    /// have none
+    #[account(seeds = [b"lock", locker.key().as_ref(), stake.owner.as_ref()], bump)]
+    pub lock: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub farm: Box<Account<'info, Farm>>,
+    #[account(mut)]
+    pub stake: Box<Account<'info, UserStake>>,
+    pub farm_program: Program<'info, FarmProgram>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLocker<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub locker: Box<Account<'info, Locker>>,
+    pub authority: Signer<'info>,
+}
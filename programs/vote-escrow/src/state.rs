@@ -0,0 +1,291 @@
+use anchor_lang::prelude::*;
+
+/// Basis points in one whole
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Length of an emission epoch; lock ends are rounded down to one
+pub const EPOCH_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+/// Longest lock, which starts with one unit of voting power per token
+pub const MAX_LOCK_SECONDS: i64 = 4 * 365 * 24 * 60 * 60;
+
+/// Vote-escrow over one governance token. Locked tokens give voting power
+/// that decays linearly to zero at the end of the lock; the power boosts
+/// farm rewards and weights the gauges that split `emissions_per_epoch`
+/// between farms.
+#[account]
+#[derive(Default)]
+pub struct Locker {
+    /// Authority that adds gauges and sets emissions
+    pub authority: Pubkey,
+    /// Governance token that is locked
+    pub token_mint: Pubkey,
+    /// Token account holding locked tokens
+    pub token_vault: Pubkey,
+    /// Reward token the gauges emit
+    pub emission_mint: Pubkey,
+    /// Token account emissions are paid from
+    pub emission_vault: Pubkey,
+    /// Emission tokens shared between gauges each epoch
+    pub emissions_per_epoch: u64,
+    /// Tokens in locks not yet withdrawn
+    pub locked_amount: u64,
+    /// Sum of amount times end time over those locks, from which the total
+    /// voting power follows without visiting every lock
+    pub locked_amount_end: u128,
+    /// Sum of all gauge weights
+    pub total_gauge_weight: u64,
+    /// Sum of gauge weights at the start of `weight_epoch`
+    pub epoch_gauge_weight: u64,
+    /// Epoch `epoch_gauge_weight` was frozen for
+    pub weight_epoch: i64,
+    /// Bump seed for the locker authority PDA
+    pub bump: u8,
+}
+
+impl Locker {
+    pub const LEN: usize = 32 + // authority
+                           32 + // token_mint
+                           32 + // token_vault
+                           32 + // emission_mint
+                           32 + // emission_vault
+                           8 +  // emissions_per_epoch
+                           8 +  // locked_amount
+                           16 + // locked_amount_end
+                           8 +  // total_gauge_weight
+                           8 +  // epoch_gauge_weight
+                           8 +  // weight_epoch
+                           1;   // bump
+
+    /// Counts `amount` locked until `end_time` towards the totals
+    pub fn add_lock(&mut self, amount: u64, end_time: i64) -> Option<()> {
+        self.locked_amount = self.locked_amount.checked_add(amount)?;
+        self.locked_amount_end = self
+            .locked_amount_end
+            .checked_add((amount as u128).checked_mul(end_time as u128)?)?;
+        Some(())
+    }
+
+    pub fn remove_lock(&mut self, amount: u64, end_time: i64) -> Option<()> {
+        self.locked_amount = self.locked_amount.checked_sub(amount)?;
+        self.locked_amount_end = self
+            .locked_amount_end
+            .checked_sub((amount as u128).checked_mul(end_time as u128)?)?;
+        Some(())
+    }
+
+    /// Voting power of all locks at `now`. Exact while every expired lock
+    /// has been withdrawn; until then the expired ones count against it.
+    pub fn total_power(&self, now: i64) -> u64 {
+        let decayed = self.locked_amount as u128 * now.max(0) as u128;
+        (self.locked_amount_end.saturating_sub(decayed) / MAX_LOCK_SECONDS as u128).min(u64::MAX as u128) as u64
+    }
+
+    /// Freezes the total gauge weight for `epoch` before the first change
+    /// to it in that epoch
+    pub fn checkpoint(&mut self, epoch: i64) {
+        if self.weight_epoch < epoch {
+            self.epoch_gauge_weight = self.total_gauge_weight;
+            self.weight_epoch = epoch;
+        }
+    }
+
+    /// Emissions due to a gauge of frozen weight `epoch_weight` for the
+    /// epoch the locker was last checkpointed at
+    pub fn gauge_emissions(&self, epoch_weight: u64) -> u64 {
+        if self.epoch_gauge_weight == 0 {
+            return 0;
+        }
+        (self.emissions_per_epoch as u128 * epoch_weight as u128 / self.epoch_gauge_weight as u128) as u64
+    }
+}
+
+/// Rounds a lock end down to the start of its epoch
+pub fn epoch_start(time: i64) -> i64 {
+    time.div_euclid(EPOCH_SECONDS) * EPOCH_SECONDS
+}
+
+/// Number of the epoch `time` falls in
+pub fn epoch_of(time: i64) -> i64 {
+    time.div_euclid(EPOCH_SECONDS)
+}
+
+/// A user's locked tokens.
+#[account]
+#[derive(Default)]
+pub struct Lock {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+    /// Tokens locked
+    pub amount: u64,
+    /// Unix timestamp the tokens unlock at
+    pub end_time: i64,
+    /// Share of the lock's power allotted to gauges, in basis points
+    pub vote_bps: u16,
+}
+
+impl Lock {
+    pub const LEN: usize = 32 + // locker
+                           32 + // owner
+                           8 +  // amount
+                           8 +  // end_time
+                           2;   // vote_bps
+
+    /// Voting power at `now`, falling linearly from `amount` for a four
+    /// year lock to zero at `end_time`
+    pub fn voting_power(&self, now: i64) -> u64 {
+        if self.end_time <= now {
+            return 0;
+        }
+        (self.amount as u128 * (self.end_time - now) as u128 / MAX_LOCK_SECONDS as u128) as u64
+    }
+}
+
+/// Emission weight of one farm, set by vote-escrow votes. Each epoch's
+/// emissions are shared by the weights frozen at its start, so votes count
+/// from the next epoch. The farm's authority must be the locker authority
+/// so the gauge can fund it.
+#[account]
+#[derive(Default)]
+pub struct Gauge {
+    pub locker: Pubkey,
+    pub farm: Pubkey,
+    /// Farm reward slot paying the locker's emission mint
+    pub reward_index: u8,
+    /// Voting power currently allotted to the gauge
+    pub weight: u64,
+    /// Weight at the start of `weight_epoch`
+    pub epoch_weight: u64,
+    /// Epoch `epoch_weight` was frozen for
+    pub weight_epoch: i64,
+    /// Epoch emissions were last distributed for
+    pub last_epoch: i64,
+}
+
+impl Gauge {
+    pub const LEN: usize = 32 + // locker
+                           32 + // farm
+                           1 +  // reward_index
+                           8 +  // weight
+                           8 +  // epoch_weight
+                           8 +  // weight_epoch
+                           8;   // last_epoch
+
+    /// Freezes the weight for `epoch` before the first change to it in
+    /// that epoch
+    pub fn checkpoint(&mut self, epoch: i64) {
+        if self.weight_epoch < epoch {
+            self.epoch_weight = self.weight;
+            self.weight_epoch = epoch;
+        }
+    }
+}
+
+/// One lock's vote for one gauge. The power is fixed when the vote is cast
+/// and refreshed by `poke_vote` as the lock decays.
+#[account]
+#[derive(Default)]
+pub struct GaugeVote {
+    pub gauge: Pubkey,
+    pub owner: Pubkey,
+    /// Share of the lock's power voted, in basis points
+    pub weight_bps: u16,
+    /// Power the vote adds to the gauge's weight
+    pub power: u64,
+}
+
+impl GaugeVote {
+    pub const LEN: usize = 32 + // gauge
+                           32 + // owner
+                           2 +  // weight_bps
+                           8;   // power
+
+    /// Power `lock` gives a vote of `weight_bps` at `now`
+    pub fn power_at(lock: &Lock, weight_bps: u16, now: i64) -> u64 {
+        (lock.voting_power(now) as u128 * weight_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+
+    /// Replaces the vote's power at `epoch`, moving the gauge and locker
+    /// weights with it from the next epoch
+    pub fn set_power(&mut self, power: u64, gauge: &mut Gauge, locker: &mut Locker, epoch: i64) -> Option<()> {
+        gauge.checkpoint(epoch);
+        locker.checkpoint(epoch);
+        gauge.weight = gauge.weight.checked_sub(self.power)?.checked_add(power)?;
+        locker.total_gauge_weight = locker
+            .total_gauge_weight
+            .checked_sub(self.power)?
+            .checked_add(power)?;
+        self.power = power;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_decays_to_zero_at_unlock() {
+        let lock = Lock { amount: 1_000, end_time: MAX_LOCK_SECONDS, ..Default::default() };
+        assert_eq!(lock.voting_power(0), 1_000);
+        assert_eq!(lock.voting_power(MAX_LOCK_SECONDS / 4), 750);
+        assert_eq!(lock.voting_power(MAX_LOCK_SECONDS), 0);
+        assert_eq!(lock.voting_power(MAX_LOCK_SECONDS + 1), 0);
+        assert_eq!(GaugeVote::power_at(&lock, 2_500, 0), 250);
+    }
+
+    #[test]
+    fn total_power_sums_live_locks() {
+        let mut locker = Locker::default();
+        let long = Lock { amount: 1_000, end_time: MAX_LOCK_SECONDS, ..Default::default() };
+        let short = Lock { amount: 400, end_time: MAX_LOCK_SECONDS / 2, ..Default::default() };
+        locker.add_lock(long.amount, long.end_time).unwrap();
+        locker.add_lock(short.amount, short.end_time).unwrap();
+        let now = MAX_LOCK_SECONDS / 4;
+        assert_eq!(locker.total_power(now), long.voting_power(now) + short.voting_power(now));
+
+        // Once the short lock is withdrawn only the long one counts
+        locker.remove_lock(short.amount, short.end_time).unwrap();
+        let now = MAX_LOCK_SECONDS * 3 / 4;
+        assert_eq!(locker.total_power(now), long.voting_power(now));
+    }
+
+    #[test]
+    fn emissions_follow_gauge_weight() {
+        let locker = Locker { emissions_per_epoch: 7_000, epoch_gauge_weight: 700, ..Default::default() };
+        assert_eq!(locker.gauge_emissions(100), 1_000);
+        assert_eq!(locker.gauge_emissions(0), 0);
+        assert_eq!(Locker::default().gauge_emissions(100), 0);
+        assert_eq!(epoch_start(EPOCH_SECONDS * 3 + 5), EPOCH_SECONDS * 3);
+    }
+
+    #[test]
+    fn re_vote_cannot_take_a_second_share() {
+        let mut locker = Locker { emissions_per_epoch: 1_000, ..Default::default() };
+        let (mut gauge_a, mut gauge_b) = (Gauge::default(), Gauge::default());
+        let mut vote_a = GaugeVote::default();
+        let mut other_vote_b = GaugeVote::default();
+        other_vote_b.set_power(100, &mut gauge_b, &mut locker, 0).unwrap();
+        vote_a.set_power(100, &mut gauge_a, &mut locker, 0).unwrap();
+
+        // Gauge A is paid its half of epoch 1, then its voter moves to B
+        let distribute = |gauge: &mut Gauge, locker: &mut Locker| {
+            gauge.checkpoint(1);
+            locker.checkpoint(1);
+            locker.gauge_emissions(gauge.epoch_weight)
+        };
+        let paid_a = distribute(&mut gauge_a, &mut locker);
+        let mut vote_b = GaugeVote::default();
+        vote_a.set_power(0, &mut gauge_a, &mut locker, 1).unwrap();
+        vote_b.set_power(100, &mut gauge_b, &mut locker, 1).unwrap();
+
+        // B is still paid on the weights frozen at the start of the epoch
+        let paid_b = distribute(&mut gauge_b, &mut locker);
+        assert_eq!((paid_a, paid_b), (500, 500));
+
+        // The move counts from the next epoch
+        gauge_b.checkpoint(2);
+        locker.checkpoint(2);
+        assert_eq!(locker.gauge_emissions(gauge_b.epoch_weight), 1_000);
+    }
+}